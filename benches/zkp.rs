@@ -0,0 +1,72 @@
+use chaum_pederson_rust::{params, ZKP};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+// Compares plain exponentiation with precomputed fixed-base tables for the
+// three operations that exponentiate alpha and beta.
+const PARAM_SETS: &[&str] = &[params::DEFAULT_PARAM_SET, "ffdhe2048", "ristretto255"];
+
+fn variants(name: &str) -> [(&'static str, ZKP); 2] {
+    let zkp = params::param_set(name).unwrap();
+    [
+        ("plain", zkp.clone()),
+        ("tables", zkp.with_fixed_base_tables()),
+    ]
+}
+
+fn bench_register(c: &mut Criterion) {
+    let mut group = c.benchmark_group("register");
+    for name in PARAM_SETS {
+        for (label, zkp) in variants(name) {
+            let x = ZKP::generate_random_below(&zkp.q);
+            group.bench_with_input(BenchmarkId::new(label, name), &x, |b, x| {
+                b.iter(|| {
+                    (
+                        zkp.exponentiate(&zkp.alpha, black_box(x)),
+                        zkp.exponentiate(&zkp.beta, black_box(x)),
+                    )
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_prove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prove");
+    for name in PARAM_SETS {
+        for (label, zkp) in variants(name) {
+            let x = ZKP::generate_random_below(&zkp.q);
+            let context = ZKP::fiat_shamir_context("bench", 0);
+            group.bench_function(BenchmarkId::new(label, name), |b| {
+                b.iter(|| {
+                    let k = ZKP::generate_random_below(&zkp.q);
+                    zkp.prove_non_interactive(black_box(&x), &k, &context)
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_verify(c: &mut Criterion) {
+    let mut group = c.benchmark_group("verify");
+    for name in PARAM_SETS {
+        for (label, zkp) in variants(name) {
+            let x = ZKP::generate_random_below(&zkp.q);
+            let k = ZKP::generate_random_below(&zkp.q);
+            let challenge = ZKP::generate_random_below(&zkp.q);
+            let y1 = zkp.exponentiate(&zkp.alpha, &x);
+            let y2 = zkp.exponentiate(&zkp.beta, &x);
+            let r1 = zkp.exponentiate(&zkp.alpha, &k);
+            let r2 = zkp.exponentiate(&zkp.beta, &k);
+            let s = zkp.solve(&k, &challenge, &x);
+            group.bench_function(BenchmarkId::new(label, name), |b| {
+                b.iter(|| zkp.verify(&r1, &r2, &y1, &y2, &challenge, black_box(&s)))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_register, bench_prove, bench_verify);
+criterion_main!(benches);
@@ -0,0 +1,2 @@
+-- Users registered before parameter sets existed used the legacy 1024-bit group
+ALTER TABLE users ADD COLUMN param_set TEXT NOT NULL DEFAULT 'legacy-1024';
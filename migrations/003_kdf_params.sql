@@ -0,0 +1,6 @@
+-- Salt and cost of the client-side password derivation; 'none' means the raw password
+ALTER TABLE users ADD COLUMN kdf_algorithm TEXT NOT NULL DEFAULT 'none';
+ALTER TABLE users ADD COLUMN kdf_salt BYTEA NOT NULL DEFAULT '';
+ALTER TABLE users ADD COLUMN kdf_memory_kib INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN kdf_iterations INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN kdf_parallelism INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- Sessions are looked up by the SHA-256 of their token; the token itself is never stored.
+-- Raw ids from before this change cannot be kept, so those sessions must log in again.
+DELETE FROM sessions;
+ALTER TABLE sessions RENAME COLUMN session_id TO token_hash;
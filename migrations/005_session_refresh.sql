@@ -0,0 +1,19 @@
+-- Sliding expiration: sessions track their last use for the idle timeout and
+-- belong to a refresh family that shares one absolute deadline
+ALTER TABLE sessions ADD COLUMN last_used_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE sessions ADD COLUMN family_id TEXT NOT NULL DEFAULT '';
+
+-- Refresh tokens are single use; a used token presented again revokes its family
+CREATE TABLE refresh_tokens (
+    token_hash TEXT PRIMARY KEY,
+    family_id TEXT NOT NULL,
+    user_name TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP,
+
+    FOREIGN KEY (user_name) REFERENCES users(user_name) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_sessions_family_id ON sessions(family_id);
+CREATE INDEX idx_refresh_tokens_family_id ON refresh_tokens(family_id);
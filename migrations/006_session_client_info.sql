@@ -0,0 +1,3 @@
+-- Where each session was created from, shown to the user by ListSessions
+ALTER TABLE sessions ADD COLUMN client_addr TEXT;
+ALTER TABLE sessions ADD COLUMN user_agent TEXT;
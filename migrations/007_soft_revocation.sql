@@ -0,0 +1,7 @@
+-- Logout and revocation keep the row for forensics; a retention job purges old ones
+UPDATE sessions SET is_active = TRUE WHERE is_active IS NULL;
+ALTER TABLE sessions ALTER COLUMN is_active SET NOT NULL;
+ALTER TABLE sessions ADD COLUMN revoked_at TIMESTAMP;
+ALTER TABLE sessions ADD COLUMN revoke_reason TEXT;
+
+ALTER TABLE refresh_tokens ADD COLUMN revoked_at TIMESTAMP;
@@ -0,0 +1,2 @@
+-- When the user last replaced their commitments with RotateCredential
+ALTER TABLE users ADD COLUMN credential_rotated_at TIMESTAMP;
@@ -0,0 +1,3 @@
+-- Auth logs may outlive a deleted account under an anonymous alias, so they no
+-- longer cascade from users; db::delete_user_by_username removes them itself
+ALTER TABLE auth_logs DROP CONSTRAINT auth_logs_user_name_fkey;
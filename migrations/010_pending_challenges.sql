@@ -0,0 +1,14 @@
+-- Challenges waiting for an answer, shared by every server instance
+CREATE TABLE pending_challenges (
+    auth_id TEXT PRIMARY KEY,
+    user_name TEXT NOT NULL,
+    r1 BYTEA NOT NULL,
+    r2 BYTEA NOT NULL,
+    c BYTEA NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+
+    FOREIGN KEY (user_name) REFERENCES users(user_name) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_pending_challenges_expires_at ON pending_challenges(expires_at);
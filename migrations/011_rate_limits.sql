@@ -0,0 +1,10 @@
+-- Failed attempts per rate-limit key, shared by every server instance
+CREATE TABLE rate_limits (
+    key TEXT PRIMARY KEY,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_attempt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    blocked_until TIMESTAMP,
+    blocks INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX idx_rate_limits_last_attempt ON rate_limits(last_attempt);
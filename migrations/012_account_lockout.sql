@@ -0,0 +1,19 @@
+-- A locked account cannot authenticate. A lock with a reason but no
+-- locked_until lasts until an admin unlocks it.
+ALTER TABLE users ADD COLUMN locked_until TIMESTAMP;
+ALTER TABLE users ADD COLUMN lock_reason TEXT;
+
+-- Audit trail of accounts being locked and unlocked
+CREATE TABLE lockout_events (
+    id SERIAL PRIMARY KEY,
+    user_name TEXT NOT NULL,
+    event TEXT NOT NULL,
+    reason TEXT,
+    actor TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    FOREIGN KEY (user_name) REFERENCES users(user_name) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_lockout_events_user_name ON lockout_events(user_name);
+CREATE INDEX idx_auth_logs_user_created ON auth_logs(user_name, created_at);
@@ -0,0 +1,8 @@
+-- Non-interactive proofs already accepted, kept until they can no longer pass
+-- the timestamp check so that no instance accepts them twice
+CREATE TABLE used_proofs (
+    proof_id TEXT PRIMARY KEY,
+    expires_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_used_proofs_expires_at ON used_proofs(expires_at);
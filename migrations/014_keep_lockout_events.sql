@@ -0,0 +1,3 @@
+-- The lockout audit trail outlives a deleted account under an anonymous
+-- alias, so it no longer cascades from users
+ALTER TABLE lockout_events DROP CONSTRAINT lockout_events_user_name_fkey;
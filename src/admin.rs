@@ -0,0 +1,560 @@
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use tracing::{info, instrument};
+
+use crate::db;
+use crate::lockout;
+use crate::server::{AuthError, AuthImpl};
+use crate::session::RevokeReason;
+use crate::zkp_auth::{self, admin_server::Admin};
+
+/// Default address of the admin service, kept apart from the public port
+pub const DEFAULT_ADMIN_ADDR: &str = "127.0.0.1:50052";
+/// Largest page `ListUsers` returns
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+/// The bearer token operators present; only its hash is kept
+#[derive(Clone)]
+pub struct AdminCredential {
+    token_hash: [u8; 32],
+}
+
+impl AdminCredential {
+    pub fn new(token: &str) -> Self {
+        AdminCredential {
+            token_hash: Sha256::digest(token.as_bytes()).into(),
+        }
+    }
+
+    /// Checks the `authorization: Bearer <token>` metadata of a request.
+    /// Hashes are compared, so timing reveals nothing about the token.
+    pub fn check<T>(&self, request: &Request<T>) -> Result<(), AuthError> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(AuthError::AdminUnauthorized)?;
+        if <[u8; 32]>::from(Sha256::digest(token.as_bytes())) != self.token_hash {
+            return Err(AuthError::AdminUnauthorized);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for AdminCredential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AdminCredential")
+    }
+}
+
+// Admin service; shares the database and rate limiting state of the public one.
+#[derive(Debug, Clone)]
+pub struct AdminImpl {
+    pub auth: Arc<AuthImpl>, // The public service whose users are managed.
+    pub credential: AdminCredential, // Token every admin call must present.
+}
+
+fn user_summary(user: db::User) -> zkp_auth::UserSummary {
+    zkp_auth::UserSummary {
+        user_name: user.user_name,
+        param_set: user.param_set,
+        kdf_algorithm: user.kdf.algorithm,
+        created_at: user.created_at.timestamp(),
+    }
+}
+
+#[tonic::async_trait]
+impl Admin for AdminImpl {
+    // Lists users by name, a page at a time.
+    #[instrument(skip(self, request))]
+    async fn list_users(
+        &self,
+        request: Request<zkp_auth::ListUsersRequest>,
+    ) -> Result<Response<zkp_auth::ListUsersResponse>, Status> {
+        self.credential.check(&request)?;
+        let request = request.into_inner();
+        info!(
+            page_size = request.page_size,
+            event = "admin_list_users",
+            "start"
+        );
+        let mut tx = self
+            .auth
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        // An unset page size gets the largest page rather than every user.
+        let page_size = match request.page_size {
+            0 => MAX_PAGE_SIZE,
+            page_size => page_size.min(MAX_PAGE_SIZE),
+        };
+        let users = db::get_users_page(&mut tx, &request.page_token, page_size as i64)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+
+        // A full page may be followed by more; the token is the last name seen.
+        let next_page_token = match users.last() {
+            Some(last) if users.len() == page_size as usize => last.user_name.clone(),
+            _ => String::new(),
+        };
+        info!(users = users.len(), event = "admin_list_users", "completed");
+        Ok(Response::new(zkp_auth::ListUsersResponse {
+            users: users.into_iter().map(user_summary).collect(),
+            next_page_token,
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn count_users(
+        &self,
+        request: Request<zkp_auth::CountUsersRequest>,
+    ) -> Result<Response<zkp_auth::CountUsersResponse>, Status> {
+        self.credential.check(&request)?;
+        let mut tx = self
+            .auth
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        let count = db::count_users(&mut tx)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        info!(count, event = "admin_count_users", "completed");
+        Ok(Response::new(zkp_auth::CountUsersResponse {
+            count: count as u64,
+        }))
+    }
+
+    // Returns a user's auth history, oldest first.
+    #[instrument(skip(self, request))]
+    async fn get_login_attempts(
+        &self,
+        request: Request<zkp_auth::GetLoginAttemptsRequest>,
+    ) -> Result<Response<zkp_auth::GetLoginAttemptsResponse>, Status> {
+        self.credential.check(&request)?;
+        let user_name = request.into_inner().user_name;
+        info!(user = %user_name, event = "admin_get_login_attempts", "start");
+        let mut tx = self
+            .auth
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        let logs = db::get_login_attempts_by_user(&mut tx, &user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        Ok(Response::new(zkp_auth::GetLoginAttemptsResponse {
+            attempts: logs
+                .into_iter()
+                .map(|log| zkp_auth::LoginAttempt {
+                    auth_id: log.auth_id,
+                    success: log.success,
+                    created_at: log.created_at.timestamp(),
+                    failure_reason: log.failure_reason.unwrap_or_default(),
+                })
+                .collect(),
+        }))
+    }
+
+    // Deletes a user with their sessions and auth logs.
+    #[instrument(skip(self, request))]
+    async fn delete_user(
+        &self,
+        request: Request<zkp_auth::DeleteUserRequest>,
+    ) -> Result<Response<zkp_auth::DeleteUserResponse>, Status> {
+        self.credential.check(&request)?;
+        let user_name = request.into_inner().user_name;
+        info!(user = %user_name, event = "admin_delete_user", "start");
+        let mut tx = self
+            .auth
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        db::get_user_by_username(&mut tx, &user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error while fetching user: {}", e)))?
+            .ok_or_else(|| AuthError::UserNotFound(user_name.clone()))?;
+        db::delete_user_by_username(&mut tx, &user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        self.auth
+            .rate_limiter
+            .reset_user(&user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("Rate limiter error: {}", e)))?;
+        info!(user = %user_name, event = "admin_delete_user", "completed");
+        Ok(Response::new(zkp_auth::DeleteUserResponse {}))
+    }
+
+    // Signs a user out everywhere and revokes their refresh tokens.
+    #[instrument(skip(self, request))]
+    async fn revoke_user_sessions(
+        &self,
+        request: Request<zkp_auth::RevokeUserSessionsRequest>,
+    ) -> Result<Response<zkp_auth::RevokeUserSessionsResponse>, Status> {
+        self.credential.check(&request)?;
+        let user_name = request.into_inner().user_name;
+        info!(user = %user_name, event = "admin_revoke_user_sessions", "start");
+        let mut tx = self
+            .auth
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        let revoked = db::revoke_sessions_by_user(
+            &mut tx,
+            &user_name,
+            None,
+            None,
+            RevokeReason::Admin.as_str(),
+        )
+        .await
+        .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        info!(user = %user_name, revoked, event = "admin_revoke_user_sessions", "completed");
+        Ok(Response::new(zkp_auth::RevokeUserSessionsResponse {
+            revoked: revoked as u32,
+        }))
+    }
+
+    // Clears a user's failed attempts, any block and any account lock.
+    #[instrument(skip(self, request))]
+    async fn unlock_user(
+        &self,
+        request: Request<zkp_auth::UnlockUserRequest>,
+    ) -> Result<Response<zkp_auth::UnlockUserResponse>, Status> {
+        self.credential.check(&request)?;
+        let user_name = request.into_inner().user_name;
+        let was_limited = self
+            .auth
+            .rate_limiter
+            .reset_user(&user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("Rate limiter error: {}", e)))?
+            > 0;
+        let mut tx = self
+            .auth
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        let was_locked = lockout::unlock(&mut tx, &user_name, "admin", Utc::now())
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        info!(user = %user_name, was_limited, was_locked, event = "admin_unlock_user", "completed");
+        Ok(Response::new(zkp_auth::UnlockUserResponse {
+            was_limited,
+            was_locked,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kdf::KdfParams;
+    use crate::lockout::LockoutPolicy;
+    use crate::server::ACCOUNT_LOCKED_METADATA;
+    use crate::test_utils::{
+        setup_zkp, spawn_test_admin_server, spawn_test_admin_server_with_lockout,
+    };
+    use crate::zkp_auth::{admin_client::AdminClient, auth_client::AuthClient};
+    use crate::ZKP;
+    use num_bigint::BigUint;
+    use tonic::transport::Channel;
+
+    const TOKEN: &str = "test-admin-token";
+
+    fn authorized<T>(message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", TOKEN).parse().unwrap(),
+        );
+        request
+    }
+
+    async fn login(
+        client: &mut AuthClient<Channel>,
+        zkp: &ZKP,
+        user_name: &str,
+        password: &BigUint,
+    ) -> Result<String, Status> {
+        let timestamp_ms = chrono::Utc::now().timestamp_millis() as u64;
+        let context = ZKP::fiat_shamir_context(user_name, timestamp_ms);
+        let k = ZKP::generate_random_below(&zkp.q);
+        let (r1, r2, s) = zkp.prove_non_interactive(password, &k, &context);
+        client
+            .authenticate_non_interactive(zkp_auth::NonInteractiveAuthenticationRequest {
+                name: user_name.into(),
+                r1: r1.to_bytes_be(),
+                r2: r2.to_bytes_be(),
+                s: s.to_bytes_be(),
+                timestamp_ms,
+                param_set: zkp.name().to_string(),
+                issue_token: false,
+            })
+            .await
+            .map(|res| res.into_inner().session_id)
+    }
+
+    #[tokio::test]
+    async fn test_admin_requires_credential() {
+        let (_, admin_endpoint) = spawn_test_admin_server(TOKEN).await;
+        let mut admin = AdminClient::connect(admin_endpoint).await.unwrap();
+
+        let err = admin
+            .count_users(zkp_auth::CountUsersRequest {})
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        let mut wrong = Request::new(zkp_auth::CountUsersRequest {});
+        wrong
+            .metadata_mut()
+            .insert("authorization", "Bearer nope".parse().unwrap());
+        let err = admin.count_users(wrong).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+        assert!(admin
+            .count_users(authorized(zkp_auth::CountUsersRequest {}))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_admin_manages_users() {
+        let (endpoint, admin_endpoint) = spawn_test_admin_server(TOKEN).await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+        let mut admin = AdminClient::connect(admin_endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let prefix = format!("admin_{}_", uuid::Uuid::new_v4());
+        let names: Vec<String> = ["a", "b", "c"]
+            .iter()
+            .map(|n| format!("{}{}", prefix, n))
+            .collect();
+        for name in &names {
+            client
+                .register(zkp_auth::RegisterRequest {
+                    name: name.clone(),
+                    y1: zkp.exponentiate(&zkp.alpha, &password).to_bytes_be(),
+                    y2: zkp.exponentiate(&zkp.beta, &password).to_bytes_be(),
+                    param_set: zkp.name().to_string(),
+                    kdf: Some(KdfParams::generate().into()),
+                })
+                .await
+                .unwrap();
+        }
+
+        let count = admin
+            .count_users(authorized(zkp_auth::CountUsersRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .count;
+        assert!(count >= 3);
+
+        // Pages continue after the last name of the previous one
+        let page = admin
+            .list_users(authorized(zkp_auth::ListUsersRequest {
+                page_size: 2,
+                page_token: prefix.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let listed: Vec<&str> = page.users.iter().map(|u| u.user_name.as_str()).collect();
+        assert_eq!(listed, [names[0].as_str(), names[1].as_str()]);
+        assert_eq!(page.next_page_token, names[1]);
+        let page = admin
+            .list_users(authorized(zkp_auth::ListUsersRequest {
+                page_size: 2,
+                page_token: page.next_page_token,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(page.users[0].user_name, names[2]);
+        assert_eq!(page.users[0].param_set, zkp.name());
+
+        // An unset page size is capped like any other
+        let page = admin
+            .list_users(authorized(zkp_auth::ListUsersRequest {
+                page_size: 0,
+                page_token: prefix.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(page.users[0].user_name, names[0]);
+        assert!(page.users.len() <= MAX_PAGE_SIZE as usize);
+        let full = page.users.len() == MAX_PAGE_SIZE as usize;
+        assert_eq!(page.next_page_token.is_empty(), !full);
+
+        // A failed login shows up in the history and in rate limiting
+        let wrong = ZKP::generate_random_below(&zkp.q);
+        assert!(login(&mut client, &zkp, &names[0], &wrong).await.is_err());
+        let attempts = admin
+            .get_login_attempts(authorized(zkp_auth::GetLoginAttemptsRequest {
+                user_name: names[0].clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .attempts;
+        assert_eq!(attempts.len(), 1);
+        assert!(!attempts[0].success);
+        let unlock = |name: &String| {
+            authorized(zkp_auth::UnlockUserRequest {
+                user_name: name.clone(),
+            })
+        };
+        assert!(
+            admin
+                .unlock_user(unlock(&names[0]))
+                .await
+                .unwrap()
+                .into_inner()
+                .was_limited
+        );
+        assert!(
+            !admin
+                .unlock_user(unlock(&names[0]))
+                .await
+                .unwrap()
+                .into_inner()
+                .was_limited
+        );
+
+        let session_id = login(&mut client, &zkp, &names[1], &password)
+            .await
+            .unwrap();
+        let revoked = admin
+            .revoke_user_sessions(authorized(zkp_auth::RevokeUserSessionsRequest {
+                user_name: names[1].clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .revoked;
+        assert_eq!(revoked, 1);
+        let validated = client
+            .validate_session(zkp_auth::ValidateSessionRequest { session_id })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!validated.valid);
+
+        let delete = |name: &String| {
+            authorized(zkp_auth::DeleteUserRequest {
+                user_name: name.clone(),
+            })
+        };
+        admin.delete_user(delete(&names[2])).await.unwrap();
+        let err = admin.delete_user(delete(&names[2])).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        assert!(login(&mut client, &zkp, &names[2], &password)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_admin_unlocks_locked_account() {
+        let policy = LockoutPolicy {
+            threshold: 10,
+            ..Default::default()
+        };
+        let (endpoint, admin_endpoint) = spawn_test_admin_server_with_lockout(TOKEN, policy).await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+        let mut admin = AdminClient::connect(admin_endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let user_name = format!("locked_{}", uuid::Uuid::new_v4());
+        client
+            .register(zkp_auth::RegisterRequest {
+                name: user_name.clone(),
+                y1: zkp.exponentiate(&zkp.alpha, &password).to_bytes_be(),
+                y2: zkp.exponentiate(&zkp.beta, &password).to_bytes_be(),
+                param_set: zkp.name().to_string(),
+                kdf: Some(KdfParams::generate().into()),
+            })
+            .await
+            .unwrap();
+        let unlock = || {
+            authorized(zkp_auth::UnlockUserRequest {
+                user_name: user_name.clone(),
+            })
+        };
+
+        // Failures past the lockout threshold lock the account. Clearing the
+        // rate limit halfway lets them through; it does not reset the count.
+        let wrong = ZKP::generate_random_below(&zkp.q);
+        let threshold = policy.threshold;
+        for attempt in 1..=threshold {
+            assert!(login(&mut client, &zkp, &user_name, &wrong).await.is_err());
+            if attempt == threshold / 2 {
+                assert!(
+                    !admin
+                        .unlock_user(unlock())
+                        .await
+                        .unwrap()
+                        .into_inner()
+                        .was_locked
+                );
+            }
+        }
+        let err = client
+            .create_authentication_challenge(zkp_auth::AuthenticationChallengeRequest {
+                name: user_name.clone(),
+                r1: zkp.alpha.to_bytes_be(),
+                r2: zkp.beta.to_bytes_be(),
+                param_set: zkp.name().to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(err.metadata().get(ACCOUNT_LOCKED_METADATA).is_some());
+
+        let unlocked = admin.unlock_user(unlock()).await.unwrap().into_inner();
+        assert!(unlocked.was_limited && unlocked.was_locked);
+        assert!(login(&mut client, &zkp, &user_name, &password)
+            .await
+            .is_ok());
+
+        // Both ends of the lock are in the audit trail
+        let pool = sqlx::PgPool::connect(&std::env::var("DATABASE_URL").unwrap())
+            .await
+            .unwrap();
+        let mut tx = pool.begin().await.unwrap();
+        let events: Vec<String> = db::get_lockout_events_by_user(&mut tx, &user_name)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| format!("{}:{}", event.event, event.actor))
+            .collect();
+        assert_eq!(events, ["locked:policy", "unlocked:admin"]);
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+
+use crate::{BatchItem, ZKP};
+
+/// Largest number of proofs checked in one batch
+pub const DEFAULT_MAX_BATCH: usize = 64;
+
+/// How long the first proof of a batch waits for others to arrive
+pub const DEFAULT_MAX_WAIT: Duration = Duration::from_millis(2);
+
+struct Job {
+    zkp: ZKP,
+    item: BatchItem,
+    reply: oneshot::Sender<bool>,
+}
+
+/// Collects proofs that arrive close together and checks them with
+/// `ZKP::verify_batch` on a blocking thread.
+#[derive(Debug, Clone)]
+pub struct BatchVerifier {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl BatchVerifier {
+    /// Starts the batching task on the current Tokio runtime
+    pub fn spawn(max_batch: usize, max_wait: Duration) -> Self {
+        let max_batch = max_batch.max(1);
+        let (jobs, rx) = mpsc::channel(max_batch * 4);
+        tokio::spawn(collect_batches(rx, max_batch, max_wait));
+        BatchVerifier { jobs }
+    }
+
+    /// Queues a proof and waits for its result. The elements must already be validated.
+    pub async fn verify(&self, zkp: ZKP, item: BatchItem) -> bool {
+        let (reply, result) = oneshot::channel();
+        if let Err(mpsc::error::SendError(job)) = self.jobs.send(Job { zkp, item, reply }).await {
+            // The batching task is gone; check the proof directly
+            let Job { zkp, item, .. } = job;
+            return zkp.verify(&item.r1, &item.r2, &item.y1, &item.y2, &item.c, &item.s);
+        }
+        result.await.unwrap_or(false)
+    }
+}
+
+async fn collect_batches(mut rx: mpsc::Receiver<Job>, max_batch: usize, max_wait: Duration) {
+    while let Some(first) = rx.recv().await {
+        let mut jobs = vec![first];
+        let deadline = tokio::time::Instant::now() + max_wait;
+        while jobs.len() < max_batch {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(job)) => jobs.push(job),
+                _ => break,
+            }
+        }
+        debug!(size = jobs.len(), event = "verify_batch", "dispatching");
+        tokio::task::spawn_blocking(move || verify_jobs(jobs));
+    }
+}
+
+fn verify_jobs(jobs: Vec<Job>) {
+    // Proofs from different parameter sets can't share a batch
+    let mut by_param_set: HashMap<String, Vec<Job>> = HashMap::new();
+    for job in jobs {
+        by_param_set
+            .entry(job.zkp.name().to_string())
+            .or_default()
+            .push(job);
+    }
+    for jobs in by_param_set.into_values() {
+        let zkp = jobs[0].zkp.clone();
+        let (items, replies): (Vec<BatchItem>, Vec<_>) =
+            jobs.into_iter().map(|job| (job.item, job.reply)).unzip();
+        for (reply, ok) in replies.into_iter().zip(zkp.verify_batch(&items)) {
+            let _ = reply.send(ok);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ristretto255;
+
+    fn item(zkp: &ZKP, x: &num_bigint::BigUint) -> BatchItem {
+        let k = ZKP::generate_random_below(&zkp.q);
+        let c = ZKP::generate_random_below(&zkp.q);
+        BatchItem {
+            r1: zkp.exponentiate(&zkp.alpha, &k),
+            r2: zkp.exponentiate(&zkp.beta, &k),
+            y1: zkp.exponentiate(&zkp.alpha, x),
+            y2: zkp.exponentiate(&zkp.beta, x),
+            s: zkp.solve(&k, &c, x),
+            c,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_proofs_are_batched() {
+        let verifier = BatchVerifier::spawn(16, Duration::from_millis(20));
+        let zkp = ZKP::new(Ristretto255::new());
+
+        let mut handles = Vec::new();
+        for i in 0..10 {
+            let x = ZKP::generate_random_below(&zkp.q);
+            let mut item = item(&zkp, &x);
+            if i % 3 == 0 {
+                item.s = (&item.s + 1u32) % &zkp.q;
+            }
+            let verifier = verifier.clone();
+            let zkp = zkp.clone();
+            handles.push(tokio::spawn(
+                async move { verifier.verify(zkp, item).await },
+            ));
+        }
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.await.unwrap(), i % 3 != 0, "proof {}", i);
+        }
+    }
+}
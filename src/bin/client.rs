@@ -1,4 +1,4 @@
 #[tokio::main]
 async fn main() {
     chaum_pederson_rust::client::run_client().await;
-}
\ No newline at end of file
+}
@@ -1,4 +1,4 @@
 #[tokio::main]
 async fn main() {
     chaum_pederson_rust::server::run_server().await;
-}
\ No newline at end of file
+}
@@ -0,0 +1,537 @@
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use sqlx::PgPool;
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+
+use crate::db::{self, PendingChallenge};
+
+/// How long a client has to answer a challenge
+pub const DEFAULT_CHALLENGE_TTL_SECS: i64 = 60;
+/// Default number of unanswered challenges one user may hold
+pub const DEFAULT_MAX_PER_USER: u64 = 5;
+/// Default number of unanswered challenges across all users
+pub const DEFAULT_MAX_PENDING: u64 = 10_000;
+
+/// Lifetime and caps of pending challenges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChallengeLimits {
+    pub ttl: Duration,
+    pub max_per_user: u64,
+    pub max_pending: u64,
+}
+
+impl Default for ChallengeLimits {
+    fn default() -> Self {
+        ChallengeLimits {
+            ttl: Duration::seconds(DEFAULT_CHALLENGE_TTL_SECS),
+            max_per_user: DEFAULT_MAX_PER_USER,
+            max_pending: DEFAULT_MAX_PENDING,
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+impl ChallengeLimits {
+    /// Reads `ZKP_CHALLENGE_TTL_SECS`, `ZKP_MAX_CHALLENGES_PER_USER` and
+    /// `ZKP_MAX_PENDING_CHALLENGES`, falling back to the defaults
+    pub fn from_env() -> Self {
+        ChallengeLimits {
+            ttl: Duration::seconds(env_or("ZKP_CHALLENGE_TTL_SECS", DEFAULT_CHALLENGE_TTL_SECS)),
+            max_per_user: env_or("ZKP_MAX_CHALLENGES_PER_USER", DEFAULT_MAX_PER_USER),
+            max_pending: env_or("ZKP_MAX_PENDING_CHALLENGES", DEFAULT_MAX_PENDING),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ChallengeStoreError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("too many unanswered challenges")]
+    TooManyPending,
+}
+
+/// Where pending challenges wait between `CreateAuthenticationChallenge` and
+/// `VerifyAuthentication`, and where ids of accepted non-interactive proofs are
+/// kept to refuse replays
+#[tonic::async_trait]
+pub trait ChallengeStore: Send + Sync + fmt::Debug {
+    /// Lifetime and caps this store enforces
+    fn limits(&self) -> ChallengeLimits;
+
+    /// Stores a challenge under `auth_id`, unless the store as a whole already
+    /// holds the maximum number of live challenges. A user at their own cap
+    /// loses their oldest challenge, so nobody can block a user's logins by
+    /// requesting challenges in their name.
+    async fn insert(
+        &self,
+        auth_id: &str,
+        challenge: PendingChallenge,
+    ) -> Result<(), ChallengeStoreError>;
+
+    /// Removes a challenge and returns it unless it has expired. Each
+    /// challenge is returned at most once, however many callers race for it.
+    async fn take(&self, auth_id: &str) -> Result<Option<PendingChallenge>, ChallengeStoreError>;
+
+    /// Records a non-interactive proof id until `expires_at`; returns false if
+    /// it is already recorded. Of racing claims for one id exactly one wins.
+    async fn claim_proof(
+        &self,
+        proof_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, ChallengeStoreError>;
+
+    /// Forgets a claimed proof id, so that a rejected proof does not block a
+    /// valid one with the same id
+    async fn release_proof(&self, proof_id: &str) -> Result<(), ChallengeStoreError>;
+
+    /// Drops every expired challenge and proof id; returns how many challenges
+    /// were dropped
+    async fn remove_expired(&self) -> Result<u64, ChallengeStoreError>;
+
+    /// Number of challenges currently stored
+    async fn pending(&self) -> Result<u64, ChallengeStoreError>;
+}
+
+/// Challenges kept in this process; answers must reach the same instance
+#[derive(Debug, Default)]
+pub struct MemoryChallengeStore {
+    limits: ChallengeLimits,
+    challenges: DashMap<String, PendingChallenge>,
+    /// Ids of each user's challenges, oldest first
+    per_user: DashMap<String, VecDeque<String>>,
+    total: AtomicU64,
+    proofs: DashMap<String, DateTime<Utc>>,
+}
+
+impl MemoryChallengeStore {
+    pub fn new(limits: ChallengeLimits) -> Self {
+        MemoryChallengeStore {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    // Releases the counts held by a challenge that left the map
+    fn forget(&self, auth_id: &str, challenge: &PendingChallenge) {
+        self.total.fetch_sub(1, Ordering::SeqCst);
+        if let Some(mut ids) = self.per_user.get_mut(&challenge.user_name) {
+            ids.retain(|id| id != auth_id);
+        }
+        self.per_user
+            .remove_if(&challenge.user_name, |_, ids| ids.is_empty());
+    }
+}
+
+#[tonic::async_trait]
+impl ChallengeStore for MemoryChallengeStore {
+    fn limits(&self) -> ChallengeLimits {
+        self.limits
+    }
+
+    async fn insert(
+        &self,
+        auth_id: &str,
+        challenge: PendingChallenge,
+    ) -> Result<(), ChallengeStoreError> {
+        if self.total.fetch_add(1, Ordering::SeqCst) >= self.limits.max_pending {
+            self.total.fetch_sub(1, Ordering::SeqCst);
+            return Err(ChallengeStoreError::TooManyPending);
+        }
+        // The entry guard makes the per-user eviction and insert atomic.
+        let mut ids = self
+            .per_user
+            .entry(challenge.user_name.clone())
+            .or_default();
+        while ids.len() as u64 >= self.limits.max_per_user {
+            let Some(oldest) = ids.pop_front() else {
+                break;
+            };
+            // A concurrent take that got there first releases the count itself
+            if self.challenges.remove(&oldest).is_some() {
+                self.total.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+        ids.push_back(auth_id.to_string());
+        self.challenges.insert(auth_id.to_string(), challenge);
+        Ok(())
+    }
+
+    async fn take(&self, auth_id: &str) -> Result<Option<PendingChallenge>, ChallengeStoreError> {
+        let Some((_, challenge)) = self.challenges.remove(auth_id) else {
+            return Ok(None);
+        };
+        self.forget(auth_id, &challenge);
+        Ok(Some(challenge).filter(|challenge| challenge.expires_at > Utc::now()))
+    }
+
+    async fn claim_proof(
+        &self,
+        proof_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, ChallengeStoreError> {
+        let now = Utc::now();
+        let mut claimed = false;
+        self.proofs
+            .entry(proof_id.to_string())
+            .and_modify(|until| {
+                if *until <= now {
+                    *until = expires_at;
+                    claimed = true;
+                }
+            })
+            .or_insert_with(|| {
+                claimed = true;
+                expires_at
+            });
+        Ok(claimed)
+    }
+
+    async fn release_proof(&self, proof_id: &str) -> Result<(), ChallengeStoreError> {
+        self.proofs.remove(proof_id);
+        Ok(())
+    }
+
+    async fn remove_expired(&self) -> Result<u64, ChallengeStoreError> {
+        let now = Utc::now();
+        self.proofs.retain(|_, until| *until > now);
+        let expired: Vec<String> = self
+            .challenges
+            .iter()
+            .filter(|entry| entry.expires_at <= now)
+            .map(|entry| entry.key().clone())
+            .collect();
+        let mut removed = 0;
+        for auth_id in expired {
+            // A concurrent take may have got there first
+            if let Some((_, challenge)) = self.challenges.remove(&auth_id) {
+                self.forget(&auth_id, &challenge);
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    async fn pending(&self) -> Result<u64, ChallengeStoreError> {
+        Ok(self.total.load(Ordering::SeqCst))
+    }
+}
+
+/// Challenges in the `pending_challenges` table, shared by every instance
+/// that uses the same database. Caps count live rows, so inserts racing on
+/// several instances can overshoot them by the number in flight.
+#[derive(Debug, Clone)]
+pub struct PgChallengeStore {
+    db: PgPool,
+    limits: ChallengeLimits,
+}
+
+impl PgChallengeStore {
+    pub fn new(db: PgPool, limits: ChallengeLimits) -> Self {
+        PgChallengeStore { db, limits }
+    }
+}
+
+#[tonic::async_trait]
+impl ChallengeStore for PgChallengeStore {
+    fn limits(&self) -> ChallengeLimits {
+        self.limits
+    }
+
+    async fn insert(
+        &self,
+        auth_id: &str,
+        challenge: PendingChallenge,
+    ) -> Result<(), ChallengeStoreError> {
+        let mut tx = self.db.begin().await?;
+        let now = Utc::now();
+        if db::count_live_challenges(&mut tx, None, now).await? >= self.limits.max_pending as i64 {
+            return Err(ChallengeStoreError::TooManyPending);
+        }
+        let held = db::count_live_challenges(&mut tx, Some(&challenge.user_name), now).await?;
+        let excess = held - self.limits.max_per_user as i64 + 1;
+        if excess > 0 {
+            db::delete_oldest_challenges(&mut tx, &challenge.user_name, excess, now).await?;
+        }
+        db::insert_pending_challenge(&mut tx, auth_id, &challenge).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn take(&self, auth_id: &str) -> Result<Option<PendingChallenge>, ChallengeStoreError> {
+        let mut tx = self.db.begin().await?;
+        let challenge = db::take_pending_challenge(&mut tx, auth_id).await?;
+        tx.commit().await?;
+        Ok(challenge.filter(|challenge| challenge.expires_at > Utc::now()))
+    }
+
+    async fn claim_proof(
+        &self,
+        proof_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, ChallengeStoreError> {
+        let mut tx = self.db.begin().await?;
+        let claimed = db::insert_used_proof(&mut tx, proof_id, expires_at, Utc::now()).await?;
+        tx.commit().await?;
+        Ok(claimed)
+    }
+
+    async fn release_proof(&self, proof_id: &str) -> Result<(), ChallengeStoreError> {
+        let mut tx = self.db.begin().await?;
+        db::delete_used_proof(&mut tx, proof_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn remove_expired(&self) -> Result<u64, ChallengeStoreError> {
+        let mut tx = self.db.begin().await?;
+        let now = Utc::now();
+        let removed = db::delete_expired_challenges(&mut tx, now).await?;
+        db::delete_expired_proofs(&mut tx, now).await?;
+        tx.commit().await?;
+        Ok(removed)
+    }
+
+    async fn pending(&self) -> Result<u64, ChallengeStoreError> {
+        let mut tx = self.db.begin().await?;
+        let pending = db::count_pending_challenges(&mut tx).await?;
+        tx.commit().await?;
+        Ok(pending as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kdf::KdfParams, params::LEGACY_PARAM_SET};
+    use num_bigint::BigUint;
+    use std::sync::Arc;
+
+    fn challenge(user_name: &str, ttl: Duration) -> PendingChallenge {
+        let now = Utc::now();
+        PendingChallenge {
+            user_name: user_name.to_string(),
+            r1: BigUint::from(2u32),
+            r2: BigUint::from(3u32),
+            c: BigUint::from(5u32),
+            created_at: now,
+            expires_at: now + ttl,
+        }
+    }
+
+    async fn check_store(store: Arc<dyn ChallengeStore>, user_name: &str) {
+        let auth_id = format!("chl_{}", uuid::Uuid::new_v4());
+        store
+            .insert(&auth_id, challenge(user_name, store.limits().ttl))
+            .await
+            .unwrap();
+        let taken = store
+            .take(&auth_id)
+            .await
+            .unwrap()
+            .expect("challenge missing");
+        assert_eq!(taken.user_name, user_name);
+        assert_eq!(taken.c, BigUint::from(5u32));
+        assert!(store.take(&auth_id).await.unwrap().is_none());
+
+        let expired = format!("chl_{}", uuid::Uuid::new_v4());
+        store
+            .insert(&expired, challenge(user_name, Duration::seconds(-1)))
+            .await
+            .unwrap();
+        assert!(store.take(&expired).await.unwrap().is_none());
+
+        // Of many concurrent takes exactly one wins
+        let raced = format!("chl_{}", uuid::Uuid::new_v4());
+        store
+            .insert(&raced, challenge(user_name, store.limits().ttl))
+            .await
+            .unwrap();
+        let takes = (0..8).map(|_| {
+            let store = Arc::clone(&store);
+            let raced = raced.clone();
+            tokio::spawn(async move { store.take(&raced).await.unwrap().is_some() })
+        });
+        let mut won = 0;
+        for take in takes {
+            won += take.await.unwrap() as usize;
+        }
+        assert_eq!(won, 1);
+
+        // A proof id is claimed once until it expires or is released
+        let proof_id = format!("proof_{}", uuid::Uuid::new_v4());
+        let until = Utc::now() + store.limits().ttl;
+        assert!(store.claim_proof(&proof_id, until).await.unwrap());
+        assert!(!store.claim_proof(&proof_id, until).await.unwrap());
+        store.release_proof(&proof_id).await.unwrap();
+        assert!(store.claim_proof(&proof_id, until).await.unwrap());
+        let lapsed = format!("proof_{}", uuid::Uuid::new_v4());
+        assert!(store
+            .claim_proof(&lapsed, Utc::now() - Duration::seconds(1))
+            .await
+            .unwrap());
+        assert!(store.claim_proof(&lapsed, until).await.unwrap());
+
+        let raced = format!("proof_{}", uuid::Uuid::new_v4());
+        let claims = (0..8).map(|_| {
+            let store = Arc::clone(&store);
+            let raced = raced.clone();
+            tokio::spawn(async move { store.claim_proof(&raced, until).await.unwrap() })
+        });
+        let mut won = 0;
+        for claim in claims {
+            won += claim.await.unwrap() as usize;
+        }
+        assert_eq!(won, 1);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        check_store(
+            Arc::new(MemoryChallengeStore::new(ChallengeLimits::default())),
+            "memory_user",
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_caps_and_sweep() {
+        let limits = ChallengeLimits {
+            max_per_user: 2,
+            max_pending: 3,
+            ..Default::default()
+        };
+        let store = MemoryChallengeStore::new(limits);
+        store
+            .insert("a1", challenge("alice", limits.ttl))
+            .await
+            .unwrap();
+        store
+            .insert("a2", challenge("alice", Duration::seconds(-1)))
+            .await
+            .unwrap();
+        store
+            .insert("b1", challenge("bob", limits.ttl))
+            .await
+            .unwrap();
+        assert!(matches!(
+            store.insert("c1", challenge("carol", limits.ttl)).await,
+            Err(ChallengeStoreError::TooManyPending)
+        ));
+        assert_eq!(store.pending().await.unwrap(), 3);
+
+        // Sweeping the expired challenge frees a slot, and expired proof ids go
+        // with it
+        store
+            .claim_proof("p1", Utc::now() - Duration::seconds(1))
+            .await
+            .unwrap();
+        store
+            .claim_proof("p2", Utc::now() + limits.ttl)
+            .await
+            .unwrap();
+        assert_eq!(store.remove_expired().await.unwrap(), 1);
+        assert!(!store.proofs.contains_key("p1") && store.proofs.contains_key("p2"));
+        assert_eq!(store.pending().await.unwrap(), 2);
+
+        // A user at their cap loses their oldest challenge instead of being
+        // refused
+        store
+            .insert("a3", challenge("alice", limits.ttl))
+            .await
+            .unwrap();
+        assert!(store.take("b1").await.unwrap().is_some());
+        store
+            .insert("a4", challenge("alice", limits.ttl))
+            .await
+            .unwrap();
+        assert_eq!(store.pending().await.unwrap(), 2);
+        assert!(store.take("a1").await.unwrap().is_none());
+        assert!(store.take("a3").await.unwrap().is_some());
+        assert!(store.take("a4").await.unwrap().is_some());
+        assert_eq!(store.pending().await.unwrap(), 0);
+        assert!(store.per_user.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_postgres_store() {
+        dotenvy::from_filename(".env.test").ok();
+        let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPool::connect(&db_url).await.unwrap();
+
+        let user_name = format!("challenge_user_{}", uuid::Uuid::new_v4());
+        let mut tx = pool.begin().await.unwrap();
+        db::insert_user(
+            &mut tx,
+            db::User {
+                user_name: user_name.clone(),
+                y1: BigUint::from(10u32),
+                y2: BigUint::from(20u32),
+                param_set: LEGACY_PARAM_SET.to_string(),
+                kdf: KdfParams::legacy(),
+                created_at: Utc::now(),
+            },
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        check_store(
+            Arc::new(PgChallengeStore::new(
+                pool.clone(),
+                ChallengeLimits::default(),
+            )),
+            &user_name,
+        )
+        .await;
+
+        let limits = ChallengeLimits {
+            max_per_user: 1,
+            ..Default::default()
+        };
+        let store = PgChallengeStore::new(pool.clone(), limits);
+        let expired = format!("chl_{}", uuid::Uuid::new_v4());
+        store
+            .insert(&expired, challenge(&user_name, Duration::seconds(-1)))
+            .await
+            .unwrap();
+        // Expired rows do not count towards the cap
+        let live = format!("chl_{}", uuid::Uuid::new_v4());
+        store
+            .insert(&live, challenge(&user_name, limits.ttl))
+            .await
+            .unwrap();
+        // A user at their cap loses their oldest live challenge
+        let newer = format!("chl_{}", uuid::Uuid::new_v4());
+        store
+            .insert(&newer, challenge(&user_name, limits.ttl))
+            .await
+            .unwrap();
+        assert!(store.take(&live).await.unwrap().is_none());
+        assert!(store.pending().await.unwrap() >= 2);
+        assert!(store.remove_expired().await.unwrap() >= 1);
+        let mut tx = pool.begin().await.unwrap();
+        assert!(db::take_pending_challenge(&mut tx, &expired)
+            .await
+            .unwrap()
+            .is_none());
+        tx.rollback().await.unwrap();
+        assert!(store.take(&newer).await.unwrap().is_some());
+
+        let mut tx = pool.begin().await.unwrap();
+        db::delete_user_by_username(&mut tx, &user_name)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+    }
+}
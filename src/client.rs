@@ -3,47 +3,111 @@ use num_bigint::BigUint;
 use std::time::Instant;
 use tonic::transport::Channel;
 use tracing::{info, instrument};
+use zeroize::Zeroizing;
 // Import BigUint for handling large integers.
-use crate::ZKP;
 use crate::zkp_auth::{
     self, auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest,
     RegisterRequest,
 };
+use crate::{
+    kdf::{KdfError, KdfParams, KDF_NONE},
+    params, NonceMode, SecretScalar, SharedRng, ZKP,
+};
 
 #[derive(Parser)]
 #[command(name = "ZKP Client", about = "A client for ZKP authentication server")]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Named parameter set to register and authenticate with
+    #[arg(long, global = true, default_value = params::DEFAULT_PARAM_SET)]
+    param_set: String,
+    /// Seeds the salt and nonce RNG to replay a run exactly. Insecure outside tests.
+    #[arg(long, global = true)]
+    rng_seed: Option<u64>,
+    /// Use the raw password as the secret for accounts registered before the KDF
+    #[arg(long, global = true)]
+    allow_legacy_kdf: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    Register { username: String, password: String },
-    Authenticate { username: String, password: String },
-    Logout { session_id: String },
-    ValidateSession { session_id: String },
+    Register {
+        username: String,
+        password: String,
+    },
+    Authenticate {
+        username: String,
+        password: String,
+    },
+    Logout {
+        session_id: String,
+    },
+    ValidateSession {
+        session_id: String,
+    },
+    RefreshSession {
+        refresh_token: String,
+    },
+    ListSessions {
+        session_id: String,
+    },
+    RevokeAllSessions {
+        session_id: String,
+        /// Keep the session used to make the call
+        #[arg(long)]
+        keep_current: bool,
+    },
+    RotateCredential {
+        username: String,
+        password: String,
+        new_password: String,
+        /// Also sign out every existing session
+        #[arg(long)]
+        revoke_sessions: bool,
+    },
+    DeleteAccount {
+        username: String,
+        password: String,
+        /// Keep the auth history under an anonymous alias
+        #[arg(long)]
+        anonymize_logs: bool,
+    },
+    ExportAccountData {
+        session_id: String,
+    },
 }
 
-#[instrument(skip(client, zkp, password))]
+#[instrument(skip(client, zkp, password, rng))]
 async fn register_user(
     username: String,
     password: String,
     zkp: &ZKP,
     client: &mut AuthClient<Channel>,
+    rng: &mut SharedRng,
 ) {
     info!(user = %username, event = "register", "start"); // Log registration attempt.
     let start = Instant::now(); // Start timer for registration process.
-    let password = BigUint::from_bytes_be(password.trim().as_bytes()); // Convert password to BigUint for ZKP computations.
-                                                                       // Compute y1 = alpha^password mod p and y2 = beta^password mod p for registration.
-    let y1 = zkp.exponentiate(&zkp.alpha, &password);
-    let y2 = zkp.exponentiate(&zkp.beta, &password);
+    let password = Zeroizing::new(password);
+    // Derive the secret x from the password with Argon2id and a fresh salt.
+    let kdf = KdfParams::generate_with(rng);
+    let password = match kdf.derive_secret_scalar(password.trim(), &zkp.q) {
+        Ok(x) => x,
+        Err(e) => {
+            info!(user = %username, error = %e, event = "register", "failed");
+            return;
+        }
+    };
+    // Compute y1 = alpha^x and y2 = beta^x for registration.
+    let (y1, y2) = zkp.commit(&password);
 
     // Create a registration request with the computed values.
     let request = RegisterRequest {
         name: username.clone(),
         y1: y1.to_bytes_be(),
         y2: y2.to_bytes_be(),
+        param_set: zkp.name().to_string(),
+        kdf: Some(kdf.into()),
     };
 
     // Send the registration request to the server and handle response.
@@ -58,28 +122,74 @@ async fn register_user(
     }
 }
 
-#[instrument(skip(client, zkp, password))]
+// The named parameter set, proving with hedged nonces so that a weak RNG alone
+// does not expose x
+fn client_zkp(param_set: &str) -> Option<ZKP> {
+    params::param_set(param_set).map(|zkp| zkp.with_nonce_mode(NonceMode::Hedged))
+}
+
+// Picks the nonce k for an interactive login with the ZKP's nonce mode and
+// commits to it. The user and time keep the hedged derivation from repeating.
+fn login_commitment(
+    zkp: &ZKP,
+    username: &str,
+    x: &SecretScalar,
+    timestamp_ms: u64,
+    rng: &mut SharedRng,
+) -> (SecretScalar, BigUint, BigUint) {
+    let context = ZKP::fiat_shamir_context(username, timestamp_ms);
+    let k = zkp.generate_nonce(x, &context, rng);
+    let (r1, r2) = zkp.commit(&k);
+    (k, r1, r2)
+}
+
+// Accepts the KDF parameters the server stored for a user. The raw-password
+// scheme, also what a server that sends none uses, needs an explicit opt-in.
+fn stored_kdf(
+    kdf: Option<zkp_auth::KdfParams>,
+    allow_legacy_kdf: bool,
+) -> Result<KdfParams, KdfError> {
+    let kdf = kdf.map(KdfParams::from).unwrap_or_else(KdfParams::legacy);
+    if kdf.algorithm == KDF_NONE && !allow_legacy_kdf {
+        return Err(KdfError::LegacyNotAllowed);
+    }
+    Ok(kdf)
+}
+
+#[instrument(skip(client, zkp, password, rng))]
 async fn authenticate_user(
     username: String,
     password: String,
+    allow_legacy_kdf: bool,
     zkp: &ZKP,
     client: &mut AuthClient<Channel>,
+    rng: &mut SharedRng,
 ) {
     info!(user = %username, event = "create_challenge", "start"); // Log authentication attempt.
     let start = Instant::now(); // Start timer for authentication process.
-    let password = BigUint::from_bytes_be(password.trim().as_bytes()); // Convert password to BigUint for ZKP computations.
-                                                                       // Generate a random challenge value k < q.
-    let k = ZKP::generate_random_below(&zkp.q);
+    let password = Zeroizing::new(password);
+    // Derive the secret x first, since the hedged nonce depends on it.
+    let Some(kdf) = fetch_kdf_params(&username, allow_legacy_kdf, zkp, client, rng).await else {
+        return;
+    };
+    let password = match kdf.derive_secret_scalar(password.trim(), &zkp.q) {
+        Ok(x) => x,
+        Err(e) => {
+            info!(error = %e, user = %username, event = "create_challenge", "failed");
+            return;
+        }
+    };
 
     // Compute r1 = alpha^k mod p and r2 = beta^k mod p as part of the authentication challenge.
-    let r1 = zkp.exponentiate(&zkp.alpha, &k);
-    let r2 = zkp.exponentiate(&zkp.beta, &k);
+    let timestamp_ms = chrono::Utc::now().timestamp_millis() as u64;
+    let (k, r1, r2) = login_commitment(zkp, &username, &password, timestamp_ms, rng);
 
     // Create an authentication challenge request.
     let request = AuthenticationChallengeRequest {
         name: username.clone(),
         r1: r1.to_bytes_be(),
         r2: r2.to_bytes_be(),
+        param_set: zkp.name().to_string(),
     };
 
     // Send the challenge request to the server and handle response.
@@ -97,13 +207,20 @@ async fn authenticate_user(
     let auth_id = response.auth_id;
     let c = BigUint::from_bytes_be(&response.c);
 
+    // x was derived with the parameters fetched above; they must not have changed.
+    if response.kdf.map(KdfParams::from) != Some(kdf) {
+        info!(user = %username, event = "verify", "KDF parameters changed during login");
+        return;
+    }
+
     // Compute s = k + c * password mod q as part of the challenge solution.
-    let s = zkp.solve(&k, &c, &password);
+    let s = zkp.solve_secret(&k, &c, &password);
 
     // Create an authentication answer request with the computed s value.
     let request = AuthenticationAnswerRequest {
         auth_id,
         s: s.to_bytes_be(),
+        issue_token: false,
     };
     info!(user = %username, event = "verify", "start");
     // Send the answer to the server for verification and handle response.
@@ -119,6 +236,8 @@ async fn authenticate_user(
         user = %username,
         event = "verify",
         session_id = ?reponse.session_id,
+        refresh_token = ?reponse.refresh_token,
+        expires_at = reponse.expires_at,
         duration_ms = start.elapsed().as_millis(),
         "completed"
     );
@@ -158,17 +277,253 @@ async fn validate_session(session_id: String, client: &mut AuthClient<Channel>)
     }
 }
 
+#[instrument(skip(client, refresh_token))]
+async fn refresh_session(refresh_token: String, client: &mut AuthClient<Channel>) {
+    info!(event = "refresh_session", "start");
+    let request = zkp_auth::RefreshSessionRequest {
+        refresh_token,
+        issue_token: false,
+    };
+    match client.refresh_session(request).await {
+        Ok(response) => {
+            let response = response.into_inner();
+            info!(
+                session_id = %response.session_id,
+                refresh_token = %response.refresh_token,
+                expires_at = response.expires_at,
+                event = "refresh_session",
+                "completed"
+            );
+        }
+        Err(e) => {
+            info!(error = %e, event = "refresh_session", "failed");
+        }
+    }
+}
+
+#[instrument(skip(client, session_id))]
+async fn list_sessions(session_id: String, client: &mut AuthClient<Channel>) {
+    info!(event = "list_sessions", "start");
+    match client
+        .list_sessions(zkp_auth::ListSessionsRequest { session_id })
+        .await
+    {
+        Ok(response) => {
+            for session in response.into_inner().sessions {
+                info!(
+                    id = %&session.id[..16],
+                    created_at = session.created_at,
+                    expires_at = session.expires_at,
+                    client_addr = %session.client_addr,
+                    user_agent = %session.user_agent,
+                    active = session.active,
+                    current = session.current,
+                    revoke_reason = %session.revoke_reason,
+                    event = "list_sessions",
+                    "session"
+                );
+            }
+        }
+        Err(e) => {
+            info!(error = %e, event = "list_sessions", "failed");
+        }
+    }
+}
+
+#[instrument(skip(client, session_id))]
+async fn revoke_all_sessions(
+    session_id: String,
+    keep_current: bool,
+    client: &mut AuthClient<Channel>,
+) {
+    info!(event = "revoke_all_sessions", "start");
+    let request = zkp_auth::RevokeAllSessionsRequest {
+        session_id,
+        keep_current,
+    };
+    match client.revoke_all_sessions(request).await {
+        Ok(response) => {
+            info!(
+                revoked = response.into_inner().revoked,
+                event = "revoke_all_sessions",
+                "completed"
+            );
+        }
+        Err(e) => {
+            info!(error = %e, event = "revoke_all_sessions", "failed");
+        }
+    }
+}
+
+// The server only hands out a user's stored KDF parameters with a challenge,
+// which is then left to expire.
+async fn fetch_kdf_params(
+    username: &str,
+    allow_legacy_kdf: bool,
+    zkp: &ZKP,
+    client: &mut AuthClient<Channel>,
+    rng: &mut SharedRng,
+) -> Option<KdfParams> {
+    let k = SecretScalar::random(&zkp.q, rng);
+    let (r1, r2) = zkp.commit(&k);
+    let request = AuthenticationChallengeRequest {
+        name: username.to_string(),
+        r1: r1.to_bytes_be(),
+        r2: r2.to_bytes_be(),
+        param_set: zkp.name().to_string(),
+    };
+    match client.create_authentication_challenge(request).await {
+        Ok(response) => match stored_kdf(response.into_inner().kdf, allow_legacy_kdf) {
+            Ok(kdf) => Some(kdf),
+            Err(e) => {
+                info!(error = %e, user = %username, event = "fetch_kdf_params", "failed");
+                None
+            }
+        },
+        Err(e) => {
+            info!(error = %e, user = %username, event = "fetch_kdf_params", "failed");
+            None
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(client, zkp, password, new_password, rng))]
+async fn rotate_credential(
+    username: String,
+    password: String,
+    new_password: String,
+    revoke_sessions: bool,
+    allow_legacy_kdf: bool,
+    zkp: &ZKP,
+    client: &mut AuthClient<Channel>,
+    rng: &mut SharedRng,
+) {
+    info!(user = %username, event = "rotate_credential", "start");
+    let start = Instant::now(); // Start timer for credential rotation.
+    let password = Zeroizing::new(password);
+    let new_password = Zeroizing::new(new_password);
+
+    let Some(kdf) = fetch_kdf_params(&username, allow_legacy_kdf, zkp, client, rng).await else {
+        return;
+    };
+
+    // Derive both secrets; the new one gets a fresh salt.
+    let new_kdf = KdfParams::generate_with(rng);
+    let (x, new_x) = match (
+        kdf.derive_secret_scalar(password.trim(), &zkp.q),
+        new_kdf.derive_secret_scalar(new_password.trim(), &zkp.q),
+    ) {
+        (Ok(x), Ok(new_x)) => (x, new_x),
+        (Err(e), _) | (_, Err(e)) => {
+            info!(error = %e, user = %username, event = "rotate_credential", "failed");
+            return;
+        }
+    };
+    let (new_y1, new_y2) = zkp.commit(&new_x);
+
+    // Prove the old secret over a context that fixes the new commitments.
+    let timestamp_ms = chrono::Utc::now().timestamp_millis() as u64;
+    let context = zkp.rotation_context(&username, timestamp_ms, &new_y1, &new_y2, &new_kdf);
+    let (r1, r2, s) = zkp.prove_non_interactive_with_rng(&x, &context, rng);
+    let request = zkp_auth::RotateCredentialRequest {
+        name: username.clone(),
+        r1: r1.to_bytes_be(),
+        r2: r2.to_bytes_be(),
+        s: s.to_bytes_be(),
+        timestamp_ms,
+        new_y1: new_y1.to_bytes_be(),
+        new_y2: new_y2.to_bytes_be(),
+        new_kdf: Some(new_kdf.into()),
+        revoke_sessions,
+    };
+    match client.rotate_credential(request).await {
+        Ok(response) => {
+            info!(user = %username, revoked = response.into_inner().revoked, event = "rotate_credential", duration_ms = start.elapsed().as_millis(), "completed");
+        }
+        Err(e) => {
+            info!(error = %e, user = %username, event = "rotate_credential", duration_ms = start.elapsed().as_millis(), "failed");
+        }
+    }
+}
+
+#[instrument(skip(client, zkp, password, rng))]
+async fn delete_account(
+    username: String,
+    password: String,
+    anonymize_logs: bool,
+    allow_legacy_kdf: bool,
+    zkp: &ZKP,
+    client: &mut AuthClient<Channel>,
+    rng: &mut SharedRng,
+) {
+    info!(user = %username, event = "delete_account", "start");
+    let start = Instant::now(); // Start timer for account deletion.
+    let password = Zeroizing::new(password);
+    let Some(kdf) = fetch_kdf_params(&username, allow_legacy_kdf, zkp, client, rng).await else {
+        return;
+    };
+    let x = match kdf.derive_secret_scalar(password.trim(), &zkp.q) {
+        Ok(x) => x,
+        Err(e) => {
+            info!(error = %e, user = %username, event = "delete_account", "failed");
+            return;
+        }
+    };
+
+    let timestamp_ms = chrono::Utc::now().timestamp_millis() as u64;
+    let context = ZKP::deletion_context(&username, timestamp_ms, anonymize_logs);
+    let (r1, r2, s) = zkp.prove_non_interactive_with_rng(&x, &context, rng);
+    let request = zkp_auth::DeleteAccountRequest {
+        name: username.clone(),
+        r1: r1.to_bytes_be(),
+        r2: r2.to_bytes_be(),
+        s: s.to_bytes_be(),
+        timestamp_ms,
+        anonymize_logs,
+    };
+    match client.delete_account(request).await {
+        Ok(response) => {
+            info!(user = %username, logs_anonymized = response.into_inner().logs_anonymized, event = "delete_account", duration_ms = start.elapsed().as_millis(), "completed");
+        }
+        Err(e) => {
+            info!(error = %e, user = %username, event = "delete_account", duration_ms = start.elapsed().as_millis(), "failed");
+        }
+    }
+}
+
+#[instrument(skip(client, session_id))]
+async fn export_account_data(session_id: String, client: &mut AuthClient<Channel>) {
+    info!(event = "export_account_data", "start");
+    match client
+        .export_account_data(zkp_auth::ExportAccountDataRequest { session_id })
+        .await
+    {
+        Ok(response) => {
+            // The export goes to stdout so it can be redirected to a file.
+            println!("{}", response.into_inner().json);
+            info!(event = "export_account_data", "completed");
+        }
+        Err(e) => {
+            info!(error = %e, event = "export_account_data", "failed");
+        }
+    }
+}
+
 pub async fn run_client() {
     tracing_subscriber::fmt()
         .with_env_filter("info") // can change via env
         .init();
     let cli = Cli::parse(); // Parse command-line arguments.
 
-    // Retrieve constants like alpha, beta, p, and q used for Zero-Knowledge Proofs.
-    let (alpha, beta, p, q) = ZKP::get_constants();
-
-    // Initialize the ZKP struct with constants.
-    let zkp = ZKP { p, q, alpha, beta };
+    // Look up the group and generators of the requested parameter set.
+    let zkp = match client_zkp(&cli.param_set) {
+        Some(zkp) => zkp,
+        None => {
+            info!(param_set = %cli.param_set, event = "connect", "unknown parameter set");
+            return;
+        }
+    };
 
     // Connect to the authentication server via gRPC.
     let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
@@ -179,13 +534,27 @@ pub async fn run_client() {
         }
     };
 
+    let mut rng = match cli.rng_seed {
+        Some(seed) => SharedRng::seeded(seed),
+        None => SharedRng::from_entropy(),
+    };
+
     info!(event = "connect", "Client started listening"); // Debug message.
     match cli.command {
         Commands::Register { username, password } => {
-            register_user(username, password, &zkp, &mut client).await; // Handle user registration.
+            register_user(username, password, &zkp, &mut client, &mut rng).await;
+            // Handle user registration.
         }
         Commands::Authenticate { username, password } => {
-            authenticate_user(username, password, &zkp, &mut client).await; // Handle user authentication.
+            authenticate_user(
+                username,
+                password,
+                cli.allow_legacy_kdf,
+                &zkp,
+                &mut client,
+                &mut rng,
+            )
+            .await; // Handle user authentication.
         }
         Commands::Logout { session_id } => {
             logout_user(session_id, &mut client).await; // Handle user logout.
@@ -193,22 +562,72 @@ pub async fn run_client() {
         Commands::ValidateSession { session_id } => {
             validate_session(session_id, &mut client).await; // Handle session validation.
         }
+        Commands::RefreshSession { refresh_token } => {
+            refresh_session(refresh_token, &mut client).await; // Exchange a refresh token for a new session.
+        }
+        Commands::ListSessions { session_id } => {
+            list_sessions(session_id, &mut client).await;
+        }
+        Commands::RevokeAllSessions {
+            session_id,
+            keep_current,
+        } => {
+            revoke_all_sessions(session_id, keep_current, &mut client).await; // Sign out everywhere.
+        }
+        Commands::RotateCredential {
+            username,
+            password,
+            new_password,
+            revoke_sessions,
+        } => {
+            rotate_credential(
+                username,
+                password,
+                new_password,
+                revoke_sessions,
+                cli.allow_legacy_kdf,
+                &zkp,
+                &mut client,
+                &mut rng,
+            )
+            .await; // Replace the password.
+        }
+        Commands::DeleteAccount {
+            username,
+            password,
+            anonymize_logs,
+        } => {
+            delete_account(
+                username,
+                password,
+                anonymize_logs,
+                cli.allow_legacy_kdf,
+                &zkp,
+                &mut client,
+                &mut rng,
+            )
+            .await; // Delete the account for good.
+        }
+        Commands::ExportAccountData { session_id } => {
+            export_account_data(session_id, &mut client).await; // Print everything stored about the user.
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use uuid::Uuid;
-    use num_bigint::BigUint;
+    use crate::test_utils::{setup_zkp, spawn_test_server};
+    use crate::ModPGroup;
     use clap::Parser;
+    use num_bigint::BigUint;
     use num_traits::Zero;
-    use crate::test_utils::{setup_zkp, spawn_test_server};
+    use uuid::Uuid;
 
     #[test]
     fn test_register_request_construction() {
         let (alpha, beta, p, q) = ZKP::get_constants();
-        let zkp = ZKP { p, q, alpha, beta };
+        let zkp = ZKP::new(ModPGroup::new(p, q, alpha, beta));
 
         let password = "secret";
         let password_big = BigUint::from_bytes_be(password.as_bytes());
@@ -231,6 +650,63 @@ mod tests {
             }
             _ => panic!("wrong command"),
         }
+        assert_eq!(cli.param_set, params::DEFAULT_PARAM_SET);
+    }
+
+    #[test]
+    fn test_cli_param_set_parse() {
+        let cli = Cli::parse_from([
+            "app",
+            "register",
+            "user",
+            "pass",
+            "--param-set",
+            "ristretto255",
+        ]);
+        assert_eq!(cli.param_set, "ristretto255");
+    }
+
+    #[test]
+    fn test_legacy_kdf_needs_opt_in() {
+        let cli = Cli::parse_from(["app", "authenticate", "user", "pass"]);
+        assert!(!cli.allow_legacy_kdf);
+        let cli = Cli::parse_from(["app", "authenticate", "user", "pass", "--allow-legacy-kdf"]);
+        assert!(cli.allow_legacy_kdf);
+
+        for kdf in [None, Some(KdfParams::legacy().into())] {
+            assert_eq!(
+                stored_kdf(kdf.clone(), false),
+                Err(KdfError::LegacyNotAllowed)
+            );
+            assert_eq!(stored_kdf(kdf, true), Ok(KdfParams::legacy()));
+        }
+        let kdf = KdfParams::generate();
+        assert_eq!(stored_kdf(Some(kdf.clone().into()), false), Ok(kdf));
+    }
+
+    #[test]
+    fn test_client_nonces_are_hedged() {
+        let zkp = client_zkp(params::DEFAULT_PARAM_SET).unwrap();
+        assert_eq!(zkp.nonce_mode, NonceMode::Hedged);
+
+        // With the same RNG output, k still depends on x and the context
+        let x1 = SecretScalar::random(&zkp.q, &mut SharedRng::from_entropy());
+        let x2 = SecretScalar::random(&zkp.q, &mut SharedRng::from_entropy());
+        let (k1, r1, _) = login_commitment(&zkp, "alice", &x1, 1, &mut SharedRng::seeded(7));
+        let (k2, _, _) = login_commitment(&zkp, "alice", &x2, 1, &mut SharedRng::seeded(7));
+        let (k3, _, _) = login_commitment(&zkp, "alice", &x1, 2, &mut SharedRng::seeded(7));
+        assert_ne!(k1.to_biguint(), k2.to_biguint());
+        assert_ne!(k1.to_biguint(), k3.to_biguint());
+        let context = ZKP::fiat_shamir_context("alice", 1);
+        let hedged = crate::nonce::generate_nonce(
+            NonceMode::Hedged,
+            &zkp.q,
+            &x1,
+            &context,
+            &mut SharedRng::seeded(7),
+        );
+        assert_eq!(k1.to_biguint(), hedged.to_biguint());
+        assert_eq!(r1, zkp.commit(&hedged).0);
     }
 
     #[tokio::test]
@@ -239,10 +715,11 @@ mod tests {
         let mut client = AuthClient::connect(endpoint).await.unwrap();
 
         let (zkp, _) = setup_zkp();
+        let mut rng = SharedRng::from_entropy();
         let username = format!("user_{}", Uuid::new_v4());
 
-        register_user(username.clone(), "pass".into(), &zkp, &mut client).await;
-        authenticate_user(username, "pass".into(), &zkp, &mut client).await;
+        register_user(username.clone(), "pass".into(), &zkp, &mut client, &mut rng).await;
+        authenticate_user(username, "pass".into(), false, &zkp, &mut client, &mut rng).await;
 
         // If no panic → success
     }
@@ -253,8 +730,17 @@ mod tests {
         let mut client = AuthClient::connect(endpoint).await.unwrap();
 
         let (zkp, _) = setup_zkp();
-
-        authenticate_user("nonexistent".into(), "pass".into(), &zkp, &mut client).await;
+        let mut rng = SharedRng::from_entropy();
+
+        authenticate_user(
+            "nonexistent".into(),
+            "pass".into(),
+            false,
+            &zkp,
+            &mut client,
+            &mut rng,
+        )
+        .await;
 
         // Should not panic
     }
@@ -265,11 +751,19 @@ mod tests {
         let mut client = AuthClient::connect(endpoint).await.unwrap();
 
         let (zkp, _) = setup_zkp();
+        let mut rng = SharedRng::from_entropy();
         let username = format!("user_{}", Uuid::new_v4());
 
-        register_user(username.clone(), "correct".into(), &zkp, &mut client).await;
+        register_user(
+            username.clone(),
+            "correct".into(),
+            &zkp,
+            &mut client,
+            &mut rng,
+        )
+        .await;
 
-        authenticate_user(username, "wrong".into(), &zkp, &mut client).await;
+        authenticate_user(username, "wrong".into(), false, &zkp, &mut client, &mut rng).await;
 
         // Should fail gracefully (no panic)
     }
@@ -283,4 +777,4 @@ mod tests {
 
         // Should not panic
     }
-}
\ No newline at end of file
+}
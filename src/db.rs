@@ -1,3 +1,4 @@
+use crate::kdf::KdfParams;
 use chrono::{DateTime, Utc};
 use num_bigint::BigUint;
 use sqlx::{Postgres, Transaction};
@@ -7,6 +8,8 @@ pub struct User {
     pub user_name: String,
     pub y1: BigUint,
     pub y2: BigUint,
+    pub param_set: String,
+    pub kdf: KdfParams,
     pub created_at: DateTime<Utc>,
 }
 
@@ -22,9 +25,71 @@ pub struct AuthLog {
 #[derive(Clone)]
 pub struct Session {
     pub user_name: String,
-    pub session_id: String,
+    /// `ids::hash_token` of the session token
+    pub token_hash: String,
     pub auth_id: String,
     pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    /// Sessions created from one login and its refreshes share a family
+    pub family_id: String,
+    pub client_addr: Option<String>,
+    pub user_agent: Option<String>,
+    pub is_active: bool,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub revoke_reason: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct RefreshToken {
+    /// `ids::hash_token` of the refresh token
+    pub token_hash: String,
+    pub family_id: String,
+    pub user_name: String,
+    pub created_at: DateTime<Utc>,
+    /// The absolute deadline of the whole family
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PendingChallenge {
+    pub user_name: String,
+    pub r1: BigUint,
+    pub r2: BigUint,
+    pub c: BigUint,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Why and until when an account is locked; no `locked_until` means until an
+/// admin unlocks it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountLock {
+    pub locked_until: Option<DateTime<Utc>>,
+    pub reason: String,
+}
+
+/// One entry of the `lockout_events` audit trail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockoutEvent {
+    pub user_name: String,
+    pub event: String,
+    pub reason: Option<String>,
+    /// Who caused the event: `policy` or `admin`
+    pub actor: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Failed attempts counted against one rate-limit key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitState {
+    pub attempts: u32,
+    pub last_attempt: DateTime<Utc>,
+    pub blocked_until: Option<DateTime<Utc>>,
+    /// Blocks imposed since the key was last quiet; each one doubles the next
+    pub blocks: u32,
 }
 
 /// INSERT FUNCTIONS ///
@@ -36,10 +101,16 @@ pub async fn insert_user(
         return Err(sqlx::Error::Protocol("username cannot be empty".into()));
     }
     sqlx::query!(
-        "INSERT INTO users (user_name, y1, y2, created_at) VALUES ($1, $2, $3, $4)",
+        "INSERT INTO users (user_name, y1, y2, param_set, kdf_algorithm, kdf_salt, kdf_memory_kib, kdf_iterations, kdf_parallelism, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
         user.user_name,
         user.y1.to_bytes_be(),
         user.y2.to_bytes_be(),
+        user.param_set,
+        user.kdf.algorithm,
+        user.kdf.salt,
+        user.kdf.memory_kib as i32,
+        user.kdf.iterations as i32,
+        user.kdf.parallelism as i32,
         user.created_at.naive_utc()
     )
     .execute(&mut **tx)
@@ -70,23 +141,344 @@ pub async fn insert_session(
     session: Session,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        "INSERT INTO sessions (session_id, user_name, auth_id, created_at, expires_at) VALUES ($1, $2, $3, $4, $5)",
-        session.session_id,
+        "INSERT INTO sessions (token_hash, user_name, auth_id, created_at, expires_at, last_used_at, family_id, client_addr, user_agent, is_active, revoked_at, revoke_reason) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+        session.token_hash,
         session.user_name,
         session.auth_id,
         session.created_at.naive_utc(),
-        session.created_at.naive_utc() + chrono::Duration::hours(1) // Example expiration time
+        session.expires_at.naive_utc(),
+        session.last_used_at.naive_utc(),
+        session.family_id,
+        session.client_addr,
+        session.user_agent,
+        session.is_active,
+        session.revoked_at.map(|t| t.naive_utc()),
+        session.revoke_reason
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+pub async fn insert_refresh_token(
+    tx: &mut Transaction<'_, Postgres>,
+    token: RefreshToken,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO refresh_tokens (token_hash, family_id, user_name, created_at, expires_at, used_at, revoked_at) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        token.token_hash,
+        token.family_id,
+        token.user_name,
+        token.created_at.naive_utc(),
+        token.expires_at.naive_utc(),
+        token.used_at.map(|t| t.naive_utc()),
+        token.revoked_at.map(|t| t.naive_utc())
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+pub async fn insert_pending_challenge(
+    tx: &mut Transaction<'_, Postgres>,
+    auth_id: &str,
+    challenge: &PendingChallenge,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO pending_challenges (auth_id, user_name, r1, r2, c, created_at, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        auth_id,
+        challenge.user_name,
+        challenge.r1.to_bytes_be(),
+        challenge.r2.to_bytes_be(),
+        challenge.c.to_bytes_be(),
+        challenge.created_at.naive_utc(),
+        challenge.expires_at.naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Records a proof id until `expires_at`. An id whose record expired before
+/// `now` is claimed afresh; returns false if the id is still recorded.
+pub async fn insert_used_proof(
+    tx: &mut Transaction<'_, Postgres>,
+    proof_id: &str,
+    expires_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "INSERT INTO used_proofs (proof_id, expires_at) VALUES ($1, $2)
+         ON CONFLICT (proof_id) DO UPDATE SET expires_at = EXCLUDED.expires_at
+         WHERE used_proofs.expires_at <= $3",
+        proof_id,
+        expires_at.naive_utc(),
+        now.naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn insert_lockout_event(
+    tx: &mut Transaction<'_, Postgres>,
+    event: &LockoutEvent,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO lockout_events (user_name, event, reason, actor, created_at) VALUES ($1, $2, $3, $4, $5)",
+        event.user_name,
+        event.event,
+        event.reason,
+        event.actor,
+        event.created_at.naive_utc()
     )
     .execute(&mut **tx)
     .await?;
     Ok(())
 }
 
+/// Stores the rate-limit state of `key`, replacing any earlier state
+pub async fn upsert_rate_limit(
+    tx: &mut Transaction<'_, Postgres>,
+    key: &str,
+    state: &RateLimitState,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO rate_limits (key, attempts, last_attempt, blocked_until, blocks) VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (key) DO UPDATE SET attempts = $2, last_attempt = $3, blocked_until = $4, blocks = $5",
+        key,
+        state.attempts as i32,
+        state.last_attempt.naive_utc(),
+        state.blocked_until.map(|t| t.naive_utc()),
+        state.blocks as i32
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// UPDATE FUNCTIONS ///
+/// Replaces a user's commitments and KDF parameters, but only if they still
+/// match `current`; returns false if another rotation got there first
+pub async fn update_user_credential(
+    tx: &mut Transaction<'_, Postgres>,
+    current: &User,
+    y1: &BigUint,
+    y2: &BigUint,
+    kdf: &KdfParams,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE users SET y1 = $4, y2 = $5, kdf_algorithm = $6, kdf_salt = $7, kdf_memory_kib = $8, kdf_iterations = $9, kdf_parallelism = $10, credential_rotated_at = $11 WHERE user_name = $1 AND y1 = $2 AND y2 = $3",
+        current.user_name,
+        current.y1.to_bytes_be(),
+        current.y2.to_bytes_be(),
+        y1.to_bytes_be(),
+        y2.to_bytes_be(),
+        kdf.algorithm,
+        kdf.salt,
+        kdf.memory_kib as i32,
+        kdf.iterations as i32,
+        kdf.parallelism as i32,
+        Utc::now().naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn touch_session(
+    tx: &mut Transaction<'_, Postgres>,
+    token_hash: &str,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE sessions SET last_used_at = $2 WHERE token_hash = $1",
+        token_hash,
+        now.naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Marks a refresh token as spent; returns false if it was already used
+pub async fn mark_refresh_token_used(
+    tx: &mut Transaction<'_, Postgres>,
+    token_hash: &str,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE refresh_tokens SET used_at = $2 WHERE token_hash = $1 AND used_at IS NULL",
+        token_hash,
+        now.naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Marks an active session revoked; returns false if it was not active
+pub async fn revoke_session(
+    tx: &mut Transaction<'_, Postgres>,
+    token_hash: &str,
+    reason: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE sessions SET is_active = FALSE, revoked_at = $3, revoke_reason = $2 WHERE token_hash = $1 AND is_active",
+        token_hash,
+        reason,
+        Utc::now().naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Revokes a user's active sessions except `keep`, and the refresh tokens of
+/// every family except `keep_family`. Returns how many sessions were revoked.
+pub async fn revoke_sessions_by_user(
+    tx: &mut Transaction<'_, Postgres>,
+    user_name: &str,
+    keep: Option<&str>,
+    keep_family: Option<&str>,
+    reason: &str,
+) -> Result<u64, sqlx::Error> {
+    let now = Utc::now().naive_utc();
+    let result = sqlx::query!(
+        "UPDATE sessions SET is_active = FALSE, revoked_at = $4, revoke_reason = $3 WHERE user_name = $1 AND is_active AND token_hash IS DISTINCT FROM $2",
+        user_name,
+        keep,
+        reason,
+        now
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE refresh_tokens SET revoked_at = $3 WHERE user_name = $1 AND revoked_at IS NULL AND family_id IS DISTINCT FROM $2",
+        user_name,
+        keep_family,
+        now
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Revokes every active session and refresh token of a family
+pub async fn revoke_session_family(
+    tx: &mut Transaction<'_, Postgres>,
+    family_id: &str,
+    reason: &str,
+) -> Result<u64, sqlx::Error> {
+    let now = Utc::now().naive_utc();
+    let result = sqlx::query!(
+        "UPDATE sessions SET is_active = FALSE, revoked_at = $3, revoke_reason = $2 WHERE family_id = $1 AND is_active",
+        family_id,
+        reason,
+        now
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE refresh_tokens SET revoked_at = $2 WHERE family_id = $1 AND revoked_at IS NULL",
+        family_id,
+        now
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Locks an account unless a lock is already in force at `now`; returns false
+/// if the account was already locked or does not exist
+pub async fn lock_user(
+    tx: &mut Transaction<'_, Postgres>,
+    username: &str,
+    lock: &AccountLock,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE users SET locked_until = $2, lock_reason = $3
+         WHERE user_name = $1 AND (lock_reason IS NULL OR locked_until <= $4)",
+        username,
+        lock.locked_until.map(|t| t.naive_utc()),
+        lock.reason,
+        now.naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Clears an account's lock; returns false if it had none
+pub async fn unlock_user(
+    tx: &mut Transaction<'_, Postgres>,
+    username: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE users SET locked_until = NULL, lock_reason = NULL WHERE user_name = $1 AND lock_reason IS NOT NULL",
+        username
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Clears every lock that ran out before `now`; returns the unlocked users
+pub async fn unlock_expired_users(
+    tx: &mut Transaction<'_, Postgres>,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "UPDATE users SET locked_until = NULL, lock_reason = NULL WHERE locked_until <= $1 RETURNING user_name",
+        now.naive_utc()
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.user_name).collect())
+}
+
+/// Moves a user's auth logs to an alias so they survive account deletion
+/// without naming the user. Returns how many rows were moved.
+pub async fn anonymize_auth_logs(
+    tx: &mut Transaction<'_, Postgres>,
+    username: &str,
+    alias: &str,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE auth_logs SET user_name = $2 WHERE user_name = $1",
+        username,
+        alias
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Moves a user's lockout events to `alias`; returns how many were moved
+pub async fn anonymize_lockout_events(
+    tx: &mut Transaction<'_, Postgres>,
+    username: &str,
+    alias: &str,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE lockout_events SET user_name = $2 WHERE user_name = $1",
+        username,
+        alias
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected())
+}
+
 /// DELETE FUNCTIONS ///
 pub async fn delete_user_by_username(
     tx: &mut Transaction<'_, Postgres>,
     username: &str,
 ) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM auth_logs WHERE user_name = $1", username)
+        .execute(&mut **tx)
+        .await?;
     sqlx::query!("DELETE FROM users WHERE user_name = $1", username)
         .execute(&mut **tx)
         .await?;
@@ -98,27 +490,143 @@ pub async fn delete_all_users(tx: &mut Transaction<'_, Postgres>) -> Result<(),
     Ok(())
 }
 
-pub async fn delete_session_by_id(
+/// Deletes sessions and refresh tokens that expired or were revoked before
+/// `cutoff`. Returns how many sessions were purged.
+pub async fn purge_sessions(
+    tx: &mut Transaction<'_, Postgres>,
+    cutoff: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM sessions WHERE COALESCE(revoked_at, expires_at) < $1",
+        cutoff.naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM refresh_tokens WHERE COALESCE(revoked_at, expires_at) < $1",
+        cutoff.naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Deletes a pending challenge and returns it. The row is gone afterwards, so
+/// concurrent callers cannot both receive it.
+pub async fn take_pending_challenge(
     tx: &mut Transaction<'_, Postgres>,
-    session_id: &str,
+    auth_id: &str,
+) -> Result<Option<PendingChallenge>, sqlx::Error> {
+    let row = sqlx::query!(
+        "DELETE FROM pending_challenges WHERE auth_id = $1 RETURNING user_name, r1, r2, c, created_at, expires_at",
+        auth_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    Ok(row.map(|row| PendingChallenge {
+        user_name: row.user_name,
+        r1: BigUint::from_bytes_be(&row.r1),
+        r2: BigUint::from_bytes_be(&row.r2),
+        c: BigUint::from_bytes_be(&row.c),
+        created_at: DateTime::<Utc>::from_naive_utc_and_offset(row.created_at, Utc),
+        expires_at: DateTime::<Utc>::from_naive_utc_and_offset(row.expires_at, Utc),
+    }))
+}
+
+/// Deletes the `count` oldest live challenges of one user
+pub async fn delete_oldest_challenges(
+    tx: &mut Transaction<'_, Postgres>,
+    user_name: &str,
+    count: i64,
+    now: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM pending_challenges WHERE auth_id IN (SELECT auth_id FROM pending_challenges WHERE user_name = $1 AND expires_at > $2 ORDER BY created_at, auth_id LIMIT $3)",
+        user_name,
+        now.naive_utc(),
+        count
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Deletes challenges that expired before `now`
+pub async fn delete_expired_challenges(
+    tx: &mut Transaction<'_, Postgres>,
+    now: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM pending_challenges WHERE expires_at <= $1",
+        now.naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Forgets a recorded proof id
+pub async fn delete_used_proof(
+    tx: &mut Transaction<'_, Postgres>,
+    proof_id: &str,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query!("DELETE FROM sessions WHERE session_id = $1", session_id)
+    sqlx::query!("DELETE FROM used_proofs WHERE proof_id = $1", proof_id)
         .execute(&mut **tx)
         .await?;
     Ok(())
 }
 
-pub async fn delete_expired_sessions(
+/// Deletes proof ids whose records expired before `now`
+pub async fn delete_expired_proofs(
     tx: &mut Transaction<'_, Postgres>,
-) -> Result<(), sqlx::Error> {
-    let now = Utc::now();
-    sqlx::query!(
-        "DELETE FROM sessions WHERE expires_at < $1",
+    now: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM used_proofs WHERE expires_at <= $1",
         now.naive_utc()
     )
     .execute(&mut **tx)
     .await?;
-    Ok(())
+    Ok(result.rows_affected())
+}
+
+/// Forgets the rate-limit state of `key`; returns false if there was none
+pub async fn delete_rate_limit(
+    tx: &mut Transaction<'_, Postgres>,
+    key: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM rate_limits WHERE key = $1", key)
+        .execute(&mut **tx)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Forgets the rate-limit state of every key starting with `prefix`
+pub async fn delete_rate_limits_by_prefix(
+    tx: &mut Transaction<'_, Postgres>,
+    prefix: &str,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM rate_limits WHERE starts_with(key, $1)", prefix)
+        .execute(&mut **tx)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Deletes rate-limit state whose last attempt was before `cutoff` and that
+/// is not blocked at `now`
+pub async fn delete_idle_rate_limits(
+    tx: &mut Transaction<'_, Postgres>,
+    cutoff: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM rate_limits WHERE last_attempt < $1 AND (blocked_until IS NULL OR blocked_until <= $2)",
+        cutoff.naive_utc(),
+        now.naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected())
 }
 
 /// GETTER FUNCTIONS ///
@@ -127,7 +635,7 @@ pub async fn get_user_by_username(
     username: &str,
 ) -> Result<Option<User>, sqlx::Error> {
     let row = sqlx::query!(
-        "SELECT user_name, y1, y2, created_at FROM users WHERE user_name = $1",
+        "SELECT user_name, y1, y2, param_set, kdf_algorithm, kdf_salt, kdf_memory_kib, kdf_iterations, kdf_parallelism, created_at FROM users WHERE user_name = $1",
         username
     )
     .fetch_optional(&mut **tx)
@@ -136,12 +644,20 @@ pub async fn get_user_by_username(
     if let Some(row) = row {
         let y1 = BigUint::from_bytes_be(&row.y1);
         let y2 = BigUint::from_bytes_be(&row.y2);
-        let created_at = DateTime::<Utc>::from_utc(row.created_at, Utc);
+        let created_at = DateTime::<Utc>::from_naive_utc_and_offset(row.created_at, Utc);
         Ok(Some(User {
             user_name: row.user_name,
             y1,
             y2,
-            created_at: created_at,
+            param_set: row.param_set,
+            kdf: KdfParams {
+                algorithm: row.kdf_algorithm,
+                salt: row.kdf_salt,
+                memory_kib: row.kdf_memory_kib as u32,
+                iterations: row.kdf_iterations as u32,
+                parallelism: row.kdf_parallelism as u32,
+            },
+            created_at,
         }))
     } else {
         Ok(None)
@@ -149,9 +665,46 @@ pub async fn get_user_by_username(
 }
 
 pub async fn get_all_users(tx: &mut Transaction<'_, Postgres>) -> Result<Vec<User>, sqlx::Error> {
-    let rows = sqlx::query!("SELECT user_name, y1, y2, created_at FROM users")
-        .fetch_all(&mut **tx)
-        .await?;
+    let rows = sqlx::query!(
+        "SELECT user_name, y1, y2, param_set, kdf_algorithm, kdf_salt, kdf_memory_kib, kdf_iterations, kdf_parallelism, created_at FROM users"
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let users: Vec<User> = rows
+        .into_iter()
+        .map(|row| User {
+            user_name: row.user_name,
+            y1: BigUint::from_bytes_be(&row.y1),
+            y2: BigUint::from_bytes_be(&row.y2),
+            param_set: row.param_set,
+            kdf: KdfParams {
+                algorithm: row.kdf_algorithm,
+                salt: row.kdf_salt,
+                memory_kib: row.kdf_memory_kib as u32,
+                iterations: row.kdf_iterations as u32,
+                parallelism: row.kdf_parallelism as u32,
+            },
+            created_at: DateTime::<Utc>::from_naive_utc_and_offset(row.created_at, Utc),
+        })
+        .collect();
+
+    Ok(users)
+}
+
+/// Up to `limit` users ordered by name, starting after `after`
+pub async fn get_users_page(
+    tx: &mut Transaction<'_, Postgres>,
+    after: &str,
+    limit: i64,
+) -> Result<Vec<User>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT user_name, y1, y2, param_set, kdf_algorithm, kdf_salt, kdf_memory_kib, kdf_iterations, kdf_parallelism, created_at FROM users WHERE user_name > $1 ORDER BY user_name LIMIT $2",
+        after,
+        limit
+    )
+    .fetch_all(&mut **tx)
+    .await?;
 
     let users: Vec<User> = rows
         .into_iter()
@@ -159,7 +712,15 @@ pub async fn get_all_users(tx: &mut Transaction<'_, Postgres>) -> Result<Vec<Use
             user_name: row.user_name,
             y1: BigUint::from_bytes_be(&row.y1),
             y2: BigUint::from_bytes_be(&row.y2),
-            created_at: DateTime::<Utc>::from_utc(row.created_at, Utc),
+            param_set: row.param_set,
+            kdf: KdfParams {
+                algorithm: row.kdf_algorithm,
+                salt: row.kdf_salt,
+                memory_kib: row.kdf_memory_kib as u32,
+                iterations: row.kdf_iterations as u32,
+                parallelism: row.kdf_parallelism as u32,
+            },
+            created_at: DateTime::<Utc>::from_naive_utc_and_offset(row.created_at, Utc),
         })
         .collect();
 
@@ -173,35 +734,245 @@ pub async fn count_users(tx: &mut Transaction<'_, Postgres>) -> Result<i64, sqlx
     Ok(row.count.unwrap_or(0))
 }
 
-pub async fn get_session_by_id(
+/// The lock recorded on an account, whether or not it has run out
+pub async fn get_account_lock(
     tx: &mut Transaction<'_, Postgres>,
-    session_id: &str,
+    username: &str,
+) -> Result<Option<AccountLock>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT locked_until, lock_reason FROM users WHERE user_name = $1",
+        username
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    Ok(row.and_then(|row| {
+        Some(AccountLock {
+            locked_until: row
+                .locked_until
+                .map(|t| DateTime::<Utc>::from_naive_utc_and_offset(t, Utc)),
+            reason: row.lock_reason?,
+        })
+    }))
+}
+
+/// Counts a user's failed attempts after `since` that came after their last
+/// success and their last lockout event
+pub async fn count_recent_failures(
+    tx: &mut Transaction<'_, Postgres>,
+    username: &str,
+    since: DateTime<Utc>,
+) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT COUNT(*) as count FROM auth_logs WHERE user_name = $1 AND NOT success AND created_at > GREATEST(
+            $2,
+            (SELECT MAX(created_at) FROM auth_logs WHERE user_name = $1 AND success),
+            (SELECT MAX(created_at) FROM lockout_events WHERE user_name = $1)
+        )",
+        username,
+        since.naive_utc()
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok(row.count.unwrap_or(0))
+}
+
+pub async fn get_lockout_events_by_user(
+    tx: &mut Transaction<'_, Postgres>,
+    username: &str,
+) -> Result<Vec<LockoutEvent>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT user_name, event, reason, actor, created_at FROM lockout_events WHERE user_name = $1 ORDER BY id",
+        username
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| LockoutEvent {
+            user_name: row.user_name,
+            event: row.event,
+            reason: row.reason,
+            actor: row.actor,
+            created_at: DateTime::<Utc>::from_naive_utc_and_offset(row.created_at, Utc),
+        })
+        .collect())
+}
+
+/// Counts challenges that are still answerable, of one user or of everyone
+pub async fn count_live_challenges(
+    tx: &mut Transaction<'_, Postgres>,
+    user_name: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT COUNT(*) as count FROM pending_challenges WHERE expires_at > $1 AND ($2::TEXT IS NULL OR user_name = $2)",
+        now.naive_utc(),
+        user_name
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok(row.count.unwrap_or(0))
+}
+
+/// Counts every stored challenge, expired or not
+pub async fn count_pending_challenges(
+    tx: &mut Transaction<'_, Postgres>,
+) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!("SELECT COUNT(*) as count FROM pending_challenges")
+        .fetch_one(&mut **tx)
+        .await?;
+    Ok(row.count.unwrap_or(0))
+}
+
+pub async fn get_session_by_token_hash(
+    tx: &mut Transaction<'_, Postgres>,
+    token_hash: &str,
 ) -> Result<Option<Session>, sqlx::Error> {
     let row = sqlx::query!(
-        "SELECT session_id, user_name, auth_id, created_at, expires_at FROM sessions WHERE session_id = $1",
-        session_id
+        "SELECT token_hash, user_name, auth_id, created_at, expires_at, last_used_at, family_id, client_addr, user_agent, is_active, revoked_at, revoke_reason FROM sessions WHERE token_hash = $1",
+        token_hash
     )
     .fetch_optional(&mut **tx)
     .await?;
 
     if let Some(row) = row {
         Ok(Some(Session {
-            session_id: row.session_id,
+            token_hash: row.token_hash,
             user_name: row.user_name,
             auth_id: row.auth_id,
-            created_at: DateTime::<Utc>::from_utc(row.created_at, Utc),
+            created_at: DateTime::<Utc>::from_naive_utc_and_offset(row.created_at, Utc),
+            expires_at: DateTime::<Utc>::from_naive_utc_and_offset(row.expires_at, Utc),
+            last_used_at: DateTime::<Utc>::from_naive_utc_and_offset(row.last_used_at, Utc),
+            family_id: row.family_id,
+            client_addr: row.client_addr,
+            user_agent: row.user_agent,
+            is_active: row.is_active,
+            revoked_at: row
+                .revoked_at
+                .map(|t| DateTime::<Utc>::from_naive_utc_and_offset(t, Utc)),
+            revoke_reason: row.revoke_reason,
         }))
     } else {
         Ok(None)
     }
 }
 
+pub async fn get_sessions_by_user(
+    tx: &mut Transaction<'_, Postgres>,
+    user_name: &str,
+) -> Result<Vec<Session>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT token_hash, user_name, auth_id, created_at, expires_at, last_used_at, family_id, client_addr, user_agent, is_active, revoked_at, revoke_reason FROM sessions WHERE user_name = $1 ORDER BY created_at DESC",
+        user_name
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Session {
+            token_hash: row.token_hash,
+            user_name: row.user_name,
+            auth_id: row.auth_id,
+            created_at: DateTime::<Utc>::from_naive_utc_and_offset(row.created_at, Utc),
+            expires_at: DateTime::<Utc>::from_naive_utc_and_offset(row.expires_at, Utc),
+            last_used_at: DateTime::<Utc>::from_naive_utc_and_offset(row.last_used_at, Utc),
+            family_id: row.family_id,
+            client_addr: row.client_addr,
+            user_agent: row.user_agent,
+            is_active: row.is_active,
+            revoked_at: row
+                .revoked_at
+                .map(|t| DateTime::<Utc>::from_naive_utc_and_offset(t, Utc)),
+            revoke_reason: row.revoke_reason,
+        })
+        .collect())
+}
+
+pub async fn get_rate_limit(
+    tx: &mut Transaction<'_, Postgres>,
+    key: &str,
+) -> Result<Option<RateLimitState>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT attempts, last_attempt, blocked_until, blocks FROM rate_limits WHERE key = $1",
+        key
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    Ok(row.map(|row| RateLimitState {
+        attempts: row.attempts as u32,
+        last_attempt: DateTime::<Utc>::from_naive_utc_and_offset(row.last_attempt, Utc),
+        blocked_until: row
+            .blocked_until
+            .map(|t| DateTime::<Utc>::from_naive_utc_and_offset(t, Utc)),
+        blocks: row.blocks as u32,
+    }))
+}
+
+/// Looks up the rate-limit state of `key` and locks its row until the
+/// transaction ends, first creating an empty row so that concurrent first
+/// failures also wait for each other
+pub async fn get_rate_limit_for_update(
+    tx: &mut Transaction<'_, Postgres>,
+    key: &str,
+    now: DateTime<Utc>,
+) -> Result<RateLimitState, sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO rate_limits (key, last_attempt) VALUES ($1, $2) ON CONFLICT (key) DO NOTHING",
+        key,
+        now.naive_utc()
+    )
+    .execute(&mut **tx)
+    .await?;
+    let row = sqlx::query!(
+        "SELECT attempts, last_attempt, blocked_until, blocks FROM rate_limits WHERE key = $1 FOR UPDATE",
+        key
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok(RateLimitState {
+        attempts: row.attempts as u32,
+        last_attempt: DateTime::<Utc>::from_naive_utc_and_offset(row.last_attempt, Utc),
+        blocked_until: row
+            .blocked_until
+            .map(|t| DateTime::<Utc>::from_naive_utc_and_offset(t, Utc)),
+        blocks: row.blocks as u32,
+    })
+}
+
+/// Looks up a refresh token and locks its row until the transaction ends
+pub async fn get_refresh_token_for_update(
+    tx: &mut Transaction<'_, Postgres>,
+    token_hash: &str,
+) -> Result<Option<RefreshToken>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT token_hash, family_id, user_name, created_at, expires_at, used_at, revoked_at FROM refresh_tokens WHERE token_hash = $1 FOR UPDATE",
+        token_hash
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(row.map(|row| RefreshToken {
+        token_hash: row.token_hash,
+        family_id: row.family_id,
+        user_name: row.user_name,
+        created_at: DateTime::<Utc>::from_naive_utc_and_offset(row.created_at, Utc),
+        expires_at: DateTime::<Utc>::from_naive_utc_and_offset(row.expires_at, Utc),
+        used_at: row
+            .used_at
+            .map(|t| DateTime::<Utc>::from_naive_utc_and_offset(t, Utc)),
+        revoked_at: row
+            .revoked_at
+            .map(|t| DateTime::<Utc>::from_naive_utc_and_offset(t, Utc)),
+    }))
+}
+
 pub async fn get_login_attempts_by_user(
     tx: &mut Transaction<'_, Postgres>,
     username: &str,
 ) -> Result<Vec<AuthLog>, sqlx::Error> {
     let rows = sqlx::query!(
-        "SELECT user_name, auth_id, success, created_at, failure_reason FROM auth_logs WHERE user_name = $1",
+        "SELECT user_name, auth_id, success, created_at, failure_reason FROM auth_logs WHERE user_name = $1 ORDER BY id",
         username
     )
     .fetch_all(&mut **tx)
@@ -213,9 +984,9 @@ pub async fn get_login_attempts_by_user(
             let auth_id = row.auth_id.expect("auth_id should not be null");
             AuthLog {
                 user_name: row.user_name,
-                auth_id: auth_id,
+                auth_id,
                 success: row.success,
-                created_at: DateTime::<Utc>::from_utc(row.created_at, Utc),
+                created_at: DateTime::<Utc>::from_naive_utc_and_offset(row.created_at, Utc),
                 failure_reason: row.failure_reason,
             }
         })
@@ -227,20 +998,20 @@ pub async fn get_login_attempts_by_user(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::params::LEGACY_PARAM_SET;
     use dotenvy::from_filename;
+    use sqlx::postgres::PgPoolOptions;
     use sqlx::PgPool;
     use std::env;
-    use sqlx::postgres::PgPoolOptions;
     use std::time::Duration;
 
     async fn setup_db() -> PgPool {
         from_filename(".env.test").ok();
 
-        let db_url = env::var("DATABASE_URL")
-            .expect("DATABASE_URL must be set");
+        let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
         PgPoolOptions::new()
-            .max_connections(2) 
+            .max_connections(2)
             .acquire_timeout(Duration::from_secs(5))
             .connect(&db_url)
             .await
@@ -256,6 +1027,8 @@ mod tests {
             user_name: username_1.clone(),
             y1: BigUint::from(10u32),
             y2: BigUint::from(20u32),
+            param_set: LEGACY_PARAM_SET.to_string(),
+            kdf: KdfParams::legacy(),
             created_at: Utc::now(),
         };
 
@@ -273,6 +1046,8 @@ mod tests {
             user_name: username_2.clone(),
             y1: BigUint::from(10u32),
             y2: BigUint::from(20u32),
+            param_set: LEGACY_PARAM_SET.to_string(),
+            kdf: KdfParams::legacy(),
             created_at: Utc::now(),
         };
 
@@ -319,19 +1094,29 @@ mod tests {
             user_name: user_name.clone(),
             y1: BigUint::from(10u32),
             y2: BigUint::from(20u32),
+            param_set: LEGACY_PARAM_SET.to_string(),
+            kdf: KdfParams::legacy(),
             created_at: Utc::now(),
         };
         insert_user(&mut tx, user)
             .await
             .expect("failed to insert user");
 
-        let session_id = format!("session_{}", uuid::Uuid::new_v4());
+        let token_hash = format!("session_{}", uuid::Uuid::new_v4());
 
         let session = Session {
-            session_id: session_id.clone(),
+            token_hash: token_hash.clone(),
             user_name: user_name.clone(),
             auth_id: "test_auth".to_string(),
             created_at: Utc::now(),
+            expires_at: Utc::now() + chrono::Duration::hours(1),
+            last_used_at: Utc::now(),
+            family_id: "test_family".to_string(),
+            client_addr: None,
+            user_agent: None,
+            is_active: true,
+            revoked_at: None,
+            revoke_reason: None,
         };
         insert_session(&mut tx, session)
             .await
@@ -348,11 +1133,11 @@ mod tests {
             .await
             .expect("failed to insert auth log");
 
-        let fetched = get_session_by_id(&mut tx, &session_id)
+        let fetched = get_session_by_token_hash(&mut tx, &token_hash)
             .await
             .expect("failed to get session");
         let fetched = fetched.expect("session not found");
-        assert_eq!(fetched.session_id, session_id);
+        assert_eq!(fetched.token_hash, token_hash);
 
         let logs = get_login_attempts_by_user(&mut tx, &user_name)
             .await
@@ -362,14 +1147,20 @@ mod tests {
         assert_eq!(logs[0].auth_id, "test_auth");
         assert!(logs[0].success);
 
-        delete_session_by_id(&mut tx, &session_id)
+        assert!(revoke_session(&mut tx, &token_hash, "logout")
             .await
-            .expect("failed to delete session");
+            .expect("failed to revoke session"));
+        assert!(!revoke_session(&mut tx, &token_hash, "logout")
+            .await
+            .expect("failed to revoke session twice"));
 
-        let fetched = get_session_by_id(&mut tx, &session_id)
+        let fetched = get_session_by_token_hash(&mut tx, &token_hash)
             .await
-            .expect("failed to get session after deletion");
-        assert!(fetched.is_none());
+            .expect("failed to get session after revocation")
+            .expect("revoked session was deleted");
+        assert!(!fetched.is_active);
+        assert!(fetched.revoked_at.is_some());
+        assert_eq!(fetched.revoke_reason.as_deref(), Some("logout"));
         tx.rollback().await.expect("failed to rollback transaction");
     }
 
@@ -382,6 +1173,8 @@ mod tests {
             user_name: username.clone(),
             y1: BigUint::from(10u32),
             y2: BigUint::from(20u32),
+            param_set: LEGACY_PARAM_SET.to_string(),
+            kdf: KdfParams::legacy(),
             created_at: Utc::now(),
         };
 
@@ -404,6 +1197,8 @@ mod tests {
             user_name: "".to_string(),
             y1: BigUint::from(10u32),
             y2: BigUint::from(20u32),
+            param_set: LEGACY_PARAM_SET.to_string(),
+            kdf: KdfParams::legacy(),
             created_at: Utc::now(),
         };
         let result = insert_user(&mut tx, user).await;
@@ -427,10 +1222,18 @@ mod tests {
         let pool = setup_db().await;
         let mut tx = pool.begin().await.expect("failed to begin transaction");
         let session = Session {
-            session_id: format!("orphan_session_{}", uuid::Uuid::new_v4()),
+            token_hash: format!("orphan_session_{}", uuid::Uuid::new_v4()),
             user_name: format!("non_existent_user_{}", uuid::Uuid::new_v4()),
             auth_id: "test_auth".to_string(),
             created_at: Utc::now(),
+            expires_at: Utc::now() + chrono::Duration::hours(1),
+            last_used_at: Utc::now(),
+            family_id: "test_family".to_string(),
+            client_addr: None,
+            user_agent: None,
+            is_active: true,
+            revoked_at: None,
+            revoke_reason: None,
         };
         let result = insert_session(&mut tx, session).await;
         assert!(result.is_err());
@@ -446,18 +1249,28 @@ mod tests {
             user_name: username.clone(),
             y1: BigUint::from(10u32),
             y2: BigUint::from(20u32),
+            param_set: LEGACY_PARAM_SET.to_string(),
+            kdf: KdfParams::legacy(),
             created_at: Utc::now(),
         };
         insert_user(&mut tx, user)
             .await
             .expect("failed to insert user");
 
-        let session_id = format!("cascade_session_{}", uuid::Uuid::new_v4());
+        let token_hash = format!("cascade_session_{}", uuid::Uuid::new_v4());
         let session = Session {
-            session_id: session_id.clone(),
+            token_hash: token_hash.clone(),
             user_name: username.clone(),
             auth_id: "test_auth".to_string(),
             created_at: Utc::now(),
+            expires_at: Utc::now() + chrono::Duration::hours(1),
+            last_used_at: Utc::now(),
+            family_id: "test_family".to_string(),
+            client_addr: None,
+            user_agent: None,
+            is_active: true,
+            revoked_at: None,
+            revoke_reason: None,
         };
         insert_session(&mut tx, session)
             .await
@@ -467,7 +1280,7 @@ mod tests {
             .await
             .expect("failed to delete user");
 
-        let fetched = get_session_by_id(&mut tx, &session_id)
+        let fetched = get_session_by_token_hash(&mut tx, &token_hash)
             .await
             .expect("failed to get session after user deletion");
         assert!(fetched.is_none());
@@ -475,7 +1288,66 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_delete_expired_sessions() {
+    async fn test_anonymize_and_delete_auth_logs() {
+        let pool = setup_db().await;
+        let mut tx = pool.begin().await.expect("failed to begin transaction");
+        let username = format!("logs_user_{}", uuid::Uuid::new_v4());
+        let alias = format!("deleted_{}", uuid::Uuid::new_v4());
+        for other in [&username, &format!("{}_kept", username)] {
+            let user = User {
+                user_name: other.clone(),
+                y1: BigUint::from(10u32),
+                y2: BigUint::from(20u32),
+                param_set: LEGACY_PARAM_SET.to_string(),
+                kdf: KdfParams::legacy(),
+                created_at: Utc::now(),
+            };
+            insert_user(&mut tx, user)
+                .await
+                .expect("failed to insert user");
+            for success in [false, true] {
+                let log = AuthLog {
+                    user_name: other.clone(),
+                    auth_id: "test_auth".to_string(),
+                    success,
+                    created_at: Utc::now(),
+                    failure_reason: None,
+                };
+                insert_login_attempt(&mut tx, log)
+                    .await
+                    .expect("failed to insert auth log");
+            }
+        }
+
+        let moved = anonymize_auth_logs(&mut tx, &username, &alias)
+            .await
+            .expect("failed to anonymize auth logs");
+        assert_eq!(moved, 2);
+        delete_user_by_username(&mut tx, &username)
+            .await
+            .expect("failed to delete user");
+        let kept = get_login_attempts_by_user(&mut tx, &alias)
+            .await
+            .expect("failed to get anonymized logs");
+        assert_eq!(
+            kept.iter().map(|log| log.success).collect::<Vec<_>>(),
+            [false, true]
+        );
+
+        // Without anonymising, deleting the user removes its logs
+        let other = format!("{}_kept", username);
+        delete_user_by_username(&mut tx, &other)
+            .await
+            .expect("failed to delete user");
+        let logs = get_login_attempts_by_user(&mut tx, &other)
+            .await
+            .expect("failed to get logs");
+        assert!(logs.is_empty());
+        tx.rollback().await.expect("failed to rollback transaction");
+    }
+
+    #[tokio::test]
+    async fn test_purge_sessions() {
         let pool = setup_db().await;
         let mut tx = pool.begin().await.expect("failed to begin transaction");
         let username = format!("expire_user_{}", uuid::Uuid::new_v4());
@@ -483,37 +1355,71 @@ mod tests {
             user_name: username.clone(),
             y1: BigUint::from(10u32),
             y2: BigUint::from(20u32),
+            param_set: LEGACY_PARAM_SET.to_string(),
+            kdf: KdfParams::legacy(),
             created_at: Utc::now(),
         };
         insert_user(&mut tx, user)
             .await
             .expect("failed to insert user");
         let expired_session = Session {
-            session_id: format!("expired_session_{}", uuid::Uuid::new_v4()),
+            token_hash: format!("expired_session_{}", uuid::Uuid::new_v4()),
             user_name: username.clone(),
             auth_id: "test_auth".to_string(),
             created_at: Utc::now() - chrono::Duration::hours(2), // Created 2 hours ago
+            expires_at: Utc::now() - chrono::Duration::hours(1),
+            last_used_at: Utc::now(),
+            family_id: "test_family".to_string(),
+            client_addr: None,
+            user_agent: None,
+            is_active: true,
+            revoked_at: None,
+            revoke_reason: None,
         };
         insert_session(&mut tx, expired_session.clone())
             .await
             .expect("failed to insert expired session");
         let valid_session = Session {
-            session_id: format!("valid_session_{}", uuid::Uuid::new_v4()),
+            token_hash: format!("valid_session_{}", uuid::Uuid::new_v4()),
             user_name: username.clone(),
             auth_id: "test_auth".to_string(),
             created_at: Utc::now(), // Created now
+            expires_at: Utc::now() + chrono::Duration::hours(1),
+            last_used_at: Utc::now(),
+            family_id: "test_family".to_string(),
+            client_addr: None,
+            user_agent: None,
+            is_active: true,
+            revoked_at: None,
+            revoke_reason: None,
         };
         insert_session(&mut tx, valid_session.clone())
             .await
             .expect("failed to insert valid session");
-        delete_expired_sessions(&mut tx)
+        let revoked_session = Session {
+            token_hash: format!("revoked_session_{}", uuid::Uuid::new_v4()),
+            is_active: false,
+            revoked_at: Some(Utc::now() - chrono::Duration::hours(2)),
+            revoke_reason: Some("logout".to_string()),
+            ..valid_session.clone()
+        };
+        insert_session(&mut tx, revoked_session.clone())
+            .await
+            .expect("failed to insert revoked session");
+        // Keep rows that ended within the last 30 minutes
+        let purged = purge_sessions(&mut tx, Utc::now() - chrono::Duration::minutes(30))
+            .await
+            .expect("failed to purge sessions");
+        assert_eq!(purged, 2);
+        let fetched_revoked = get_session_by_token_hash(&mut tx, &revoked_session.token_hash)
             .await
-            .expect("failed to delete expired sessions");
-        let fetched_expired = get_session_by_id(&mut tx, &expired_session.session_id)
+            .expect("failed to get revoked session after purge");
+        assert!(fetched_revoked.is_none());
+        let fetched_expired = get_session_by_token_hash(&mut tx, &expired_session.token_hash)
             .await
             .expect("failed to get expired session after deletion");
         assert!(fetched_expired.is_none());
-        let fetched_valid = get_session_by_id(&mut tx, &valid_session.session_id)
+        let fetched_valid = get_session_by_token_hash(&mut tx, &valid_session.token_hash)
             .await
             .expect("failed to get valid session after deletion");
         assert!(fetched_valid.is_some());
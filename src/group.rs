@@ -0,0 +1,584 @@
+use crypto_bigint::modular::runtime_mod::DynResidue;
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT,
+    ristretto::{CompressedRistretto, RistrettoBasepointTable, RistrettoPoint},
+    scalar::Scalar,
+    traits::{Identity, VartimeMultiscalarMul},
+};
+use num_bigint::BigUint;
+use sha2::{Digest, Sha512};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::secret::{self, SecretScalar};
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+/// A prime-order group the Chaum-Pedersen protocol can run over.
+///
+/// Elements are passed around as `BigUint`s holding the group's canonical
+/// encoding, so the server and the database never need to know which group
+/// a value belongs to. Exponents are scalars modulo `order()`.
+pub trait Group: Send + Sync + Debug {
+    /// Short identifier of the group
+    fn name(&self) -> &str;
+
+    /// Order q of the group (and modulus of the scalar field)
+    fn order(&self) -> &BigUint;
+
+    /// Returns the two generators (alpha, beta) of the group
+    fn generators(&self) -> (BigUint, BigUint);
+
+    /// Length in bytes of a fixed-width element encoding
+    fn element_len(&self) -> usize;
+
+    /// Encoding of the identity element
+    fn identity(&self) -> BigUint;
+
+    /// Checks that `e` is the canonical encoding of an element of the
+    /// prime-order group (for mod-p groups: in [1, p-1] and of order dividing q)
+    fn is_valid_element(&self, e: &BigUint) -> bool;
+
+    /// Computes a^x in the group
+    fn exponentiate(&self, a: &BigUint, x: &BigUint) -> BigUint;
+
+    /// Computes a^x for a secret exponent in time independent of its value
+    fn exponentiate_secret(&self, a: &BigUint, x: &SecretScalar) -> BigUint;
+
+    /// Computes the group operation a * b
+    fn multiply(&self, a: &BigUint, b: &BigUint) -> BigUint;
+
+    /// Computes the product of bases[i]^exps[i]. Groups override this with a
+    /// multi-exponentiation that shares work between the terms.
+    fn multi_exponentiate(&self, bases: &[BigUint], exps: &[BigUint]) -> BigUint {
+        bases.iter().zip(exps).fold(self.identity(), |acc, (b, e)| {
+            self.multiply(&acc, &self.exponentiate(b, e))
+        })
+    }
+
+    /// Deterministically hashes a public seed to a generator of the group,
+    /// so that nobody knows its discrete log with respect to any other generator
+    fn derive_generator(&self, seed: &[u8]) -> BigUint;
+
+    /// Precomputes powers of `base`, an element of the order-q group, so that
+    /// repeated exponentiations of it are cheaper
+    fn fixed_base_table(&self, base: &BigUint) -> Arc<dyn FixedBaseTable>;
+}
+
+/// Precomputed powers of one fixed base.
+pub trait FixedBaseTable: Send + Sync + Debug {
+    /// The base the table was built for
+    fn base(&self) -> &BigUint;
+
+    /// Computes base^x
+    fn exponentiate(&self, x: &BigUint) -> BigUint;
+}
+
+/// Window width of `ModPTable`: each 4-bit digit of the exponent is one lookup
+const TABLE_WINDOW: usize = 4;
+
+/// Fixed-base windowed table for a mod-p group: `rows[i][j] = base^(j * 16^i)`,
+/// so base^x is the product of one entry per hex digit of x and needs no squarings.
+#[derive(Clone)]
+pub struct ModPTable {
+    base: BigUint,
+    p: BigUint,
+    q: BigUint,
+    rows: Vec<Vec<BigUint>>,
+}
+
+impl Debug for ModPTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModPTable")
+            .field("base", &self.base)
+            .field("rows", &self.rows.len())
+            .finish()
+    }
+}
+
+impl FixedBaseTable for ModPTable {
+    fn base(&self) -> &BigUint {
+        &self.base
+    }
+
+    fn exponentiate(&self, x: &BigUint) -> BigUint {
+        // The base has order q, so reducing x keeps it within the table
+        let digits = (x % &self.q).to_radix_le(1 << TABLE_WINDOW);
+        let mut acc = BigUint::from(1u32);
+        for (row, &d) in self.rows.iter().zip(&digits) {
+            if d != 0 {
+                acc = (acc * &row[d as usize]) % &self.p;
+            }
+        }
+        acc
+    }
+}
+
+/// Wraps curve25519-dalek's basepoint table for an arbitrary Ristretto point.
+#[derive(Clone)]
+pub struct RistrettoTable {
+    base: BigUint,
+    table: RistrettoBasepointTable,
+    order: BigUint,
+}
+
+impl Debug for RistrettoTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RistrettoTable")
+            .field("base", &self.base)
+            .finish()
+    }
+}
+
+impl FixedBaseTable for RistrettoTable {
+    fn base(&self) -> &BigUint {
+        &self.base
+    }
+
+    fn exponentiate(&self, x: &BigUint) -> BigUint {
+        let scalar = Ristretto255::scalar_mod(x, &self.order);
+        Ristretto255::encode(&(&self.table * &scalar))
+    }
+}
+
+/// Expands `seed` into `len` pseudo-random bytes:
+/// SHA-512(seed || counter || block) for block = 0, 1, ... (both big-endian u32),
+/// concatenated and truncated.
+pub fn expand_seed(seed: &[u8], counter: u32, len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len + 64);
+    let mut block = 0u32;
+    while out.len() < len {
+        let mut hasher = Sha512::new();
+        hasher.update(seed);
+        hasher.update(counter.to_be_bytes());
+        hasher.update(block.to_be_bytes());
+        out.extend_from_slice(&hasher.finalize());
+        block += 1;
+    }
+    out.truncate(len);
+    out
+}
+
+/// The order-q subgroup of Z_p^* with the classic modular arithmetic.
+#[derive(Debug, Clone)]
+pub struct ModPGroup {
+    pub name: String,   // Name of the parameter set
+    pub p: BigUint,     // A large prime number
+    pub q: BigUint,     // A large prime divisor of (p-1)
+    pub alpha: BigUint, // A generator of the subgroup of order q
+    pub beta: BigUint,  // Another generator of the subgroup of order q
+}
+
+impl ModPGroup {
+    pub fn new(p: BigUint, q: BigUint, alpha: BigUint, beta: BigUint) -> Self {
+        Self::named("modp", p, q, alpha, beta)
+    }
+
+    /// Builds the group for a named parameter set
+    pub fn named(name: &str, p: BigUint, q: BigUint, alpha: BigUint, beta: BigUint) -> Self {
+        ModPGroup {
+            name: name.to_string(),
+            p,
+            q,
+            alpha,
+            beta,
+        }
+    }
+
+    /// Builds the group for a named parameter set, deriving beta from `seed`
+    pub fn with_derived_beta(
+        name: &str,
+        p: BigUint,
+        q: BigUint,
+        alpha: BigUint,
+        seed: &[u8],
+    ) -> Self {
+        let mut group = Self::named(name, p, q, alpha, BigUint::from(0u32));
+        group.beta = group.derive_generator(seed);
+        group
+    }
+}
+
+impl Group for ModPGroup {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn order(&self) -> &BigUint {
+        &self.q
+    }
+
+    fn generators(&self) -> (BigUint, BigUint) {
+        (self.alpha.clone(), self.beta.clone())
+    }
+
+    fn element_len(&self) -> usize {
+        self.p.bits().div_ceil(8) as usize
+    }
+
+    fn identity(&self) -> BigUint {
+        BigUint::from(1u32)
+    }
+
+    fn is_valid_element(&self, e: &BigUint) -> bool {
+        *e > BigUint::from(0u32) && *e < self.p && e.modpow(&self.q, &self.p) == self.identity()
+    }
+
+    fn exponentiate(&self, a: &BigUint, x: &BigUint) -> BigUint {
+        a.modpow(x, &self.p)
+    }
+
+    // Fixed-window exponentiation in Montgomery form over every bit position of q
+    fn exponentiate_secret(&self, a: &BigUint, x: &SecretScalar) -> BigUint {
+        let base = DynResidue::new(&secret::to_uint(a), secret::residue_params(&self.p));
+        let result = base.pow_bounded_exp(x.as_uint(), self.q.bits() as usize);
+        secret::from_uint(&result.retrieve())
+    }
+
+    fn multiply(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a * b) % &self.p
+    }
+
+    // Straus' interleaved method with 4-bit windows: one chain of squarings
+    // is shared by all terms, each term adds one multiplication per window.
+    fn multi_exponentiate(&self, bases: &[BigUint], exps: &[BigUint]) -> BigUint {
+        const WINDOW: usize = 4;
+        let one = self.identity();
+        let tables: Vec<Vec<BigUint>> = bases
+            .iter()
+            .map(|b| {
+                let mut table = vec![one.clone()];
+                for i in 1..1 << WINDOW {
+                    let next = self.multiply(&table[i - 1], b);
+                    table.push(next);
+                }
+                table
+            })
+            .collect();
+        let digits: Vec<Vec<u8>> = exps.iter().map(|e| e.to_radix_le(1 << WINDOW)).collect();
+        let windows = digits.iter().map(Vec::len).max().unwrap_or(0);
+
+        let mut acc = one;
+        for i in (0..windows).rev() {
+            for _ in 0..WINDOW {
+                acc = self.multiply(&acc, &acc);
+            }
+            for (table, digits) in tables.iter().zip(&digits) {
+                match digits.get(i) {
+                    Some(&d) if d != 0 => acc = self.multiply(&acc, &table[d as usize]),
+                    _ => {}
+                }
+            }
+        }
+        acc
+    }
+
+    // For counter = 0, 1, ...: take element_len + 16 bytes of `expand_seed`
+    // as an integer v, reduce it mod p and raise it to (p-1)/q. The first
+    // result other than 1 is an element of order q.
+    fn derive_generator(&self, seed: &[u8]) -> BigUint {
+        let one = BigUint::from(1u32);
+        let cofactor = (&self.p - &one) / &self.q;
+        let mut counter = 0u32;
+        loop {
+            let bytes = expand_seed(seed, counter, self.element_len() + 16);
+            let v = BigUint::from_bytes_be(&bytes) % &self.p;
+            let g = v.modpow(&cofactor, &self.p);
+            if g > one {
+                return g;
+            }
+            counter += 1;
+        }
+    }
+
+    fn fixed_base_table(&self, base: &BigUint) -> Arc<dyn FixedBaseTable> {
+        let windows = (self.q.bits() as usize).div_ceil(TABLE_WINDOW);
+        let mut rows = Vec::with_capacity(windows);
+        let mut row_base = base.clone();
+        for _ in 0..windows {
+            let mut row = vec![self.identity()];
+            for j in 1..1 << TABLE_WINDOW {
+                let next = self.multiply(&row[j - 1], &row_base);
+                row.push(next);
+            }
+            // base^(16^(i+1)) = row[15] * base^(16^i)
+            row_base = self.multiply(&row[(1 << TABLE_WINDOW) - 1], &row_base);
+            rows.push(row);
+        }
+        Arc::new(ModPTable {
+            base: base.clone(),
+            p: self.p.clone(),
+            q: self.q.clone(),
+            rows,
+        })
+    }
+}
+
+/// The Ristretto255 prime-order group built on Curve25519.
+///
+/// Elements are the 32-byte compressed Ristretto encodings read as big-endian
+/// integers. `alpha` is the standard basepoint and `beta` is hashed to the
+/// group from a public label, so nobody knows log_alpha(beta).
+#[derive(Debug, Clone)]
+pub struct Ristretto255 {
+    order: BigUint,
+    beta: RistrettoPoint,
+}
+
+impl Ristretto255 {
+    /// Public seed beta is hashed from
+    pub const BETA_SEED: &'static [u8] = b"chaum-pederson-rust/ristretto255/beta";
+
+    pub fn new() -> Self {
+        // l = 2^252 + 27742317777372353535851937790883648493
+        let order = (BigUint::from(1u32) << 252usize)
+            + BigUint::parse_bytes(b"27742317777372353535851937790883648493", 10).unwrap();
+        let beta = RistrettoPoint::hash_from_bytes::<Sha512>(Self::BETA_SEED);
+        Ristretto255 { order, beta }
+    }
+
+    fn encode(point: &RistrettoPoint) -> BigUint {
+        BigUint::from_bytes_be(point.compress().as_bytes())
+    }
+
+    fn decode(e: &BigUint) -> Option<RistrettoPoint> {
+        let bytes = e.to_bytes_be();
+        if bytes.len() > 32 {
+            return None;
+        }
+        let mut buf = [0u8; 32];
+        buf[32 - bytes.len()..].copy_from_slice(&bytes);
+        CompressedRistretto(buf).decompress()
+    }
+
+    // Invalid encodings are mapped to the identity; callers check
+    // `is_valid_element` on untrusted input first.
+    fn point(e: &BigUint) -> RistrettoPoint {
+        Self::decode(e).unwrap_or_else(RistrettoPoint::identity)
+    }
+
+    fn scalar(&self, x: &BigUint) -> Scalar {
+        Self::scalar_mod(x, &self.order)
+    }
+
+    fn scalar_mod(x: &BigUint, order: &BigUint) -> Scalar {
+        let mut bytes = (x % order).to_bytes_le();
+        bytes.resize(32, 0);
+        let mut buf = [0u8; 32];
+        buf.copy_from_slice(&bytes);
+        Scalar::from_bytes_mod_order(buf)
+    }
+}
+
+impl Default for Ristretto255 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Group for Ristretto255 {
+    fn name(&self) -> &str {
+        "ristretto255"
+    }
+
+    fn order(&self) -> &BigUint {
+        &self.order
+    }
+
+    fn generators(&self) -> (BigUint, BigUint) {
+        (
+            Self::encode(&RISTRETTO_BASEPOINT_POINT),
+            Self::encode(&self.beta),
+        )
+    }
+
+    fn element_len(&self) -> usize {
+        32
+    }
+
+    fn identity(&self) -> BigUint {
+        Self::encode(&RistrettoPoint::identity())
+    }
+
+    fn is_valid_element(&self, e: &BigUint) -> bool {
+        Self::decode(e).is_some()
+    }
+
+    fn exponentiate(&self, a: &BigUint, x: &BigUint) -> BigUint {
+        Self::encode(&(Self::point(a) * self.scalar(x)))
+    }
+
+    // x is already reduced below l < 2^253, so its low 32 bytes hold all of it
+    fn exponentiate_secret(&self, a: &BigUint, x: &SecretScalar) -> BigUint {
+        let mut bytes = Zeroizing::new([0u8; 32]);
+        bytes.copy_from_slice(&x.to_le_bytes()[..32]);
+        let mut scalar = Scalar::from_bytes_mod_order(*bytes);
+        let result = Self::encode(&(Self::point(a) * scalar));
+        scalar.zeroize();
+        result
+    }
+
+    fn multiply(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        Self::encode(&(Self::point(a) + Self::point(b)))
+    }
+
+    // Variable time is fine here: it is only used to verify public values
+    fn multi_exponentiate(&self, bases: &[BigUint], exps: &[BigUint]) -> BigUint {
+        let points: Vec<RistrettoPoint> = bases.iter().map(Self::point).collect();
+        let scalars: Vec<Scalar> = exps.iter().map(|e| self.scalar(e)).collect();
+        Self::encode(&RistrettoPoint::vartime_multiscalar_mul(scalars, points))
+    }
+
+    // The Elligator-based hash-to-group map from the Ristretto spec
+    fn derive_generator(&self, seed: &[u8]) -> BigUint {
+        Self::encode(&RistrettoPoint::hash_from_bytes::<Sha512>(seed))
+    }
+
+    fn fixed_base_table(&self, base: &BigUint) -> Arc<dyn FixedBaseTable> {
+        Arc::new(RistrettoTable {
+            base: base.clone(),
+            table: RistrettoBasepointTable::create(&Self::point(base)),
+            order: self.order.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ristretto_exponent_laws() {
+        let group = Ristretto255::new();
+        let (alpha, beta) = group.generators();
+        let a = BigUint::from(12345u32);
+        let b = BigUint::from(6789u32);
+
+        let lhs = group.multiply(
+            &group.exponentiate(&alpha, &a),
+            &group.exponentiate(&alpha, &b),
+        );
+        let rhs = group.exponentiate(&alpha, &(&a + &b));
+        assert_eq!(lhs, rhs);
+
+        // x and x + q give the same element
+        let wrapped = group.exponentiate(&beta, &(&a + group.order()));
+        assert_eq!(wrapped, group.exponentiate(&beta, &a));
+        assert_ne!(alpha, beta);
+    }
+
+    #[test]
+    fn test_ristretto_rejects_invalid_encoding() {
+        let group = Ristretto255::new();
+        let (alpha, _) = group.generators();
+        assert!(group.is_valid_element(&alpha));
+        assert!(!group.is_valid_element(&(BigUint::from(1u32) << 256usize)));
+        assert!(!group.is_valid_element(&BigUint::from_bytes_be(&[0xffu8; 32])));
+    }
+
+    #[test]
+    fn test_modp_element_range() {
+        let group = ModPGroup::new(
+            BigUint::from(23u32),
+            BigUint::from(11u32),
+            BigUint::from(4u32),
+            BigUint::from(6u32),
+        );
+        assert!(group.is_valid_element(&BigUint::from(4u32)));
+        assert!(!group.is_valid_element(&BigUint::from(0u32)));
+        assert!(!group.is_valid_element(&BigUint::from(23u32)));
+        // 5 is a non-residue mod 23, so it lies outside the order-11 subgroup
+        assert!(!group.is_valid_element(&BigUint::from(5u32)));
+        assert!(!group.is_valid_element(&BigUint::from(22u32)));
+        assert_eq!(group.element_len(), 1);
+    }
+
+    #[test]
+    fn test_modp_derived_generator_has_order_q() {
+        let group = ModPGroup::with_derived_beta(
+            "toy",
+            BigUint::from(23u32),
+            BigUint::from(11u32),
+            BigUint::from(4u32),
+            b"seed",
+        );
+        let one = BigUint::from(1u32);
+        assert_ne!(group.beta, one);
+        assert_eq!(group.exponentiate(&group.beta, &group.q), one);
+        assert_eq!(group.derive_generator(b"seed"), group.beta);
+    }
+
+    #[test]
+    fn test_ristretto_beta_is_derived_from_seed() {
+        let group = Ristretto255::new();
+        let (_, beta) = group.generators();
+        assert_eq!(group.derive_generator(Ristretto255::BETA_SEED), beta);
+        assert_ne!(group.derive_generator(b"other seed"), beta);
+    }
+
+    #[test]
+    fn test_multi_exponentiate_matches_product() {
+        let modp = ModPGroup::new(
+            BigUint::from(23u32),
+            BigUint::from(11u32),
+            BigUint::from(4u32),
+            BigUint::from(6u32),
+        );
+        let groups: [&dyn Group; 2] = [&modp, &Ristretto255::new()];
+        for group in groups {
+            let (alpha, beta) = group.generators();
+            let bases = [
+                alpha.clone(),
+                beta.clone(),
+                group.exponentiate(&alpha, &BigUint::from(3u32)),
+            ];
+            let exps = [
+                BigUint::from(0u32),
+                BigUint::from(0x1234_5678u32),
+                BigUint::from(1u32) << 130usize,
+            ];
+            let expected = bases
+                .iter()
+                .zip(&exps)
+                .fold(group.identity(), |acc, (b, e)| {
+                    group.multiply(&acc, &group.exponentiate(b, e))
+                });
+            assert_eq!(
+                group.multi_exponentiate(&bases, &exps),
+                expected,
+                "{}",
+                group.name()
+            );
+            assert_eq!(group.multi_exponentiate(&[], &[]), group.identity());
+        }
+    }
+
+    #[test]
+    fn test_fixed_base_table_matches_exponentiate() {
+        let modp = ModPGroup::new(
+            BigUint::from(23u32),
+            BigUint::from(11u32),
+            BigUint::from(4u32),
+            BigUint::from(6u32),
+        );
+        let groups: [&dyn Group; 2] = [&modp, &Ristretto255::new()];
+        for group in groups {
+            let (alpha, beta) = group.generators();
+            for base in [alpha, beta] {
+                let table = group.fixed_base_table(&base);
+                assert_eq!(table.base(), &base);
+                for x in [0u32, 1, 10, 11, 12, 0xdead_beef] {
+                    let x = BigUint::from(x);
+                    assert_eq!(table.exponentiate(&x), group.exponentiate(&base, &x));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_expand_seed_lengths() {
+        let long = expand_seed(b"seed", 0, 300);
+        assert_eq!(long.len(), 300);
+        assert_eq!(&long[..64], &expand_seed(b"seed", 0, 64)[..]);
+        assert_ne!(expand_seed(b"seed", 0, 64), expand_seed(b"seed", 1, 64));
+    }
+}
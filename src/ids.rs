@@ -0,0 +1,113 @@
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Random bytes in every identifier (128 bits)
+pub const ID_BYTES: usize = 16;
+/// Bytes of SHA-256 appended as a checksum
+pub const CHECKSUM_BYTES: usize = 4;
+
+/// What an identifier names; each kind has its own prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdKind {
+    /// A session token handed to the client after login
+    Session,
+    /// A pending authentication challenge (`auth_id`)
+    Challenge,
+    /// A single-use refresh token
+    Refresh,
+    /// A login and all sessions refreshed from it
+    Family,
+}
+
+impl IdKind {
+    pub fn prefix(self) -> &'static str {
+        match self {
+            IdKind::Session => "ses_",
+            IdKind::Challenge => "chl_",
+            IdKind::Refresh => "ref_",
+            IdKind::Family => "fam_",
+        }
+    }
+}
+
+fn checksum(prefix: &str, body: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(prefix.as_bytes())
+        .chain_update(body.as_bytes())
+        .finalize();
+    hex::encode(&digest[..CHECKSUM_BYTES])
+}
+
+/// Generates `<prefix><32 hex chars of randomness><8 hex chars of checksum>`
+pub fn generate_id<R: CryptoRng + RngCore>(kind: IdKind, rng: &mut R) -> String {
+    let mut bytes = [0u8; ID_BYTES];
+    rng.fill_bytes(&mut bytes);
+    let body = hex::encode(bytes);
+    format!(
+        "{}{}{}",
+        kind.prefix(),
+        body,
+        checksum(kind.prefix(), &body)
+    )
+}
+
+/// Checks the prefix, length and checksum of an identifier, so that typos and
+/// guessed values are rejected before any lookup
+pub fn check_id(kind: IdKind, id: &str) -> bool {
+    let Some(rest) = id.strip_prefix(kind.prefix()) else {
+        return false;
+    };
+    if rest.len() != 2 * (ID_BYTES + CHECKSUM_BYTES) || !rest.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        return false;
+    }
+    let (body, sum) = rest.split_at(2 * ID_BYTES);
+    checksum(kind.prefix(), body) == sum
+}
+
+/// Hex SHA-256 of a token; the database stores this instead of the token
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// A short form of the token hash that is safe to log
+pub fn fingerprint(token: &str) -> String {
+    hash_token(token)[..16].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SharedRng;
+
+    #[test]
+    fn test_generate_and_check() {
+        let mut rng = SharedRng::from_entropy();
+        let id = generate_id(IdKind::Session, &mut rng);
+        assert!(id.starts_with("ses_"));
+        assert_eq!(id.len(), 4 + 40);
+        assert!(check_id(IdKind::Session, &id));
+        assert!(!check_id(IdKind::Challenge, &id));
+        assert_ne!(id, generate_id(IdKind::Session, &mut rng));
+
+        // Flipping one character breaks the checksum
+        let mut chars: Vec<char> = id.chars().collect();
+        chars[10] = if chars[10] == '0' { '1' } else { '0' };
+        assert!(!check_id(
+            IdKind::Session,
+            &chars.into_iter().collect::<String>()
+        ));
+        assert!(!check_id(IdKind::Session, "ses_short"));
+        assert!(!check_id(IdKind::Session, "abcdefghijkl"));
+    }
+
+    #[test]
+    fn test_hash_token() {
+        // SHA-256("abc")
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(fingerprint("abc"), "ba7816bf8f01cfea");
+    }
+}
@@ -0,0 +1,274 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use num_bigint::BigUint;
+use rand::{CryptoRng, RngCore};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::{zkp_auth, SecretScalar};
+
+/// Argon2id password hashing
+pub const KDF_ARGON2ID: &str = "argon2id";
+/// Raw password bytes used directly as the secret (users registered before the KDF existed)
+pub const KDF_NONE: &str = "none";
+
+/// Default cost: the OWASP recommendation for Argon2id (19 MiB, 2 passes, 1 lane)
+pub const DEFAULT_MEMORY_KIB: u32 = 19 * 1024;
+pub const DEFAULT_ITERATIONS: u32 = 2;
+pub const DEFAULT_PARALLELISM: u32 = 1;
+
+/// Bounds accepted by both sides, so neither a weak registration nor a
+/// hostile server can pick unreasonable costs
+pub const MIN_MEMORY_KIB: u32 = 8 * 1024;
+pub const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+pub const MAX_ITERATIONS: u32 = 10;
+pub const MAX_PARALLELISM: u32 = 16;
+pub const SALT_LEN: usize = 16;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum KdfError {
+    #[error("unknown KDF algorithm {0}")]
+    UnknownAlgorithm(String),
+    #[error("invalid KDF parameters: {0}")]
+    InvalidParams(String),
+    #[error("KDF parameters are required for a new credential")]
+    Missing,
+    #[error("the none KDF is only accepted for existing credentials")]
+    LegacyNotAllowed,
+}
+
+/// Salt and cost parameters of the password-to-secret derivation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfParams {
+    pub algorithm: String,
+    pub salt: Vec<u8>,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Fresh Argon2id parameters with a random salt and the default cost
+    pub fn generate() -> Self {
+        Self::generate_with(&mut rand::thread_rng())
+    }
+
+    /// `generate` drawing the salt from the given RNG
+    pub fn generate_with<R: CryptoRng + RngCore>(rng: &mut R) -> Self {
+        let mut salt = vec![0u8; SALT_LEN];
+        rng.fill_bytes(&mut salt);
+        KdfParams {
+            algorithm: KDF_ARGON2ID.to_string(),
+            salt,
+            memory_kib: DEFAULT_MEMORY_KIB,
+            iterations: DEFAULT_ITERATIONS,
+            parallelism: DEFAULT_PARALLELISM,
+        }
+    }
+
+    /// Parameters for users whose secret is the raw password
+    pub fn legacy() -> Self {
+        KdfParams {
+            algorithm: KDF_NONE.to_string(),
+            salt: Vec::new(),
+            memory_kib: 0,
+            iterations: 0,
+            parallelism: 0,
+        }
+    }
+
+    /// Checks the algorithm is known and the salt and costs are within bounds
+    pub fn validate(&self) -> Result<(), KdfError> {
+        match self.algorithm.as_str() {
+            KDF_NONE => Ok(()),
+            KDF_ARGON2ID => {
+                if self.salt.len() < SALT_LEN || self.salt.len() > 64 {
+                    return Err(KdfError::InvalidParams(format!(
+                        "salt must be {} to 64 bytes",
+                        SALT_LEN
+                    )));
+                }
+                if !(MIN_MEMORY_KIB..=MAX_MEMORY_KIB).contains(&self.memory_kib) {
+                    return Err(KdfError::InvalidParams(format!(
+                        "memory must be between {} and {} KiB",
+                        MIN_MEMORY_KIB, MAX_MEMORY_KIB
+                    )));
+                }
+                if !(1..=MAX_ITERATIONS).contains(&self.iterations) {
+                    return Err(KdfError::InvalidParams(format!(
+                        "iterations must be between 1 and {}",
+                        MAX_ITERATIONS
+                    )));
+                }
+                if !(1..=MAX_PARALLELISM).contains(&self.parallelism) {
+                    return Err(KdfError::InvalidParams(format!(
+                        "parallelism must be between 1 and {}",
+                        MAX_PARALLELISM
+                    )));
+                }
+                Ok(())
+            }
+            other => Err(KdfError::UnknownAlgorithm(other.to_string())),
+        }
+    }
+
+    /// Checks parameters offered with a new credential. Besides `validate`,
+    /// this refuses missing parameters and the raw-password scheme, which only
+    /// rows that already use it may keep.
+    pub fn validate_new(params: Option<zkp_auth::KdfParams>) -> Result<Self, KdfError> {
+        let params = KdfParams::from(params.ok_or(KdfError::Missing)?);
+        if params.algorithm == KDF_NONE {
+            return Err(KdfError::LegacyNotAllowed);
+        }
+        params.validate()?;
+        Ok(params)
+    }
+
+    /// Derives the secret exponent x from a password.
+    ///
+    /// Argon2id output is 16 bytes longer than q so the reduction mod q is
+    /// close to uniform.
+    pub fn derive_secret(&self, password: &str, q: &BigUint) -> Result<BigUint, KdfError> {
+        if self.algorithm == KDF_NONE {
+            self.validate()?;
+            return Ok(BigUint::from_bytes_be(password.as_bytes()));
+        }
+        Ok(BigUint::from_bytes_be(&self.hash(password, q)?) % q)
+    }
+
+    /// Like `derive_secret`, but reduces into a `SecretScalar` in constant time
+    /// and wipes the intermediate hash output
+    pub fn derive_secret_scalar(
+        &self,
+        password: &str,
+        q: &BigUint,
+    ) -> Result<SecretScalar, KdfError> {
+        if self.algorithm == KDF_NONE {
+            self.validate()?;
+            return Ok(SecretScalar::from_bytes_be(password.as_bytes(), q));
+        }
+        Ok(SecretScalar::from_bytes_be(&self.hash(password, q)?, q))
+    }
+
+    // Argon2id output 16 bytes longer than q
+    fn hash(&self, password: &str, q: &BigUint) -> Result<Zeroizing<Vec<u8>>, KdfError> {
+        self.validate()?;
+        let out_len = (q.bits() as usize).div_ceil(8) + 16;
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(out_len),
+        )
+        .map_err(|e| KdfError::InvalidParams(e.to_string()))?;
+        let mut out = Zeroizing::new(vec![0u8; out_len]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &self.salt, &mut out)
+            .map_err(|e| KdfError::InvalidParams(e.to_string()))?;
+        Ok(out)
+    }
+}
+
+impl From<KdfParams> for zkp_auth::KdfParams {
+    fn from(params: KdfParams) -> Self {
+        zkp_auth::KdfParams {
+            algorithm: params.algorithm,
+            salt: params.salt,
+            memory_kib: params.memory_kib,
+            iterations: params.iterations,
+            parallelism: params.parallelism,
+        }
+    }
+}
+
+impl From<zkp_auth::KdfParams> for KdfParams {
+    fn from(params: zkp_auth::KdfParams) -> Self {
+        KdfParams {
+            algorithm: params.algorithm,
+            salt: params.salt,
+            memory_kib: params.memory_kib,
+            iterations: params.iterations,
+            parallelism: params.parallelism,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_params(salt: &[u8]) -> KdfParams {
+        KdfParams {
+            salt: salt.to_vec(),
+            memory_kib: MIN_MEMORY_KIB,
+            iterations: 1,
+            ..KdfParams::generate()
+        }
+    }
+
+    #[test]
+    fn test_derive_secret_is_deterministic_and_salted() {
+        let q = BigUint::from_bytes_be(
+            &hex::decode("F518AA8781A8DF278ABA4E7D64B7CB9D49462353").unwrap(),
+        );
+        let params = test_params(b"0123456789abcdef");
+
+        let x1 = params.derive_secret("pass", &q).unwrap();
+        let x2 = params.derive_secret("pass", &q).unwrap();
+        assert_eq!(x1, x2);
+        assert!(x1 < q);
+        // Short passwords no longer give tiny exponents
+        assert!(x1.bits() > 128);
+
+        let scalar = params.derive_secret_scalar("pass", &q).unwrap();
+        assert_eq!(scalar.to_biguint(), x1);
+
+        let other_salt = test_params(b"fedcba9876543210");
+        assert_ne!(other_salt.derive_secret("pass", &q).unwrap(), x1);
+        assert_ne!(params.derive_secret("pasS", &q).unwrap(), x1);
+    }
+
+    #[test]
+    fn test_legacy_secret_is_raw_password() {
+        let q = BigUint::from(1000u32);
+        let x = KdfParams::legacy().derive_secret("ab", &q).unwrap();
+        assert_eq!(x, BigUint::from_bytes_be(b"ab"));
+    }
+
+    #[test]
+    fn test_validate_new_refuses_legacy() {
+        assert_eq!(KdfParams::validate_new(None), Err(KdfError::Missing));
+        assert_eq!(
+            KdfParams::validate_new(Some(KdfParams::legacy().into())),
+            Err(KdfError::LegacyNotAllowed)
+        );
+        let params = KdfParams::generate();
+        assert_eq!(
+            KdfParams::validate_new(Some(params.clone().into())),
+            Ok(params)
+        );
+    }
+
+    #[test]
+    fn test_validate_bounds() {
+        assert!(KdfParams::generate().validate().is_ok());
+        assert!(test_params(b"short").validate().is_err());
+        let cheap = KdfParams {
+            memory_kib: 64,
+            ..KdfParams::generate()
+        };
+        assert!(cheap.validate().is_err());
+        let huge = KdfParams {
+            memory_kib: MAX_MEMORY_KIB + 1,
+            ..KdfParams::generate()
+        };
+        assert!(huge.validate().is_err());
+        let unknown = KdfParams {
+            algorithm: "md5".into(),
+            ..KdfParams::generate()
+        };
+        assert_eq!(
+            unknown.validate(),
+            Err(KdfError::UnknownAlgorithm("md5".into()))
+        );
+    }
+}
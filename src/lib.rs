@@ -1,32 +1,154 @@
 use num_bigint::{BigUint, RandBigInt};
-use rand::{self, Rng};
+use rand::{self, CryptoRng, Rng, RngCore};
+use std::sync::Arc;
+use thiserror::Error;
+pub mod admin;
+pub mod batch;
+pub mod challenge;
+pub mod client;
 pub mod db;
+pub mod group;
+pub mod ids;
+pub mod kdf;
+pub mod lockout;
+pub mod nonce;
+pub mod params;
+pub mod proof;
+pub mod rate_limit;
+pub mod rng;
+pub mod secret;
 pub mod server;
-pub mod client;
+pub mod session;
 pub mod test_utils;
+pub mod token;
 pub mod zkp_auth {
     include!("./zkp_auth.rs");
 }
+pub use group::{FixedBaseTable, Group, ModPGroup, Ristretto255};
+pub use nonce::NonceMode;
+pub use rng::SharedRng;
+pub use secret::SecretScalar;
+
+/// Reasons a value received from the other party is rejected.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("value is not an element of the order-q group")]
+    NotInGroup,
+    #[error("value is the identity element")]
+    Identity,
+    #[error("scalar is not below the group order")]
+    ScalarOutOfRange,
+}
+
+/// Bit length of the random weights in `ZKP::verify_batch`; a batch containing
+/// an invalid proof passes with probability at most 2^-BATCH_WEIGHT_BITS
+pub const BATCH_WEIGHT_BITS: u64 = 128;
+
+/// The values of one proof checked by `ZKP::verify_batch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchItem {
+    pub r1: BigUint,
+    pub r2: BigUint,
+    pub y1: BigUint,
+    pub y2: BigUint,
+    pub c: BigUint,
+    pub s: BigUint,
+}
+
 /// Represents the Zero-Knowledge Proof (ZKP) constants and operations.
+#[derive(Debug, Clone)]
 pub struct ZKP {
-    pub p: BigUint,     // A large prime number
-    pub q: BigUint,     // A large prime divisor of (p-1)
-    pub alpha: BigUint, // A generator of the subgroup of order q
-    pub beta: BigUint,  // Another generator of the subgroup of order q
+    pub group: Arc<dyn Group>, // The prime-order group the protocol runs over
+    pub q: BigUint,            // The order of the group
+    pub alpha: BigUint,        // A generator of the group
+    pub beta: BigUint,         // Another generator of the group
+    pub nonce_mode: NonceMode, // How `generate_nonce` picks k
+    fixed_bases: Vec<Arc<dyn FixedBaseTable>>, // Precomputed tables, empty unless requested
 }
 
 impl ZKP {
-    /// Computes a^x mod p
+    /// Builds a ZKP instance over the given group
+    pub fn new<G: Group + 'static>(group: G) -> Self {
+        let q = group.order().clone();
+        let (alpha, beta) = group.generators();
+        ZKP {
+            group: Arc::new(group),
+            q,
+            alpha,
+            beta,
+            nonce_mode: NonceMode::Random,
+            fixed_bases: Vec::new(),
+        }
+    }
+
+    /// Precomputes fixed-base tables for alpha and beta, which `exponentiate`
+    /// and `verify` then use. Worth it when the instance is reused many times.
+    pub fn with_fixed_base_tables(mut self) -> Self {
+        self.fixed_bases = vec![
+            self.group.fixed_base_table(&self.alpha),
+            self.group.fixed_base_table(&self.beta),
+        ];
+        self
+    }
+
+    /// Sets how `generate_nonce` picks k
+    pub fn with_nonce_mode(mut self, nonce_mode: NonceMode) -> Self {
+        self.nonce_mode = nonce_mode;
+        self
+    }
+
+    /// Picks the nonce k for proving knowledge of x in `context` according to `nonce_mode`
+    pub fn generate_nonce<R: CryptoRng + RngCore>(
+        &self,
+        x: &SecretScalar,
+        context: &[u8],
+        rng: &mut R,
+    ) -> SecretScalar {
+        nonce::generate_nonce(self.nonce_mode, &self.q, x, context, rng)
+    }
+
+    /// Whether `with_fixed_base_tables` was applied
+    pub fn has_fixed_base_tables(&self) -> bool {
+        !self.fixed_bases.is_empty()
+    }
+
+    /// Returns the name of the parameter set the group was built from
+    pub fn name(&self) -> &str {
+        self.group.name()
+    }
+
+    /// Computes a^x in the group, using a precomputed table when `a` has one
     pub fn exponentiate(&self, a: &BigUint, x: &BigUint) -> BigUint {
-        a.modpow(x, &self.p)
+        match self.fixed_bases.iter().find(|table| table.base() == a) {
+            Some(table) => table.exponentiate(x),
+            None => self.group.exponentiate(a, x),
+        }
+    }
+
+    /// Computes a^x for a secret exponent in constant time. Fixed-base tables
+    /// are not used here since their lookups depend on the exponent.
+    pub fn exponentiate_secret(&self, a: &BigUint, x: &SecretScalar) -> BigUint {
+        self.group.exponentiate_secret(a, x)
+    }
+
+    /// Computes the commitment (r1, r2) = (alpha^k, beta^k) in constant time
+    pub fn commit(&self, k: &SecretScalar) -> (BigUint, BigUint) {
+        (
+            self.exponentiate_secret(&self.alpha, k),
+            self.exponentiate_secret(&self.beta, k),
+        )
     }
 
     /// Solves for s in the equation k = cx + s mod q
     pub fn solve(&self, k: &BigUint, c: &BigUint, x: &BigUint) -> BigUint {
-        if *k >= c * x {
-            return (k - c * x).modpow(&BigUint::from(1u32), &self.q);
-        }
-        &self.q - (c * x - k).modpow(&BigUint::from(1u32), &self.q)
+        let k = SecretScalar::from_biguint(k, &self.q);
+        let x = SecretScalar::from_biguint(x, &self.q);
+        self.solve_secret(&k, c, &x)
+    }
+
+    /// Constant-time `solve` on fixed-width secrets
+    pub fn solve_secret(&self, k: &SecretScalar, c: &BigUint, x: &SecretScalar) -> BigUint {
+        secret::solve(&self.q, k, c, x)
     }
 
     /// Verifies the ZKP proof by checking the consistency of the provided values
@@ -39,16 +161,257 @@ impl ZKP {
         c: &BigUint,
         s: &BigUint,
     ) -> bool {
-        let cond1 = *r1
-            == (&self.alpha.modpow(s, &self.p) * y1.modpow(c, &self.p))
-                .modpow(&BigUint::from(1u32), &self.p);
-        let cond2 = *r2
-            == (&self.beta.modpow(s, &self.p) * y2.modpow(c, &self.p))
-                .modpow(&BigUint::from(1u32), &self.p);
+        if ![r1, r2, y1, y2]
+            .iter()
+            .all(|e| self.group.is_valid_element(e))
+        {
+            return false;
+        }
+        let g = &self.group;
+        let cond1 = *r1 == g.multiply(&self.exponentiate(&self.alpha, s), &g.exponentiate(y1, c));
+        let cond2 = *r2 == g.multiply(&self.exponentiate(&self.beta, s), &g.exponentiate(y2, c));
         cond1 && cond2
     }
 
-    /// Returns predefined constants used for the ZKP
+    /// Verifies many proofs at once, returning one result per item.
+    ///
+    /// Each item gets a random weight w_i and both equations are checked for
+    /// the whole batch with two multi-exponentiations:
+    /// prod r1_i^w_i == alpha^(sum w_i s_i) * prod y1_i^(w_i c_i), likewise for beta.
+    /// When the combined check fails the batch is split in halves until the
+    /// failing items are found.
+    ///
+    /// The weighted check is only sound inside the prime-order group, so every
+    /// element must already have passed `validate_element`.
+    pub fn verify_batch(&self, items: &[BatchItem]) -> Vec<bool> {
+        self.verify_batch_with_rng(items, &mut rand::thread_rng())
+    }
+
+    /// `verify_batch` drawing the weights from the given RNG
+    pub fn verify_batch_with_rng<R: CryptoRng + RngCore>(
+        &self,
+        items: &[BatchItem],
+        rng: &mut R,
+    ) -> Vec<bool> {
+        let mut results = vec![false; items.len()];
+        self.verify_batch_into(items, &mut results, rng);
+        results
+    }
+
+    fn verify_batch_into<R: CryptoRng + RngCore>(
+        &self,
+        items: &[BatchItem],
+        results: &mut [bool],
+        rng: &mut R,
+    ) {
+        match items {
+            [] => {}
+            [item] => {
+                results[0] = self.verify(&item.r1, &item.r2, &item.y1, &item.y2, &item.c, &item.s)
+            }
+            _ if self.batch_holds(items, rng) => results.fill(true),
+            _ => {
+                let mid = items.len() / 2;
+                let (left, right) = results.split_at_mut(mid);
+                self.verify_batch_into(&items[..mid], left, rng);
+                self.verify_batch_into(&items[mid..], right, rng);
+            }
+        }
+    }
+
+    fn batch_holds<R: CryptoRng + RngCore>(&self, items: &[BatchItem], rng: &mut R) -> bool {
+        let n = items.len();
+        let mut weights = Vec::with_capacity(n);
+        let (mut r1s, mut r2s) = (Vec::with_capacity(n), Vec::with_capacity(n));
+        let (mut y1s, mut y2s) = (Vec::with_capacity(n), Vec::with_capacity(n));
+        let (mut s_sum, mut exps) = (BigUint::from(0u32), Vec::with_capacity(n));
+        for item in items {
+            let w = rng.gen_biguint(BATCH_WEIGHT_BITS);
+            s_sum = (s_sum + &w * &item.s) % &self.q;
+            exps.push((&w * &item.c) % &self.q);
+            weights.push(w);
+            r1s.push(item.r1.clone());
+            r2s.push(item.r2.clone());
+            y1s.push(item.y1.clone());
+            y2s.push(item.y2.clone());
+        }
+        let g = &self.group;
+        let rhs1 = g.multiply(
+            &self.exponentiate(&self.alpha, &s_sum),
+            &g.multi_exponentiate(&y1s, &exps),
+        );
+        let rhs2 = g.multiply(
+            &self.exponentiate(&self.beta, &s_sum),
+            &g.multi_exponentiate(&y2s, &exps),
+        );
+        g.multi_exponentiate(&r1s, &weights) == rhs1 && g.multi_exponentiate(&r2s, &weights) == rhs2
+    }
+
+    /// Encodes an element as fixed-width big-endian bytes of `element_len()`
+    pub fn encode_element(&self, e: &BigUint) -> Vec<u8> {
+        let bytes = e.to_bytes_be();
+        let len = self.group.element_len().max(bytes.len());
+        let mut out = vec![0u8; len - bytes.len()];
+        out.extend_from_slice(&bytes);
+        out
+    }
+
+    /// Length in bytes of a fixed-width scalar encoding
+    pub fn scalar_len(&self) -> usize {
+        (self.q.bits() as usize).div_ceil(8)
+    }
+
+    /// Binds a non-interactive proof to a user and a point in time
+    pub fn fiat_shamir_context(user_name: &str, timestamp_ms: u64) -> Vec<u8> {
+        let mut context = Vec::with_capacity(user_name.len() + 16);
+        context.extend_from_slice(&(user_name.len() as u64).to_be_bytes());
+        context.extend_from_slice(user_name.as_bytes());
+        context.extend_from_slice(&timestamp_ms.to_be_bytes());
+        context
+    }
+
+    /// Binds a credential rotation proof to the user, a point in time and the
+    /// replacement commitments and KDF parameters, so that it cannot be used
+    /// as a login proof or to install other values
+    pub fn rotation_context(
+        &self,
+        user_name: &str,
+        timestamp_ms: u64,
+        new_y1: &BigUint,
+        new_y2: &BigUint,
+        new_kdf: &kdf::KdfParams,
+    ) -> Vec<u8> {
+        let mut context = Vec::new();
+        let mut append = |bytes: &[u8]| {
+            context.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+            context.extend_from_slice(bytes);
+        };
+        append(b"chaum-pederson-rust/rotate-credential/v1");
+        append(&Self::fiat_shamir_context(user_name, timestamp_ms));
+        append(&self.encode_element(new_y1));
+        append(&self.encode_element(new_y2));
+        append(new_kdf.algorithm.as_bytes());
+        append(&new_kdf.salt);
+        for cost in [new_kdf.memory_kib, new_kdf.iterations, new_kdf.parallelism] {
+            append(&cost.to_be_bytes());
+        }
+        context
+    }
+
+    /// Binds an account deletion proof to the user, a point in time and the
+    /// choice of keeping anonymised auth logs
+    pub fn deletion_context(user_name: &str, timestamp_ms: u64, anonymize_logs: bool) -> Vec<u8> {
+        let mut context = Vec::new();
+        let mut append = |bytes: &[u8]| {
+            context.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+            context.extend_from_slice(bytes);
+        };
+        append(b"chaum-pederson-rust/delete-account/v1");
+        append(&Self::fiat_shamir_context(user_name, timestamp_ms));
+        append(&[anonymize_logs as u8]);
+        context
+    }
+
+    /// Derives the Fiat-Shamir challenge c from the full transcript.
+    ///
+    /// Hashes a domain tag, the parameter set name, both generators, y1, y2,
+    /// r1, r2 (fixed-width) and the caller's context, each length-prefixed,
+    /// then reduces 16 extra bytes of output mod q.
+    pub fn fiat_shamir_challenge(
+        &self,
+        y1: &BigUint,
+        y2: &BigUint,
+        r1: &BigUint,
+        r2: &BigUint,
+        context: &[u8],
+    ) -> BigUint {
+        let mut transcript = Vec::new();
+        let mut append = |bytes: &[u8]| {
+            transcript.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+            transcript.extend_from_slice(bytes);
+        };
+        append(b"chaum-pederson-rust/fiat-shamir/v1");
+        append(self.name().as_bytes());
+        for e in [&self.alpha, &self.beta, y1, y2, r1, r2] {
+            append(&self.encode_element(e));
+        }
+        append(context);
+
+        let len = self.scalar_len() + 16;
+        BigUint::from_bytes_be(&group::expand_seed(&transcript, 0, len)) % &self.q
+    }
+
+    /// Produces a non-interactive proof (r1, r2, s) of knowledge of x
+    pub fn prove_non_interactive(
+        &self,
+        x: &BigUint,
+        k: &BigUint,
+        context: &[u8],
+    ) -> (BigUint, BigUint, BigUint) {
+        let x = SecretScalar::from_biguint(x, &self.q);
+        let k = SecretScalar::from_biguint(k, &self.q);
+        self.prove_non_interactive_secret(&x, &k, context)
+    }
+
+    /// Produces a non-interactive proof with a nonce picked by `generate_nonce`
+    pub fn prove_non_interactive_with_rng<R: CryptoRng + RngCore>(
+        &self,
+        x: &SecretScalar,
+        context: &[u8],
+        rng: &mut R,
+    ) -> (BigUint, BigUint, BigUint) {
+        let k = self.generate_nonce(x, context, rng);
+        self.prove_non_interactive_secret(x, &k, context)
+    }
+
+    /// Constant-time `prove_non_interactive` on fixed-width secrets
+    pub fn prove_non_interactive_secret(
+        &self,
+        x: &SecretScalar,
+        k: &SecretScalar,
+        context: &[u8],
+    ) -> (BigUint, BigUint, BigUint) {
+        let (y1, y2) = self.commit(x);
+        let (r1, r2) = self.commit(k);
+        let c = self.fiat_shamir_challenge(&y1, &y2, &r1, &r2, context);
+        let s = self.solve_secret(k, &c, x);
+        (r1, r2, s)
+    }
+
+    /// Verifies a non-interactive proof against the stored y1, y2
+    pub fn verify_non_interactive(
+        &self,
+        r1: &BigUint,
+        r2: &BigUint,
+        y1: &BigUint,
+        y2: &BigUint,
+        s: &BigUint,
+        context: &[u8],
+    ) -> bool {
+        let c = self.fiat_shamir_challenge(y1, y2, r1, r2, context);
+        self.verify(r1, r2, y1, y2, &c, s)
+    }
+
+    /// Checks that a received element lies in the prime-order group and is not the identity
+    pub fn validate_element(&self, e: &BigUint) -> Result<(), ValidationError> {
+        if !self.group.is_valid_element(e) {
+            return Err(ValidationError::NotInGroup);
+        }
+        if *e == self.group.identity() {
+            return Err(ValidationError::Identity);
+        }
+        Ok(())
+    }
+
+    /// Checks that a received scalar is reduced modulo q
+    pub fn validate_scalar(&self, s: &BigUint) -> Result<(), ValidationError> {
+        if *s >= self.q {
+            return Err(ValidationError::ScalarOutOfRange);
+        }
+        Ok(())
+    }
+
+    /// Returns the constants of the legacy 1024-bit group (see `params::LEGACY_PARAM_SET`)
     pub fn get_constants() -> (BigUint, BigUint, BigUint, BigUint) {
         let p = BigUint::from_bytes_be(&hex::decode("B10B8F96A080E01DDE92DE5EAE5D54EC52C99FBCFB06A3C69A6A9DCA52D23B616073E28675A23D189838EF1E2EE652C013ECB4AEA906112324975C3CD49B83BFACCBDD7D90C4BD7098488E9C219A73724EFFD6FAE5644738FAA31A4FF55BCCC0A151AF5F0DC8B4BD45BF37DF365C1A65E68CFDA76D4DA708DF1FB2BC2E4A4371").unwrap());
         let q = BigUint::from_bytes_be(
@@ -62,16 +425,24 @@ impl ZKP {
 
     /// Generates a random alphanumeric string of the specified size
     pub fn generate_random_string(size: usize) -> String {
-        rand::thread_rng()
-            .sample_iter(rand::distributions::Alphanumeric)
+        Self::random_string(size, &mut rand::thread_rng())
+    }
+
+    /// Generates a random BigUint below the given bound
+    pub fn generate_random_below(bound: &BigUint) -> BigUint {
+        Self::random_below(bound, &mut rand::thread_rng())
+    }
+
+    /// `generate_random_string` drawing from the given RNG
+    pub fn random_string<R: CryptoRng + RngCore>(size: usize, rng: &mut R) -> String {
+        rng.sample_iter(rand::distributions::Alphanumeric)
             .take(size)
             .map(char::from)
             .collect()
     }
 
-    /// Generates a random BigUint below the given bound
-    pub fn generate_random_below(bound: &BigUint) -> BigUint {
-        let mut rng = rand::thread_rng();
+    /// `generate_random_below` drawing from the given RNG
+    pub fn random_below<R: CryptoRng + RngCore>(bound: &BigUint, rng: &mut R) -> BigUint {
         rng.gen_biguint_below(bound)
     }
 }
@@ -90,12 +461,7 @@ mod test {
         let x = BigUint::from(6u32);
         let k = BigUint::from(7u32);
         let c = BigUint::from(4u32);
-        let zkp = ZKP {
-            p,
-            q,
-            alpha: alpha.clone(),
-            beta: beta.clone(),
-        };
+        let zkp = ZKP::new(ModPGroup::new(p, q, alpha.clone(), beta.clone()));
 
         let y1 = zkp.exponentiate(&alpha, &x);
         let y2 = zkp.exponentiate(&beta, &x);
@@ -114,12 +480,7 @@ mod test {
         let beta = BigUint::from(6u32);
         let p = BigUint::from(23u32);
         let q = BigUint::from(11u32);
-        let zkp = ZKP {
-            p,
-            q,
-            alpha: alpha.clone(),
-            beta: beta.clone(),
-        };
+        let zkp = ZKP::new(ModPGroup::new(p, q, alpha.clone(), beta.clone()));
         let x = BigUint::from(6u32);
         let k = ZKP::generate_random_below(&zkp.q);
         let c = ZKP::generate_random_below(&zkp.q);
@@ -133,4 +494,209 @@ mod test {
 
         assert!(zkp.verify(&r1, &r2, &y1, &y2, &c, &s))
     }
+
+    /// Runs the full protocol over Ristretto255
+    #[test]
+    fn test_ristretto_example() {
+        let zkp = ZKP::new(Ristretto255::new());
+        let x = ZKP::generate_random_below(&zkp.q);
+        let k = ZKP::generate_random_below(&zkp.q);
+        let c = ZKP::generate_random_below(&zkp.q);
+
+        let y1 = zkp.exponentiate(&zkp.alpha, &x);
+        let y2 = zkp.exponentiate(&zkp.beta, &x);
+        let r1 = zkp.exponentiate(&zkp.alpha, &k);
+        let r2 = zkp.exponentiate(&zkp.beta, &k);
+
+        let s = zkp.solve(&k, &c, &x);
+        assert!(zkp.verify(&r1, &r2, &y1, &y2, &c, &s));
+
+        let wrong = zkp.solve(&k, &c, &(&x + 1u32));
+        assert!(!zkp.verify(&r1, &r2, &y1, &y2, &c, &wrong));
+    }
+
+    /// Tests that trivial, out-of-range and wrong-subgroup values are rejected
+    #[test]
+    fn test_validation() {
+        let zkp = ZKP::new(ModPGroup::new(
+            BigUint::from(23u32),
+            BigUint::from(11u32),
+            BigUint::from(4u32),
+            BigUint::from(6u32),
+        ));
+        assert_eq!(zkp.validate_element(&BigUint::from(4u32)), Ok(()));
+        assert_eq!(
+            zkp.validate_element(&BigUint::from(0u32)),
+            Err(ValidationError::NotInGroup)
+        );
+        assert_eq!(
+            zkp.validate_element(&BigUint::from(1u32)),
+            Err(ValidationError::Identity)
+        );
+        assert_eq!(
+            zkp.validate_element(&BigUint::from(23u32)),
+            Err(ValidationError::NotInGroup)
+        );
+        // p - 1 has order 2
+        assert_eq!(
+            zkp.validate_element(&BigUint::from(22u32)),
+            Err(ValidationError::NotInGroup)
+        );
+        assert_eq!(zkp.validate_scalar(&BigUint::from(10u32)), Ok(()));
+        assert_eq!(
+            zkp.validate_scalar(&BigUint::from(11u32)),
+            Err(ValidationError::ScalarOutOfRange)
+        );
+
+        let zkp = ZKP::new(Ristretto255::new());
+        assert_eq!(
+            zkp.validate_element(&BigUint::from(0u32)),
+            Err(ValidationError::Identity)
+        );
+        assert_eq!(zkp.validate_element(&zkp.beta), Ok(()));
+    }
+
+    fn batch_item(zkp: &ZKP, valid: bool) -> BatchItem {
+        let x = ZKP::generate_random_below(&zkp.q);
+        let k = ZKP::generate_random_below(&zkp.q);
+        let c = ZKP::generate_random_below(&zkp.q);
+        let s = zkp.solve(&k, &c, &x);
+        BatchItem {
+            r1: zkp.exponentiate(&zkp.alpha, &k),
+            r2: zkp.exponentiate(&zkp.beta, &k),
+            y1: zkp.exponentiate(&zkp.alpha, &x),
+            y2: zkp.exponentiate(&zkp.beta, &x),
+            s: if valid { s } else { (s + 1u32) % &zkp.q },
+            c,
+        }
+    }
+
+    /// Tests that the constant-time prover path matches the variable-time one
+    #[test]
+    fn test_secret_path_matches() {
+        for zkp in [ZKP::new(Ristretto255::new()), params::default_param_set()] {
+            let mut rng = rand::thread_rng();
+            let x = SecretScalar::random(&zkp.q, &mut rng);
+            let k = SecretScalar::random(&zkp.q, &mut rng);
+            let c = ZKP::generate_random_below(&zkp.q);
+            let (r1, r2) = zkp.commit(&k);
+            assert_eq!(r1, zkp.exponentiate(&zkp.alpha, &k.to_biguint()));
+            assert_eq!(r2, zkp.exponentiate(&zkp.beta, &k.to_biguint()));
+
+            let (y1, y2) = zkp.commit(&x);
+            let s = zkp.solve_secret(&k, &c, &x);
+            assert_eq!(s, zkp.solve(&k.to_biguint(), &c, &x.to_biguint()));
+            assert!(zkp.verify(&r1, &r2, &y1, &y2, &c, &s));
+        }
+    }
+
+    /// Tests that deterministic nonces give reproducible, valid proofs
+    #[test]
+    fn test_deterministic_nonce_proof() {
+        let zkp = ZKP::new(Ristretto255::new()).with_nonce_mode(NonceMode::Deterministic);
+        let mut rng = rand::thread_rng();
+        let x = SecretScalar::random(&zkp.q, &mut rng);
+        let (y1, y2) = zkp.commit(&x);
+        let context = ZKP::fiat_shamir_context("alice", 1_700_000_000_000);
+
+        let proof = zkp.prove_non_interactive_with_rng(&x, &context, &mut rng);
+        assert_eq!(
+            proof,
+            zkp.prove_non_interactive_with_rng(&x, &context, &mut rng)
+        );
+        let (r1, r2, s) = proof;
+        assert!(zkp.verify_non_interactive(&r1, &r2, &y1, &y2, &s, &context));
+
+        let hedged = zkp.clone().with_nonce_mode(NonceMode::Hedged);
+        let (r1, r2, s) = hedged.prove_non_interactive_with_rng(&x, &context, &mut rng);
+        assert!(hedged.verify_non_interactive(&r1, &r2, &y1, &y2, &s, &context));
+    }
+
+    /// Tests that precomputed tables give the same results as plain exponentiation
+    #[test]
+    fn test_fixed_base_tables() {
+        for zkp in [ZKP::new(Ristretto255::new()), params::default_param_set()] {
+            let fast = zkp.clone().with_fixed_base_tables();
+            assert!(fast.has_fixed_base_tables() && !zkp.has_fixed_base_tables());
+            let x = ZKP::generate_random_below(&zkp.q);
+            let k = ZKP::generate_random_below(&zkp.q);
+            let c = ZKP::generate_random_below(&zkp.q);
+            for base in [&zkp.alpha, &zkp.beta] {
+                assert_eq!(fast.exponentiate(base, &x), zkp.exponentiate(base, &x));
+            }
+
+            let y1 = fast.exponentiate(&fast.alpha, &x);
+            let y2 = fast.exponentiate(&fast.beta, &x);
+            let r1 = fast.exponentiate(&fast.alpha, &k);
+            let r2 = fast.exponentiate(&fast.beta, &k);
+            let s = fast.solve(&k, &c, &x);
+            assert!(fast.verify(&r1, &r2, &y1, &y2, &c, &s));
+            assert!(zkp.verify(&r1, &r2, &y1, &y2, &c, &s));
+        }
+    }
+
+    /// Tests that batch verification agrees with checking each proof on its own
+    #[test]
+    fn test_verify_batch() {
+        for zkp in [ZKP::new(Ristretto255::new()), params::default_param_set()] {
+            let mut items: Vec<BatchItem> = (0..8).map(|_| batch_item(&zkp, true)).collect();
+            assert_eq!(zkp.verify_batch(&items), vec![true; 8]);
+
+            items[2] = batch_item(&zkp, false);
+            items[7] = batch_item(&zkp, false);
+            let expected: Vec<bool> = (0..8).map(|i| i != 2 && i != 7).collect();
+            assert_eq!(zkp.verify_batch(&items), expected);
+            assert!(zkp.verify_batch(&[]).is_empty());
+        }
+    }
+
+    /// Tests that a Fiat-Shamir proof only verifies in its own context
+    #[test]
+    fn test_non_interactive_proof() {
+        let zkp = ZKP::new(Ristretto255::new());
+        let x = ZKP::generate_random_below(&zkp.q);
+        let k = ZKP::generate_random_below(&zkp.q);
+        let y1 = zkp.exponentiate(&zkp.alpha, &x);
+        let y2 = zkp.exponentiate(&zkp.beta, &x);
+
+        let context = ZKP::fiat_shamir_context("alice", 1_700_000_000_000);
+        let (r1, r2, s) = zkp.prove_non_interactive(&x, &k, &context);
+        assert!(zkp.verify_non_interactive(&r1, &r2, &y1, &y2, &s, &context));
+
+        let other_user = ZKP::fiat_shamir_context("bob", 1_700_000_000_000);
+        assert!(!zkp.verify_non_interactive(&r1, &r2, &y1, &y2, &s, &other_user));
+        let other_time = ZKP::fiat_shamir_context("alice", 1_700_000_000_001);
+        assert!(!zkp.verify_non_interactive(&r1, &r2, &y1, &y2, &s, &other_time));
+    }
+
+    /// Tests that a rotation proof is tied to the new commitments and KDF
+    #[test]
+    fn test_rotation_context_binds_new_credential() {
+        let zkp = ZKP::new(Ristretto255::new());
+        let x = ZKP::generate_random_below(&zkp.q);
+        let k = ZKP::generate_random_below(&zkp.q);
+        let y1 = zkp.exponentiate(&zkp.alpha, &x);
+        let y2 = zkp.exponentiate(&zkp.beta, &x);
+        let new_x = ZKP::generate_random_below(&zkp.q);
+        let new_y1 = zkp.exponentiate(&zkp.alpha, &new_x);
+        let new_y2 = zkp.exponentiate(&zkp.beta, &new_x);
+        let new_kdf = kdf::KdfParams::generate();
+
+        let context = zkp.rotation_context("alice", 1_700_000_000_000, &new_y1, &new_y2, &new_kdf);
+        let (r1, r2, s) = zkp.prove_non_interactive(&x, &k, &context);
+        assert!(zkp.verify_non_interactive(&r1, &r2, &y1, &y2, &s, &context));
+
+        let login = ZKP::fiat_shamir_context("alice", 1_700_000_000_000);
+        assert!(!zkp.verify_non_interactive(&r1, &r2, &y1, &y2, &s, &login));
+        let swapped = zkp.rotation_context("alice", 1_700_000_000_000, &y1, &y2, &new_kdf);
+        assert!(!zkp.verify_non_interactive(&r1, &r2, &y1, &y2, &s, &swapped));
+        let other_kdf = zkp.rotation_context(
+            "alice",
+            1_700_000_000_000,
+            &new_y1,
+            &new_y2,
+            &kdf::KdfParams::legacy(),
+        );
+        assert!(!zkp.verify_non_interactive(&r1, &r2, &y1, &y2, &s, &other_kdf));
+    }
 }
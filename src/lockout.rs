@@ -0,0 +1,319 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{Postgres, Transaction};
+use std::env;
+
+use crate::db::{self, AccountLock, LockoutEvent};
+
+/// Default number of failed proofs in the window that locks an account. Off:
+/// failures are not tied to an address, so anyone who can reach the server
+/// could otherwise lock any account, while rate limiting already slows guessing
+pub const DEFAULT_LOCKOUT_THRESHOLD: u32 = 0;
+/// Default window in which failed proofs are counted
+pub const DEFAULT_LOCKOUT_WINDOW_SECS: i64 = 900;
+/// Default length of a lock
+pub const DEFAULT_LOCKOUT_SECS: i64 = 3600;
+/// Reason stored with locks imposed by the failure policy
+pub const TOO_MANY_FAILURES: &str = "too_many_failures";
+
+/// What happened to an account's lock; stored in `lockout_events.event`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockoutEventKind {
+    /// The account was locked
+    Locked,
+    /// An admin unlocked the account
+    Unlocked,
+    /// The lock ran out
+    Expired,
+}
+
+impl LockoutEventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LockoutEventKind::Locked => "locked",
+            LockoutEventKind::Unlocked => "unlocked",
+            LockoutEventKind::Expired => "expired",
+        }
+    }
+}
+
+/// How many failed proofs lock an account, and for how long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    /// Failed proofs within `window` that lock the account; zero disables lockout
+    pub threshold: u32,
+    pub window: Duration,
+    /// How long a lock lasts; `None` keeps it until an admin unlocks it
+    pub duration: Option<Duration>,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        LockoutPolicy {
+            threshold: DEFAULT_LOCKOUT_THRESHOLD,
+            window: Duration::seconds(DEFAULT_LOCKOUT_WINDOW_SECS),
+            duration: Some(Duration::seconds(DEFAULT_LOCKOUT_SECS)),
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+impl LockoutPolicy {
+    /// Whether failures lock accounts at all
+    pub fn is_enabled(&self) -> bool {
+        self.threshold > 0
+    }
+
+    /// Reads `ZKP_LOCKOUT_THRESHOLD`, `ZKP_LOCKOUT_WINDOW_SECS` and
+    /// `ZKP_LOCKOUT_SECS` (0 locks until an admin unlocks), falling back to
+    /// the defaults
+    pub fn from_env() -> Self {
+        let secs = env_or("ZKP_LOCKOUT_SECS", DEFAULT_LOCKOUT_SECS);
+        LockoutPolicy {
+            threshold: env_or("ZKP_LOCKOUT_THRESHOLD", DEFAULT_LOCKOUT_THRESHOLD),
+            window: Duration::seconds(env_or(
+                "ZKP_LOCKOUT_WINDOW_SECS",
+                DEFAULT_LOCKOUT_WINDOW_SECS,
+            )),
+            duration: (secs > 0).then(|| Duration::seconds(secs)),
+        }
+    }
+
+    /// Locks the account if its failed proofs in the window, counted from the
+    /// auth logs, have reached the threshold. Failures before the user's last
+    /// success or lockout event do not count. Returns the new lock.
+    pub async fn check_failures(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        user_name: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<AccountLock>, sqlx::Error> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        let failures = db::count_recent_failures(tx, user_name, now - self.window).await?;
+        if failures < self.threshold as i64 {
+            return Ok(None);
+        }
+        let lock = AccountLock {
+            locked_until: self.duration.map(|duration| now + duration),
+            reason: TOO_MANY_FAILURES.to_string(),
+        };
+        if !db::lock_user(tx, user_name, &lock, now).await? {
+            return Ok(None);
+        }
+        record_event(
+            tx,
+            user_name,
+            LockoutEventKind::Locked,
+            Some(&lock.reason),
+            "policy",
+            now,
+        )
+        .await?;
+        Ok(Some(lock))
+    }
+}
+
+/// Whether `lock` is still in force at `now`
+pub fn is_locked(lock: &AccountLock, now: DateTime<Utc>) -> bool {
+    lock.locked_until.is_none_or(|until| until > now)
+}
+
+async fn record_event(
+    tx: &mut Transaction<'_, Postgres>,
+    user_name: &str,
+    kind: LockoutEventKind,
+    reason: Option<&str>,
+    actor: &str,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    db::insert_lockout_event(
+        tx,
+        &LockoutEvent {
+            user_name: user_name.to_string(),
+            event: kind.as_str().to_string(),
+            reason: reason.map(str::to_string),
+            actor: actor.to_string(),
+            created_at: now,
+        },
+    )
+    .await
+}
+
+/// Clears an account's lock on behalf of `actor` and records the unlock;
+/// returns false if the account had no lock
+pub async fn unlock(
+    tx: &mut Transaction<'_, Postgres>,
+    user_name: &str,
+    actor: &str,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    if !db::unlock_user(tx, user_name).await? {
+        return Ok(false);
+    }
+    record_event(tx, user_name, LockoutEventKind::Unlocked, None, actor, now).await?;
+    Ok(true)
+}
+
+/// Clears locks that ran out and records each as expired; returns the users
+/// unlocked
+pub async fn expire_locks(
+    tx: &mut Transaction<'_, Postgres>,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>, sqlx::Error> {
+    let unlocked = db::unlock_expired_users(tx, now).await?;
+    for user_name in &unlocked {
+        record_event(
+            tx,
+            user_name,
+            LockoutEventKind::Expired,
+            None,
+            "policy",
+            now,
+        )
+        .await?;
+    }
+    Ok(unlocked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::AuthLog, kdf::KdfParams, params::LEGACY_PARAM_SET};
+    use num_bigint::BigUint;
+    use sqlx::PgPool;
+
+    #[tokio::test]
+    async fn test_lock_from_auth_logs() {
+        dotenvy::from_filename(".env.test").ok();
+        let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPool::connect(&db_url).await.unwrap();
+        let policy = LockoutPolicy {
+            threshold: 3,
+            ..Default::default()
+        };
+
+        let user_name = format!("lockout_user_{}", uuid::Uuid::new_v4());
+        let mut tx = pool.begin().await.unwrap();
+        db::insert_user(
+            &mut tx,
+            db::User {
+                user_name: user_name.clone(),
+                y1: BigUint::from(10u32),
+                y2: BigUint::from(20u32),
+                param_set: LEGACY_PARAM_SET.to_string(),
+                kdf: KdfParams::legacy(),
+                created_at: Utc::now(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let now = Utc::now();
+        let log = |success: bool, age: Duration| AuthLog {
+            user_name: user_name.clone(),
+            auth_id: format!("auth_{}", uuid::Uuid::new_v4()),
+            success,
+            created_at: now - age,
+            failure_reason: None,
+        };
+        // Failures outside the window or before a success do not count
+        db::insert_login_attempt(&mut tx, log(false, policy.window * 2))
+            .await
+            .unwrap();
+        db::insert_login_attempt(&mut tx, log(false, Duration::seconds(50)))
+            .await
+            .unwrap();
+        db::insert_login_attempt(&mut tx, log(true, Duration::seconds(40)))
+            .await
+            .unwrap();
+        db::insert_login_attempt(&mut tx, log(false, Duration::seconds(30)))
+            .await
+            .unwrap();
+        db::insert_login_attempt(&mut tx, log(false, Duration::seconds(20)))
+            .await
+            .unwrap();
+        assert_eq!(
+            policy
+                .check_failures(&mut tx, &user_name, now)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            db::get_account_lock(&mut tx, &user_name).await.unwrap(),
+            None
+        );
+
+        db::insert_login_attempt(&mut tx, log(false, Duration::seconds(10)))
+            .await
+            .unwrap();
+        let lock = policy
+            .check_failures(&mut tx, &user_name, now)
+            .await
+            .unwrap()
+            .expect("not locked");
+        assert_eq!(lock.locked_until, Some(now + policy.duration.unwrap()));
+        let stored = db::get_account_lock(&mut tx, &user_name)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(is_locked(&stored, now));
+        assert!(!is_locked(&stored, now + policy.duration.unwrap()));
+
+        // The lock event restarts the count
+        db::insert_login_attempt(&mut tx, log(false, Duration::zero()))
+            .await
+            .unwrap();
+        assert_eq!(
+            policy
+                .check_failures(&mut tx, &user_name, now)
+                .await
+                .unwrap(),
+            None
+        );
+
+        assert!(unlock(&mut tx, &user_name, "admin", now).await.unwrap());
+        assert!(!unlock(&mut tx, &user_name, "admin", now).await.unwrap());
+
+        // A lock that ran out is cleared and recorded as expired
+        let expired = AccountLock {
+            locked_until: Some(now - Duration::seconds(1)),
+            reason: TOO_MANY_FAILURES.to_string(),
+        };
+        assert!(db::lock_user(&mut tx, &user_name, &expired, now)
+            .await
+            .unwrap());
+        assert!(expire_locks(&mut tx, now)
+            .await
+            .unwrap()
+            .contains(&user_name));
+        assert_eq!(
+            db::get_account_lock(&mut tx, &user_name).await.unwrap(),
+            None
+        );
+
+        let events: Vec<(String, String)> = db::get_lockout_events_by_user(&mut tx, &user_name)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| (event.event, event.actor))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("locked", "policy"),
+                ("unlocked", "admin"),
+                ("expired", "policy")
+            ]
+            .map(|(event, actor)| (event.to_string(), actor.to_string()))
+        );
+        tx.rollback().await.unwrap();
+    }
+}
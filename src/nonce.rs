@@ -0,0 +1,165 @@
+use crypto_bigint::{
+    subtle::{ConstantTimeEq, ConstantTimeLess},
+    U2048,
+};
+use hmac::{Hmac, Mac};
+use num_bigint::BigUint;
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::secret::{self, SecretScalar, SECRET_BYTES};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How the prover picks its nonce k
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonceMode {
+    /// k comes from the RNG alone
+    #[default]
+    Random,
+    /// RFC 6979 with 32 fresh random bytes as additional data (section 3.6):
+    /// as good as `Random` with a working RNG, and still unpredictable if the
+    /// RNG is broken, as long as the context never repeats
+    Hedged,
+    /// Plain RFC 6979. Only safe for Fiat-Shamir proofs, where the same k
+    /// always meets the same challenge; in the interactive protocol two
+    /// challenges for one context reveal x
+    Deterministic,
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> Zeroizing<[u8; 32]> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    Zeroizing::new(mac.finalize().into_bytes().into())
+}
+
+// RFC 6979 bits2int for a public value: the leftmost qlen bits as an integer
+fn bits2int(bytes: &[u8], qlen: usize) -> BigUint {
+    let v = BigUint::from_bytes_be(bytes);
+    if bytes.len() * 8 > qlen {
+        v >> (bytes.len() * 8 - qlen)
+    } else {
+        v
+    }
+}
+
+/// Derives k with HMAC-DRBG over SHA-256 as in RFC 6979 section 3.2, with
+/// SHA-256(context) in place of the message hash and `extra` as the optional
+/// additional data k'.
+///
+/// With an empty `extra` this is RFC 6979 with SHA-256 and `context` as the
+/// message, so its DSA test vectors apply.
+pub fn derive_nonce(q: &BigUint, x: &SecretScalar, context: &[u8], extra: &[u8]) -> SecretScalar {
+    let qlen = q.bits() as usize;
+    let rlen = qlen.div_ceil(8);
+
+    // int2octets(x) || bits2octets(H(context)) || extra
+    let mut seed = Zeroizing::new(Vec::with_capacity(2 * rlen + extra.len()));
+    seed.extend_from_slice(&x.to_be_bytes()[SECRET_BYTES - rlen..]);
+    let h1 = bits2int(&Sha256::digest(context), qlen) % q;
+    let h1 = h1.to_bytes_be();
+    seed.resize(2 * rlen - h1.len(), 0);
+    seed.extend_from_slice(&h1);
+    seed.extend_from_slice(extra);
+
+    let mut v = Zeroizing::new([1u8; 32]);
+    let mut k = hmac(&[0u8; 32], &[&v[..], &[0x00], &seed]);
+    *v = *hmac(&k[..], &[&v[..]]);
+    k = hmac(&k[..], &[&v[..], &[0x01], &seed]);
+    *v = *hmac(&k[..], &[&v[..]]);
+
+    let q_uint = secret::to_uint(q);
+    let blocks = rlen.div_ceil(32);
+    loop {
+        let mut t = Zeroizing::new([0u8; SECRET_BYTES]);
+        let start = SECRET_BYTES - 32 * blocks;
+        for block in t[start..].chunks_mut(32) {
+            *v = *hmac(&k[..], &[&v[..]]);
+            block.copy_from_slice(&v[..]);
+        }
+        let candidate = U2048::from_be_slice(&t[..]).shr_vartime(32 * blocks * 8 - qlen);
+        // Only whether a candidate was rejected is visible, never its value
+        let in_range = !candidate.ct_eq(&U2048::ZERO) & candidate.ct_lt(&q_uint);
+        if bool::from(in_range) {
+            return SecretScalar::from_reduced(candidate);
+        }
+        k = hmac(&k[..], &[&v[..], &[0x00]]);
+        *v = *hmac(&k[..], &[&v[..]]);
+    }
+}
+
+/// Picks a nonce in the given mode
+pub fn generate_nonce<R: CryptoRng + RngCore>(
+    mode: NonceMode,
+    q: &BigUint,
+    x: &SecretScalar,
+    context: &[u8],
+    rng: &mut R,
+) -> SecretScalar {
+    match mode {
+        NonceMode::Random => SecretScalar::random(q, rng),
+        NonceMode::Hedged => {
+            let mut extra = Zeroizing::new([0u8; 32]);
+            rng.fill_bytes(&mut extra[..]);
+            derive_nonce(q, x, context, &extra[..])
+        }
+        NonceMode::Deterministic => derive_nonce(q, x, context, &[]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(s: &str) -> BigUint {
+        BigUint::parse_bytes(s.as_bytes(), 16).unwrap()
+    }
+
+    // RFC 6979 appendix A.2.1 (DSA, 1024 bits) with SHA-256
+    #[test]
+    fn test_rfc6979_vectors() {
+        let q = from_hex("996F967F6C8E388D9E28D01E205FBA957A5698B1");
+        let x =
+            SecretScalar::from_biguint(&from_hex("411602CB19A6CCC34494D79D98EF1E7ED5AF25F7"), &q);
+        assert_eq!(
+            derive_nonce(&q, &x, b"sample", &[]).to_biguint(),
+            from_hex("519BA0546D0C39202A7D34D7DFA5E760B318BCFB")
+        );
+        assert_eq!(
+            derive_nonce(&q, &x, b"test", &[]).to_biguint(),
+            from_hex("5A67592E8128E03A417B0484410FB72C0B630E1A")
+        );
+    }
+
+    // Computed with an independent Python implementation of the same construction
+    #[test]
+    fn test_hedged_vector_over_ristretto_order() {
+        let q = (BigUint::from(1u32) << 252usize)
+            + BigUint::parse_bytes(b"27742317777372353535851937790883648493", 10).unwrap();
+        let x = SecretScalar::from_biguint(&from_hex("1234567890abcdef"), &q);
+        let extra: Vec<u8> = (0u8..32).collect();
+        assert_eq!(
+            derive_nonce(&q, &x, b"ctx", &extra).to_biguint(),
+            from_hex("7917a7161a497dcbe35f2f68dee16e72811f0f3e50bc1f3f649d2170eb6d3c9")
+        );
+    }
+
+    #[test]
+    fn test_modes() {
+        let q = from_hex("996F967F6C8E388D9E28D01E205FBA957A5698B1");
+        let x = SecretScalar::from_biguint(&BigUint::from(7u32), &q);
+        let mut rng = rand::thread_rng();
+        let det = |rng: &mut _| {
+            generate_nonce(NonceMode::Deterministic, &q, &x, b"ctx", rng).to_biguint()
+        };
+        assert_eq!(det(&mut rng), det(&mut rng));
+
+        let hedged1 = generate_nonce(NonceMode::Hedged, &q, &x, b"ctx", &mut rng).to_biguint();
+        let hedged2 = generate_nonce(NonceMode::Hedged, &q, &x, b"ctx", &mut rng).to_biguint();
+        assert_ne!(hedged1, hedged2);
+        assert!(hedged1 < q && hedged1 > BigUint::from(0u32));
+    }
+}
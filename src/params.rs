@@ -0,0 +1,187 @@
+use crate::{ModPGroup, Ristretto255, ZKP};
+use dashmap::DashMap;
+use num_bigint::BigUint;
+use std::sync::OnceLock;
+
+/// Parameter set used for new registrations when none is requested
+pub const DEFAULT_PARAM_SET: &str = "rfc5114-2048-256";
+
+/// The original 1024-bit group returned by `ZKP::get_constants`
+pub const LEGACY_PARAM_SET: &str = "legacy-1024";
+
+/// Names of every parameter set known to the registry
+pub const PARAM_SET_NAMES: &[&str] = &[
+    LEGACY_PARAM_SET,
+    "rfc5114-2048-224",
+    "rfc5114-2048-256",
+    "ffdhe2048",
+    "ristretto255",
+];
+
+// RFC 5114, section 2.2: 2048-bit MODP group with 224-bit prime order subgroup
+const RFC5114_2048_224_P: &str = "AD107E1E9123A9D0D660FAA79559C51FA20D64E5683B9FD1B54B1597B61D0A75E6FA141DF95A56DBAF9A3C407BA1DF15EB3D688A309C180E1DE6B85A1274A0A66D3F8152AD6AC2129037C9EDEFDA4DF8D91E8FEF55B7394B7AD5B7D0B6C12207C9F98D11ED34DBF6C6BA0B2C8BBC27BE6A00E0A0B9C49708B3BF8A317091883681286130BC8985DB1602E714415D9330278273C7DE31EFDC7310F7121FD5A07415987D9ADC0A486DCDF93ACC44328387315D75E198C641A480CD86A1B9E587E8BE60E69CC928B2B9C52172E413042E9B23F10B0E16E79763C9B53DCF4BA80A29E3FB73C16B8E75B97EF363E2FFA31F71CF9DE5384E71B81C0AC4DFFE0C10E64F";
+const RFC5114_2048_224_Q: &str = "801C0D34C58D93FE997177101F80535A4738CEBCBF389A99B36371EB";
+const RFC5114_2048_224_G: &str = "AC4032EF4F2D9AE39DF30B5C8FFDAC506CDEBE7B89998CAF74866A08CFE4FFE3A6824A4E10B9A6F0DD921F01A70C4AFAAB739D7700C29F52C57DB17C620A8652BE5E9001A8D66AD7C17669101999024AF4D027275AC1348BB8A762D0521BC98AE247150422EA1ED409939D54DA7460CDB5F6C6B250717CBEF180EB34118E98D119529A45D6F834566E3025E316A330EFBB77A86F0C1AB15B051AE3D428C8F8ACB70A8137150B8EEB10E183EDD19963DDD9E263E4770589EF6AA21E7F5F2FF381B539CCE3409D13CD566AFBB48D6C019181E1BCFE94B30269EDFE72FE9B6AA4BD7B5A0F1C71CFFF4C19C418E1F6EC017981BC087F2A7065B384B890D3191F2BFA";
+
+// RFC 5114, section 2.3: 2048-bit MODP group with 256-bit prime order subgroup
+const RFC5114_2048_256_P: &str = "87A8E61DB4B6663CFFBBD19C651959998CEEF608660DD0F25D2CEED4435E3B00E00DF8F1D61957D4FAF7DF4561B2AA3016C3D91134096FAA3BF4296D830E9A7C209E0C6497517ABD5A8A9D306BCF67ED91F9E6725B4758C022E0B1EF4275BF7B6C5BFC11D45F9088B941F54EB1E59BB8BC39A0BF12307F5C4FDB70C581B23F76B63ACAE1CAA6B7902D52526735488A0EF13C6D9A51BFA4AB3AD8347796524D8EF6A167B5A41825D967E144E5140564251CCACB83E6B486F6B3CA3F7971506026C0B857F689962856DED4010ABD0BE621C3A3960A54E710C375F26375D7014103A4B54330C198AF126116D2276E11715F693877FAD7EF09CADB094AE91E1A1597";
+const RFC5114_2048_256_Q: &str = "8CF83642A709A097B447997640129DA299B1A47D1EB3750BA308B0FE64F5FBD3";
+const RFC5114_2048_256_G: &str = "3FB32C9B73134D0B2E77506660EDBD484CA7B18F21EF205407F4793A1A0BA12510DBC15077BE463FFF4FED4AAC0BB555BE3A6C1B0C6B47B1BC3773BF7E8C6F62901228F8C28CBB18A55AE31341000A650196F931C77A57F2DDF463E5E9EC144B777DE62AAAB8A8628AC376D282D6ED3864E67982428EBC831D14348F6F2F9193B5045AF2767164E1DFC967C1FB3F2E55A4BD1BFFE83B9C80D052B985D182EA0ADB2A3B7313D3FE14C8484B1E052588B9B7D2BBD2DF016199ECD06E1557CD0915B3353BBB64E0EC377FD028370DF92B52C7891428CDC67EB6184B523D1DB246C32F63078490F00EF8D647D148D47954515E2327CFEF98C582664B4C0F6CC41659";
+
+// RFC 7919 ffdhe2048: safe prime p = 2q + 1, generator 2 of the order-q subgroup
+const FFDHE2048_P: &str = "FFFFFFFFFFFFFFFFADF85458A2BB4A9AAFDC5620273D3CF1D8B9C583CE2D3695A9E13641146433FBCC939DCE249B3EF97D2FE363630C75D8F681B202AEC4617AD3DF1ED5D5FD65612433F51F5F066ED0856365553DED1AF3B557135E7F57C935984F0C70E0E68B77E2A689DAF3EFE8721DF158A136ADE73530ACCA4F483A797ABC0AB182B324FB61D108A94BB2C8E3FBB96ADAB760D7F4681D4F42A3DE394DF4AE56EDE76372BB190B07A7C8EE0A6D709E02FCE1CDF7E2ECC03404CD28342F619172FE9CE98583FF8E4F1232EEF28183C3FE3B1B4C6FAD733BB5FCBC2EC22005C58EF1837D1683B2C6F34A26C1B2EFFA886B423861285C97FFFFFFFFFFFFFFFF";
+
+fn from_hex(s: &str) -> BigUint {
+    BigUint::from_bytes_be(&hex::decode(s).unwrap())
+}
+
+/// Public seed the second generator of a parameter set is derived from.
+///
+/// Returns `None` for the legacy set, whose beta is `alpha^0x266FEA1E5C41564B777E69`
+/// and therefore has a known discrete log.
+pub fn generator_seed(name: &str) -> Option<String> {
+    if name == LEGACY_PARAM_SET || !PARAM_SET_NAMES.contains(&name) {
+        return None;
+    }
+    Some(format!("chaum-pederson-rust/{}/beta", name))
+}
+
+/// Re-derives beta from the public seed and checks it against the parameter set,
+/// so anyone can confirm nobody knows log_alpha(beta)
+pub fn verify_generators(zkp: &ZKP) -> bool {
+    match generator_seed(zkp.name()) {
+        Some(seed) => {
+            let beta = zkp.group.derive_generator(seed.as_bytes());
+            beta == zkp.beta && beta != zkp.alpha && zkp.group.is_valid_element(&beta)
+        }
+        None => false,
+    }
+}
+
+fn mod_p_set(name: &str, p: BigUint, q: BigUint, alpha: BigUint) -> ZKP {
+    let seed = generator_seed(name).expect("parameter set must have a seed");
+    ZKP::new(ModPGroup::with_derived_beta(
+        name,
+        p,
+        q,
+        alpha,
+        seed.as_bytes(),
+    ))
+}
+
+/// Looks up a parameter set by name, returning `None` for unknown names
+pub fn param_set(name: &str) -> Option<ZKP> {
+    match name {
+        LEGACY_PARAM_SET => {
+            let (alpha, beta, p, q) = ZKP::get_constants();
+            Some(ZKP::new(ModPGroup::named(name, p, q, alpha, beta)))
+        }
+        "rfc5114-2048-224" => Some(mod_p_set(
+            name,
+            from_hex(RFC5114_2048_224_P),
+            from_hex(RFC5114_2048_224_Q),
+            from_hex(RFC5114_2048_224_G),
+        )),
+        "rfc5114-2048-256" => Some(mod_p_set(
+            name,
+            from_hex(RFC5114_2048_256_P),
+            from_hex(RFC5114_2048_256_Q),
+            from_hex(RFC5114_2048_256_G),
+        )),
+        "ffdhe2048" => {
+            let p = from_hex(FFDHE2048_P);
+            let q = (&p - 1u32) >> 1usize;
+            Some(mod_p_set(name, p, q, BigUint::from(2u32)))
+        }
+        "ristretto255" => Some(ZKP::new(Ristretto255::new())),
+        _ => None,
+    }
+}
+
+/// Like `param_set`, but with fixed-base tables, built once per process and
+/// shared afterwards. Meant for long-running verifiers such as the server.
+pub fn precomputed_param_set(name: &str) -> Option<ZKP> {
+    static CACHE: OnceLock<DashMap<String, ZKP>> = OnceLock::new();
+    let cache = CACHE.get_or_init(DashMap::new);
+    if let Some(zkp) = cache.get(name) {
+        return Some(zkp.clone());
+    }
+    let zkp = param_set(name)?.with_fixed_base_tables();
+    cache.insert(name.to_string(), zkp.clone());
+    Some(zkp)
+}
+
+/// Returns the parameter set used when the caller doesn't pick one
+pub fn default_param_set() -> ZKP {
+    param_set(DEFAULT_PARAM_SET).expect("default parameter set must exist")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_param_sets_resolve() {
+        for name in PARAM_SET_NAMES {
+            let zkp = param_set(name).expect("parameter set missing");
+            assert_eq!(zkp.name(), *name);
+        }
+        assert!(param_set("no-such-set").is_none());
+    }
+
+    #[test]
+    fn test_mod_p_generators_have_order_q() {
+        for name in PARAM_SET_NAMES.iter().filter(|n| **n != "ristretto255") {
+            let zkp = param_set(name).unwrap();
+            let one = BigUint::from(1u32);
+            assert_eq!(zkp.exponentiate(&zkp.alpha, &zkp.q), one, "{}", name);
+            assert_eq!(zkp.exponentiate(&zkp.beta, &zkp.q), one, "{}", name);
+            assert_ne!(zkp.alpha, one);
+            assert_ne!(zkp.beta, one);
+        }
+    }
+
+    #[test]
+    fn test_verify_generators() {
+        for name in PARAM_SET_NAMES {
+            let zkp = param_set(name).unwrap();
+            assert_eq!(
+                verify_generators(&zkp),
+                *name != LEGACY_PARAM_SET,
+                "{}",
+                name
+            );
+        }
+
+        // A beta that was not derived from the seed is rejected
+        let mut zkp = default_param_set();
+        zkp.beta = zkp.exponentiate(&zkp.alpha, &BigUint::from(42u32));
+        assert!(!verify_generators(&zkp));
+    }
+
+    #[test]
+    fn test_precomputed_param_set_is_cached() {
+        let zkp = precomputed_param_set("ristretto255").unwrap();
+        assert!(zkp.has_fixed_base_tables());
+        assert_eq!(zkp.beta, param_set("ristretto255").unwrap().beta);
+        assert!(precomputed_param_set("no-such-set").is_none());
+    }
+
+    #[test]
+    fn test_proof_over_each_param_set() {
+        for name in PARAM_SET_NAMES {
+            let zkp = param_set(name).unwrap();
+            let x = ZKP::generate_random_below(&zkp.q);
+            let k = ZKP::generate_random_below(&zkp.q);
+            let c = ZKP::generate_random_below(&zkp.q);
+
+            let y1 = zkp.exponentiate(&zkp.alpha, &x);
+            let y2 = zkp.exponentiate(&zkp.beta, &x);
+            let r1 = zkp.exponentiate(&zkp.alpha, &k);
+            let r2 = zkp.exponentiate(&zkp.beta, &k);
+            let s = zkp.solve(&k, &c, &x);
+
+            assert!(zkp.verify(&r1, &r2, &y1, &y2, &c, &s), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{params, ZKP};
+
+/// Current version of the binary and JSON encodings
+pub const PROOF_VERSION: u8 = 1;
+
+#[derive(Error, Debug)]
+pub enum ProofError {
+    #[error("unsupported proof version {0}")]
+    UnsupportedVersion(u8),
+    #[error("unknown parameter set {0}")]
+    UnknownParamSet(String),
+    #[error("malformed proof: {0}")]
+    Malformed(String),
+    #[error("invalid proof JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A complete Chaum-Pedersen transcript: the statement (y1, y2), the
+/// commitment (r1, r2), the challenge c and the response s.
+///
+/// Binary layout (version 1), all integers big-endian:
+///
+/// ```text
+/// version: u8 | name_len: u8 | param_set: name_len bytes
+/// y1 | y2 | r1 | r2   each element_len bytes of the parameter set
+/// c | s               each scalar_len bytes of the parameter set
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    pub param_set: String,
+    pub y1: BigUint,
+    pub y2: BigUint,
+    pub r1: BigUint,
+    pub r2: BigUint,
+    pub c: BigUint,
+    pub s: BigUint,
+}
+
+// JSON form: every value is fixed-width lowercase hex
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProofJson {
+    version: u8,
+    param_set: String,
+    y1: String,
+    y2: String,
+    r1: String,
+    r2: String,
+    c: String,
+    s: String,
+}
+
+fn fixed_width(value: &BigUint, len: usize, field: &str) -> Result<Vec<u8>, ProofError> {
+    let bytes = value.to_bytes_be();
+    if bytes.len() > len {
+        return Err(ProofError::Malformed(format!(
+            "{} does not fit in {} bytes",
+            field, len
+        )));
+    }
+    let mut out = vec![0u8; len - bytes.len()];
+    out.extend_from_slice(&bytes);
+    Ok(out)
+}
+
+fn from_hex_field(value: &str, len: usize, field: &str) -> Result<BigUint, ProofError> {
+    let bytes = hex::decode(value)
+        .map_err(|e| ProofError::Malformed(format!("{} is not hex: {}", field, e)))?;
+    if bytes.len() != len {
+        return Err(ProofError::Malformed(format!(
+            "{} must be {} bytes",
+            field, len
+        )));
+    }
+    Ok(BigUint::from_bytes_be(&bytes))
+}
+
+impl Proof {
+    /// Looks up the parameter set the proof was made in
+    pub fn zkp(&self) -> Result<ZKP, ProofError> {
+        params::param_set(&self.param_set)
+            .ok_or_else(|| ProofError::UnknownParamSet(self.param_set.clone()))
+    }
+
+    /// Re-verifies the transcript outside the gRPC flow
+    pub fn verify(&self) -> Result<bool, ProofError> {
+        let zkp = self.zkp()?;
+        Ok(zkp.validate_scalar(&self.s).is_ok()
+            && zkp.verify(&self.r1, &self.r2, &self.y1, &self.y2, &self.c, &self.s))
+    }
+
+    fn fields(&self) -> [(&'static str, &BigUint); 6] {
+        [
+            ("y1", &self.y1),
+            ("y2", &self.y2),
+            ("r1", &self.r1),
+            ("r2", &self.r2),
+            ("c", &self.c),
+            ("s", &self.s),
+        ]
+    }
+
+    // Fixed widths of the six fields for a parameter set
+    fn widths(zkp: &ZKP) -> [usize; 6] {
+        let e = zkp.group.element_len();
+        let s = zkp.scalar_len();
+        [e, e, e, e, s, s]
+    }
+
+    /// Canonical binary encoding
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProofError> {
+        let zkp = self.zkp()?;
+        let mut out = vec![PROOF_VERSION, self.param_set.len() as u8];
+        out.extend_from_slice(self.param_set.as_bytes());
+        for ((field, value), len) in self.fields().into_iter().zip(Self::widths(&zkp)) {
+            out.extend(fixed_width(value, len, field)?);
+        }
+        Ok(out)
+    }
+
+    /// Parses the binary encoding, rejecting trailing or missing bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        let (&version, rest) = bytes
+            .split_first()
+            .ok_or_else(|| ProofError::Malformed("empty input".into()))?;
+        if version != PROOF_VERSION {
+            return Err(ProofError::UnsupportedVersion(version));
+        }
+        let (&name_len, rest) = rest
+            .split_first()
+            .ok_or_else(|| ProofError::Malformed("missing parameter set".into()))?;
+        if rest.len() < name_len as usize {
+            return Err(ProofError::Malformed("truncated parameter set".into()));
+        }
+        let (name, mut rest) = rest.split_at(name_len as usize);
+        let param_set = String::from_utf8(name.to_vec())
+            .map_err(|_| ProofError::Malformed("parameter set is not UTF-8".into()))?;
+        let zkp = params::param_set(&param_set)
+            .ok_or_else(|| ProofError::UnknownParamSet(param_set.clone()))?;
+
+        let widths = Self::widths(&zkp);
+        if rest.len() != widths.iter().sum::<usize>() {
+            return Err(ProofError::Malformed(format!(
+                "expected {} value bytes, got {}",
+                widths.iter().sum::<usize>(),
+                rest.len()
+            )));
+        }
+        let mut values = Vec::with_capacity(6);
+        for len in widths {
+            let (value, tail) = rest.split_at(len);
+            values.push(BigUint::from_bytes_be(value));
+            rest = tail;
+        }
+        let [y1, y2, r1, r2, c, s]: [BigUint; 6] = values.try_into().unwrap();
+        Ok(Proof {
+            param_set,
+            y1,
+            y2,
+            r1,
+            r2,
+            c,
+            s,
+        })
+    }
+
+    /// JSON encoding with fixed-width hex fields
+    pub fn to_json(&self) -> Result<String, ProofError> {
+        let zkp = self.zkp()?;
+        let mut hex_fields = Vec::with_capacity(6);
+        for ((field, value), len) in self.fields().into_iter().zip(Self::widths(&zkp)) {
+            hex_fields.push(hex::encode(fixed_width(value, len, field)?));
+        }
+        let [y1, y2, r1, r2, c, s]: [String; 6] = hex_fields.try_into().unwrap();
+        Ok(serde_json::to_string(&ProofJson {
+            version: PROOF_VERSION,
+            param_set: self.param_set.clone(),
+            y1,
+            y2,
+            r1,
+            r2,
+            c,
+            s,
+        })?)
+    }
+
+    /// Parses the JSON encoding
+    pub fn from_json(json: &str) -> Result<Self, ProofError> {
+        let parsed: ProofJson = serde_json::from_str(json)?;
+        if parsed.version != PROOF_VERSION {
+            return Err(ProofError::UnsupportedVersion(parsed.version));
+        }
+        let zkp = params::param_set(&parsed.param_set)
+            .ok_or_else(|| ProofError::UnknownParamSet(parsed.param_set.clone()))?;
+        let [e, _, _, _, s, _] = Self::widths(&zkp);
+        Ok(Proof {
+            y1: from_hex_field(&parsed.y1, e, "y1")?,
+            y2: from_hex_field(&parsed.y2, e, "y2")?,
+            r1: from_hex_field(&parsed.r1, e, "r1")?,
+            r2: from_hex_field(&parsed.r2, e, "r2")?,
+            c: from_hex_field(&parsed.c, s, "c")?,
+            s: from_hex_field(&parsed.s, s, "s")?,
+            param_set: parsed.param_set,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_proof(param_set: &str) -> Proof {
+        let zkp = params::param_set(param_set).unwrap();
+        let x = ZKP::generate_random_below(&zkp.q);
+        let k = ZKP::generate_random_below(&zkp.q);
+        let c = ZKP::generate_random_below(&zkp.q);
+        Proof {
+            param_set: param_set.to_string(),
+            y1: zkp.exponentiate(&zkp.alpha, &x),
+            y2: zkp.exponentiate(&zkp.beta, &x),
+            r1: zkp.exponentiate(&zkp.alpha, &k),
+            r2: zkp.exponentiate(&zkp.beta, &k),
+            s: zkp.solve(&k, &c, &x),
+            c,
+        }
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        for name in ["ristretto255", params::DEFAULT_PARAM_SET] {
+            let proof = make_proof(name);
+            let bytes = proof.to_bytes().unwrap();
+            let zkp = proof.zkp().unwrap();
+            let expected = 2 + name.len() + 4 * zkp.group.element_len() + 2 * zkp.scalar_len();
+            assert_eq!(bytes.len(), expected);
+
+            let decoded = Proof::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded, proof);
+            assert!(decoded.verify().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let proof = make_proof("ristretto255");
+        let json = proof.to_json().unwrap();
+        assert!(json.contains("\"version\":1"));
+
+        let decoded = Proof::from_json(&json).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(decoded.verify().unwrap());
+    }
+
+    #[test]
+    fn test_tampered_proof_fails_verification() {
+        let mut proof = make_proof("ristretto255");
+        proof.c += 1u32;
+        assert!(!proof.verify().unwrap());
+    }
+
+    #[test]
+    fn test_rejects_malformed_encodings() {
+        let proof = make_proof("ristretto255");
+        let mut bytes = proof.to_bytes().unwrap();
+
+        bytes.push(0);
+        assert!(matches!(
+            Proof::from_bytes(&bytes),
+            Err(ProofError::Malformed(_))
+        ));
+        bytes.truncate(bytes.len() - 2);
+        assert!(matches!(
+            Proof::from_bytes(&bytes),
+            Err(ProofError::Malformed(_))
+        ));
+
+        let mut wrong_version = proof.to_bytes().unwrap();
+        wrong_version[0] = 2;
+        assert!(matches!(
+            Proof::from_bytes(&wrong_version),
+            Err(ProofError::UnsupportedVersion(2))
+        ));
+
+        let unknown = Proof {
+            param_set: "nope".into(),
+            ..proof.clone()
+        };
+        assert!(matches!(
+            unknown.to_bytes(),
+            Err(ProofError::UnknownParamSet(_))
+        ));
+
+        // Hex fields must keep their fixed width
+        let json = proof.to_json().unwrap();
+        let short = json.replacen("\"c\":\"", "\"c\":\"00", 1);
+        assert!(matches!(
+            Proof::from_json(&short),
+            Err(ProofError::Malformed(_))
+        ));
+    }
+}
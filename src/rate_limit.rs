@@ -0,0 +1,583 @@
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use sqlx::PgPool;
+use std::env;
+use std::fmt;
+
+use crate::db::{self, RateLimitState};
+
+/// Default number of failures that blocks a key
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+/// Default length of the first block
+pub const DEFAULT_BLOCK_SECS: i64 = 60;
+/// Default cap on a block after repeated blocks have doubled it
+pub const DEFAULT_MAX_BLOCK_SECS: i64 = 3600;
+/// Default time after which one counted failure is forgiven
+pub const DEFAULT_DECAY_SECS: i64 = 300;
+/// Default number of failures, across all users, that blocks one client address
+pub const DEFAULT_IP_MAX_ATTEMPTS: u32 = 20;
+/// Default number of failures across the whole server that blocks everyone.
+/// Off by default: a spent budget refuses every login, so a distributed
+/// attacker could lock out all users at once
+pub const DEFAULT_GLOBAL_MAX_ATTEMPTS: u32 = 0;
+
+/// When failures block a key, and for how long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitPolicy {
+    pub max_attempts: u32,
+    pub block: Duration,
+    pub max_block: Duration,
+    /// One failure is forgiven per `decay` since the last one; zero disables decay
+    pub decay: Duration,
+}
+
+/// Policies for the three kinds of key a failure is counted under
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    /// One user from one client address
+    pub user: RateLimitPolicy,
+    /// One client address, whichever users it tries
+    pub ip: RateLimitPolicy,
+    /// The whole server
+    pub global: RateLimitPolicy,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            user: RateLimitPolicy::default(),
+            ip: RateLimitPolicy {
+                max_attempts: DEFAULT_IP_MAX_ATTEMPTS,
+                decay: Duration::seconds(60),
+                ..Default::default()
+            },
+            global: RateLimitPolicy {
+                max_attempts: DEFAULT_GLOBAL_MAX_ATTEMPTS,
+                max_block: Duration::seconds(300),
+                decay: Duration::seconds(1),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Reads the user policy from `ZKP_RATE_LIMIT_*`, the address policy from
+    /// `ZKP_IP_RATE_LIMIT_*` and the global budget from `ZKP_GLOBAL_RATE_LIMIT_*`
+    pub fn from_env() -> Self {
+        let default = RateLimitConfig::default();
+        RateLimitConfig {
+            user: RateLimitPolicy::from_env("ZKP_RATE_LIMIT", default.user),
+            ip: RateLimitPolicy::from_env("ZKP_IP_RATE_LIMIT", default.ip),
+            global: RateLimitPolicy::from_env("ZKP_GLOBAL_RATE_LIMIT", default.global),
+        }
+    }
+
+    /// Age after which the state of any key has fully decayed
+    pub fn idle_after(&self) -> Duration {
+        self.user
+            .idle_after()
+            .max(self.ip.idle_after())
+            .max(self.global.idle_after())
+    }
+}
+
+/// What a failure is counted against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateLimitKey {
+    /// A user tried from one client address. Failures from an attacker's
+    /// address do not block the owner logging in from elsewhere.
+    User { user_name: String, ip: String },
+    /// A client address, across every user it tries
+    Ip(String),
+    /// Every failed verification on the server
+    Global,
+}
+
+/// Start of every stored key of `user_name`. The length prefix keeps one
+/// user's prefix from matching the keys of another whose name extends it.
+fn user_prefix(user_name: &str) -> String {
+    format!("user:{}:{}@", user_name.len(), user_name)
+}
+
+impl RateLimitKey {
+    /// The policy that applies to this key
+    pub fn policy(&self, config: &RateLimitConfig) -> RateLimitPolicy {
+        match self {
+            RateLimitKey::User { .. } => config.user,
+            RateLimitKey::Ip(_) => config.ip,
+            RateLimitKey::Global => config.global,
+        }
+    }
+
+    /// The key's name in storage
+    pub fn storage_key(&self) -> String {
+        match self {
+            RateLimitKey::User { user_name, ip } => format!("{}{}", user_prefix(user_name), ip),
+            RateLimitKey::Ip(ip) => format!("ip:{}", ip),
+            RateLimitKey::Global => "global".to_string(),
+        }
+    }
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        RateLimitPolicy {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            block: Duration::seconds(DEFAULT_BLOCK_SECS),
+            max_block: Duration::seconds(DEFAULT_MAX_BLOCK_SECS),
+            decay: Duration::seconds(DEFAULT_DECAY_SECS),
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+impl RateLimitPolicy {
+    /// Reads `<prefix>_ATTEMPTS`, `<prefix>_BLOCK_SECS`, `<prefix>_MAX_BLOCK_SECS`
+    /// and `<prefix>_DECAY_SECS`, falling back to `default`
+    pub fn from_env(prefix: &str, default: RateLimitPolicy) -> Self {
+        let secs = |name: &str, default: Duration| {
+            Duration::seconds(env_or(
+                &format!("{}_{}", prefix, name),
+                default.num_seconds(),
+            ))
+        };
+        RateLimitPolicy {
+            max_attempts: env_or(&format!("{}_ATTEMPTS", prefix), default.max_attempts),
+            block: secs("BLOCK_SECS", default.block),
+            max_block: secs("MAX_BLOCK_SECS", default.max_block),
+            decay: secs("DECAY_SECS", default.decay),
+        }
+    }
+
+    /// Length of a block after `blocks` earlier ones: `block` doubled for each,
+    /// up to `max_block`
+    pub fn block_for(&self, blocks: u32) -> Duration {
+        self.block
+            .checked_mul(1 << blocks.min(30))
+            .unwrap_or(self.max_block)
+            .min(self.max_block)
+    }
+
+    /// `state` as of `now`: failures decay with the time since the last one,
+    /// and the backoff starts over once the key has been quiet for `max_block`
+    pub fn decayed(&self, mut state: RateLimitState, now: DateTime<Utc>) -> RateLimitState {
+        let quiet = now - state.last_attempt;
+        if self.decay > Duration::zero() {
+            let forgiven = quiet.num_milliseconds() / self.decay.num_milliseconds();
+            state.attempts = state
+                .attempts
+                .saturating_sub(forgiven.clamp(0, u32::MAX as i64) as u32);
+        }
+        if quiet >= self.max_block && self.blocked_until(&state, now).is_none() {
+            state.blocks = 0;
+        }
+        state
+    }
+
+    /// Counts a failure at `now`, blocking the key once it reaches `max_attempts`
+    pub fn record_failure(
+        &self,
+        state: Option<RateLimitState>,
+        now: DateTime<Utc>,
+    ) -> RateLimitState {
+        let mut state = match state {
+            Some(state) => self.decayed(state, now),
+            None => RateLimitState {
+                attempts: 0,
+                last_attempt: now,
+                blocked_until: None,
+                blocks: 0,
+            },
+        };
+        state.attempts += 1;
+        state.last_attempt = now;
+        // A policy with no attempts allowed counts failures but never blocks
+        if self.max_attempts > 0 && state.attempts >= self.max_attempts {
+            state.blocked_until = Some(now + self.block_for(state.blocks));
+            state.blocks += 1;
+            state.attempts = 0;
+        }
+        state
+    }
+
+    /// When the key is blocked until, if it is blocked at `now`
+    pub fn blocked_until(
+        &self,
+        state: &RateLimitState,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        state.blocked_until.filter(|until| *until > now)
+    }
+
+    /// How long after its last failure a key's state has fully decayed and can
+    /// be dropped
+    pub fn idle_after(&self) -> Duration {
+        self.max_block.max(self.decay * self.max_attempts as i32)
+    }
+}
+
+/// Where failed attempts are counted
+#[tonic::async_trait]
+pub trait RateLimiter: Send + Sync + fmt::Debug {
+    /// Thresholds this limiter applies
+    fn config(&self) -> RateLimitConfig;
+
+    /// When `key` is blocked until, or `None` if it may try now
+    async fn blocked_until(&self, key: &RateLimitKey)
+        -> Result<Option<DateTime<Utc>>, sqlx::Error>;
+
+    /// Counts a failure against `key`; returns the end of the block if this
+    /// failure started one
+    async fn record_failure(
+        &self,
+        key: &RateLimitKey,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error>;
+
+    /// Forgets the failures and block of `key`; returns false if there were none
+    async fn reset(&self, key: &RateLimitKey) -> Result<bool, sqlx::Error>;
+
+    /// Forgets the failures of `user_name` from every client address; returns
+    /// how many keys were cleared
+    async fn reset_user(&self, user_name: &str) -> Result<u64, sqlx::Error>;
+
+    /// Drops the state of keys whose failures have fully decayed; returns how
+    /// many were dropped
+    async fn remove_idle(&self) -> Result<u64, sqlx::Error>;
+}
+
+/// Failures counted in this process; lost on restart and not shared
+#[derive(Debug, Default)]
+pub struct MemoryRateLimiter {
+    config: RateLimitConfig,
+    entries: DashMap<String, RateLimitState>,
+}
+
+impl MemoryRateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        MemoryRateLimiter {
+            config,
+            entries: DashMap::new(),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl RateLimiter for MemoryRateLimiter {
+    fn config(&self) -> RateLimitConfig {
+        self.config
+    }
+
+    async fn blocked_until(
+        &self,
+        key: &RateLimitKey,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let policy = key.policy(&self.config);
+        Ok(self
+            .entries
+            .get(&key.storage_key())
+            .and_then(|state| policy.blocked_until(&state, Utc::now())))
+    }
+
+    async fn record_failure(
+        &self,
+        key: &RateLimitKey,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let policy = key.policy(&self.config);
+        let now = Utc::now();
+        // The entry guard serialises concurrent failures of one key.
+        let mut entry = self
+            .entries
+            .entry(key.storage_key())
+            .or_insert_with(|| RateLimitState {
+                attempts: 0,
+                last_attempt: now,
+                blocked_until: None,
+                blocks: 0,
+            });
+        let blocks = entry.blocks;
+        *entry = policy.record_failure(Some(*entry), now);
+        Ok(entry.blocked_until.filter(|_| entry.blocks > blocks))
+    }
+
+    async fn reset(&self, key: &RateLimitKey) -> Result<bool, sqlx::Error> {
+        Ok(self.entries.remove(&key.storage_key()).is_some())
+    }
+
+    async fn reset_user(&self, user_name: &str) -> Result<u64, sqlx::Error> {
+        let prefix = user_prefix(user_name);
+        let before = self.entries.len();
+        self.entries.retain(|key, _| !key.starts_with(&prefix));
+        Ok((before - self.entries.len()) as u64)
+    }
+
+    async fn remove_idle(&self) -> Result<u64, sqlx::Error> {
+        let now = Utc::now();
+        let cutoff = now - self.config.idle_after();
+        let before = self.entries.len();
+        self.entries.retain(|_, state| {
+            state.last_attempt >= cutoff || state.blocked_until.is_some_and(|until| until > now)
+        });
+        Ok((before - self.entries.len()) as u64)
+    }
+}
+
+/// Failures counted in the `rate_limits` table, shared by every instance that
+/// uses the same database and kept across restarts
+#[derive(Debug, Clone)]
+pub struct PgRateLimiter {
+    db: PgPool,
+    config: RateLimitConfig,
+}
+
+impl PgRateLimiter {
+    pub fn new(db: PgPool, config: RateLimitConfig) -> Self {
+        PgRateLimiter { db, config }
+    }
+}
+
+#[tonic::async_trait]
+impl RateLimiter for PgRateLimiter {
+    fn config(&self) -> RateLimitConfig {
+        self.config
+    }
+
+    async fn blocked_until(
+        &self,
+        key: &RateLimitKey,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let mut tx = self.db.begin().await?;
+        let state = db::get_rate_limit(&mut tx, &key.storage_key()).await?;
+        tx.commit().await?;
+        let policy = key.policy(&self.config);
+        Ok(state.and_then(|state| policy.blocked_until(&state, Utc::now())))
+    }
+
+    async fn record_failure(
+        &self,
+        key: &RateLimitKey,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let storage_key = key.storage_key();
+        let mut tx = self.db.begin().await?;
+        let now = Utc::now();
+        let current = db::get_rate_limit_for_update(&mut tx, &storage_key, now).await?;
+        let state = key.policy(&self.config).record_failure(Some(current), now);
+        db::upsert_rate_limit(&mut tx, &storage_key, &state).await?;
+        tx.commit().await?;
+        Ok(state
+            .blocked_until
+            .filter(|_| state.blocks > current.blocks))
+    }
+
+    async fn reset(&self, key: &RateLimitKey) -> Result<bool, sqlx::Error> {
+        let mut tx = self.db.begin().await?;
+        let existed = db::delete_rate_limit(&mut tx, &key.storage_key()).await?;
+        tx.commit().await?;
+        Ok(existed)
+    }
+
+    async fn reset_user(&self, user_name: &str) -> Result<u64, sqlx::Error> {
+        let mut tx = self.db.begin().await?;
+        let cleared = db::delete_rate_limits_by_prefix(&mut tx, &user_prefix(user_name)).await?;
+        tx.commit().await?;
+        Ok(cleared)
+    }
+
+    async fn remove_idle(&self) -> Result<u64, sqlx::Error> {
+        let mut tx = self.db.begin().await?;
+        let now = Utc::now();
+        let removed =
+            db::delete_idle_rate_limits(&mut tx, now - self.config.idle_after(), now).await?;
+        tx.commit().await?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_backoff_and_decay() {
+        let policy = RateLimitPolicy::default();
+        let now = Utc::now();
+        let mut state = None;
+        for _ in 0..policy.max_attempts {
+            state = Some(policy.record_failure(state, now));
+        }
+        let blocked = state.unwrap();
+        assert_eq!(blocked.blocked_until, Some(now + policy.block));
+        assert_eq!(
+            policy.blocked_until(&blocked, now),
+            Some(now + policy.block)
+        );
+        assert_eq!(policy.blocked_until(&blocked, now + policy.block), None);
+
+        // Each further block doubles, up to the cap
+        assert_eq!(policy.block_for(1), policy.block * 2);
+        assert_eq!(policy.block_for(3), policy.block * 8);
+        assert_eq!(policy.block_for(40), policy.max_block);
+        let mut state = Some(blocked);
+        for _ in 0..policy.max_attempts {
+            state = Some(policy.record_failure(state, now + policy.block));
+        }
+        let later = now + policy.block;
+        assert_eq!(state.unwrap().blocked_until, Some(later + policy.block * 2));
+
+        // Failures are forgiven one per decay interval
+        let state = policy.record_failure(Some(policy.record_failure(None, now)), now);
+        assert_eq!(state.attempts, 2);
+        assert_eq!(policy.decayed(state, now + policy.decay).attempts, 1);
+        assert_eq!(policy.decayed(state, now + policy.decay * 5).attempts, 0);
+
+        // A long quiet period resets the backoff
+        let state = RateLimitState {
+            blocks: 3,
+            ..blocked
+        };
+        assert_eq!(policy.decayed(state, now + policy.max_block).blocks, 0);
+        assert_eq!(policy.decayed(state, now + policy.block).blocks, 3);
+    }
+
+    #[test]
+    fn test_global_budget_is_off_by_default() {
+        let policy = RateLimitConfig::default().global;
+        let now = Utc::now();
+        let mut state = None;
+        for _ in 0..10_000 {
+            state = Some(policy.record_failure(state, now));
+        }
+        assert_eq!(policy.blocked_until(&state.unwrap(), now), None);
+    }
+
+    #[test]
+    fn test_storage_keys() {
+        let user = |user_name: &str, ip: &str| RateLimitKey::User {
+            user_name: user_name.to_string(),
+            ip: ip.to_string(),
+        };
+        assert_eq!(
+            user("alice", "10.0.0.1").storage_key(),
+            "user:5:alice@10.0.0.1"
+        );
+        assert_eq!(RateLimitKey::Ip("::1".to_string()).storage_key(), "ip:::1");
+        assert_eq!(RateLimitKey::Global.storage_key(), "global");
+        // A user whose name extends another's does not share its prefix
+        assert!(!user("alice@10", "0.0.1")
+            .storage_key()
+            .starts_with(&user_prefix("alice")));
+
+        let config = RateLimitConfig::default();
+        assert_eq!(user("alice", "10.0.0.1").policy(&config), config.user);
+        assert_eq!(RateLimitKey::Global.policy(&config), config.global);
+        assert!(config.idle_after() >= config.user.idle_after());
+    }
+
+    async fn check_limiter(limiter: Arc<dyn RateLimiter>, user_name: &str) {
+        let key = RateLimitKey::User {
+            user_name: user_name.to_string(),
+            ip: "10.0.0.1".to_string(),
+        };
+        let elsewhere = RateLimitKey::User {
+            user_name: user_name.to_string(),
+            ip: "10.0.0.2".to_string(),
+        };
+        let policy = key.policy(&limiter.config());
+        assert_eq!(limiter.blocked_until(&key).await.unwrap(), None);
+        for _ in 1..policy.max_attempts {
+            assert_eq!(limiter.record_failure(&key).await.unwrap(), None);
+        }
+        let until = limiter
+            .record_failure(&key)
+            .await
+            .unwrap()
+            .expect("key not blocked");
+        assert!(until > Utc::now());
+        assert!(limiter.blocked_until(&key).await.unwrap().is_some());
+        // The same user from another address is not blocked
+        assert_eq!(limiter.blocked_until(&elsewhere).await.unwrap(), None);
+
+        assert!(limiter.reset(&key).await.unwrap());
+        assert!(!limiter.reset(&key).await.unwrap());
+        assert_eq!(limiter.blocked_until(&key).await.unwrap(), None);
+
+        // Concurrent failures are all counted
+        let failures = (0..policy.max_attempts).map(|_| {
+            let limiter = Arc::clone(&limiter);
+            let key = key.clone();
+            tokio::spawn(async move { limiter.record_failure(&key).await.unwrap().is_some() })
+        });
+        let mut blocks = 0;
+        for failure in failures {
+            blocks += failure.await.unwrap() as usize;
+        }
+        assert_eq!(blocks, 1);
+
+        // Resetting a user clears every address, but not other users
+        let other = RateLimitKey::User {
+            user_name: format!("{}@10", user_name),
+            ip: "0.0.1".to_string(),
+        };
+        limiter.record_failure(&elsewhere).await.unwrap();
+        limiter.record_failure(&other).await.unwrap();
+        assert_eq!(limiter.reset_user(user_name).await.unwrap(), 2);
+        assert!(limiter.reset(&other).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_memory_limiter() {
+        let limiter = MemoryRateLimiter::new(RateLimitConfig::default());
+        limiter.entries.insert(
+            "idle".to_string(),
+            RateLimitState {
+                attempts: 1,
+                last_attempt: Utc::now() - limiter.config.idle_after() - Duration::seconds(1),
+                blocked_until: None,
+                blocks: 0,
+            },
+        );
+        assert_eq!(limiter.remove_idle().await.unwrap(), 1);
+        check_limiter(Arc::new(limiter), "memory_user").await;
+    }
+
+    #[tokio::test]
+    async fn test_postgres_limiter() {
+        dotenvy::from_filename(".env.test").ok();
+        let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPool::connect(&db_url).await.unwrap();
+        let limiter = PgRateLimiter::new(pool.clone(), RateLimitConfig::default());
+
+        let idle = format!("idle_{}", uuid::Uuid::new_v4());
+        let mut tx = pool.begin().await.unwrap();
+        db::upsert_rate_limit(
+            &mut tx,
+            &idle,
+            &RateLimitState {
+                attempts: 1,
+                last_attempt: Utc::now() - limiter.config.idle_after() - Duration::seconds(1),
+                blocked_until: None,
+                blocks: 0,
+            },
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+        assert!(limiter.remove_idle().await.unwrap() >= 1);
+        let mut tx = pool.begin().await.unwrap();
+        assert!(!db::delete_rate_limit(&mut tx, &idle).await.unwrap());
+        tx.rollback().await.unwrap();
+
+        check_limiter(
+            Arc::new(limiter),
+            &format!("pg_user_{}", uuid::Uuid::new_v4()),
+        )
+        .await;
+    }
+}
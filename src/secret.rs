@@ -0,0 +1,165 @@
+use crypto_bigint::{
+    modular::runtime_mod::{DynResidue, DynResidueParams},
+    Encoding, NonZero, RandomMod, U2048,
+};
+use num_bigint::BigUint;
+use rand::{CryptoRng, RngCore};
+use std::fmt;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+/// Width of the fixed-width integers, enough for every parameter set
+pub const SECRET_BYTES: usize = 256;
+
+/// Converts a public value to a fixed-width integer.
+///
+/// Panics if the value is wider than `SECRET_BYTES`; every supported modulus fits.
+pub(crate) fn to_uint(value: &BigUint) -> U2048 {
+    let bytes = value.to_bytes_be();
+    assert!(
+        bytes.len() <= SECRET_BYTES,
+        "value does not fit in 2048 bits"
+    );
+    let mut buf = [0u8; SECRET_BYTES];
+    buf[SECRET_BYTES - bytes.len()..].copy_from_slice(&bytes);
+    U2048::from_be_slice(&buf)
+}
+
+/// Converts a fixed-width integer holding a public value back to a `BigUint`
+pub(crate) fn from_uint(value: &U2048) -> BigUint {
+    BigUint::from_bytes_be(&value.to_be_bytes())
+}
+
+/// Montgomery parameters for an odd modulus (q, or p of a mod-p group)
+pub(crate) fn residue_params(modulus: &BigUint) -> DynResidueParams<{ U2048::LIMBS }> {
+    DynResidueParams::new(&to_uint(modulus))
+}
+
+/// A secret exponent (the password-derived x or a nonce k) reduced mod q.
+///
+/// The value lives in fixed-width limbs so that arithmetic on it runs in
+/// constant time, and it is wiped from memory when dropped.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct SecretScalar(U2048);
+
+impl SecretScalar {
+    /// Reduces big-endian bytes mod q without branching on their value.
+    ///
+    /// Inputs up to twice `SECRET_BYTES` are reduced in constant time; longer
+    /// ones (only raw legacy passwords) are first reduced with `BigUint`.
+    pub fn from_bytes_be(bytes: &[u8], q: &BigUint) -> Self {
+        if bytes.len() > 2 * SECRET_BYTES {
+            let reduced = Zeroizing::new((BigUint::from_bytes_be(bytes) % q).to_bytes_be());
+            return Self::from_bytes_be(&reduced, q);
+        }
+        let mut buf = Zeroizing::new([0u8; 2 * SECRET_BYTES]);
+        buf[2 * SECRET_BYTES - bytes.len()..].copy_from_slice(bytes);
+        let mut upper = U2048::from_be_slice(&buf[..SECRET_BYTES]);
+        let mut lower = U2048::from_be_slice(&buf[SECRET_BYTES..]);
+        let (reduced, _) = U2048::const_rem_wide((lower, upper), &to_uint(q));
+        lower.zeroize();
+        upper.zeroize();
+        SecretScalar(reduced)
+    }
+
+    /// Reduces a `BigUint` mod q. The caller's copy is not wiped.
+    pub fn from_biguint(x: &BigUint, q: &BigUint) -> Self {
+        Self::from_bytes_be(&Zeroizing::new(x.to_bytes_be()), q)
+    }
+
+    /// Draws a uniform scalar in [0, q)
+    pub fn random<R: CryptoRng + RngCore>(q: &BigUint, rng: &mut R) -> Self {
+        let modulus = NonZero::new(to_uint(q)).expect("group order must be nonzero");
+        SecretScalar(U2048::random_mod(rng, &modulus))
+    }
+
+    /// Wraps a value the caller has already reduced mod q
+    pub(crate) fn from_reduced(value: U2048) -> Self {
+        SecretScalar(value)
+    }
+
+    /// The fixed-width value
+    pub fn as_uint(&self) -> &U2048 {
+        &self.0
+    }
+
+    /// Big-endian bytes of the value, wiped when the buffer is dropped
+    pub fn to_be_bytes(&self) -> Zeroizing<[u8; SECRET_BYTES]> {
+        Zeroizing::new(self.0.to_be_bytes())
+    }
+
+    /// Little-endian bytes of the value, wiped when the buffer is dropped
+    pub fn to_le_bytes(&self) -> Zeroizing<[u8; SECRET_BYTES]> {
+        Zeroizing::new(self.0.to_le_bytes())
+    }
+
+    /// Copies the value out as a `BigUint`, which is not wiped on drop
+    pub fn to_biguint(&self) -> BigUint {
+        BigUint::from_bytes_be(&self.to_be_bytes()[..])
+    }
+}
+
+impl fmt::Debug for SecretScalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretScalar(..)")
+    }
+}
+
+/// Computes s = k - c * x mod q with Montgomery arithmetic, without branching
+/// on k or x
+pub fn solve(q: &BigUint, k: &SecretScalar, c: &BigUint, x: &SecretScalar) -> BigUint {
+    let params = residue_params(q);
+    let c = DynResidue::new(&to_uint(&(c % q)), params);
+    let mut k = DynResidue::new(&k.0, params);
+    let mut x = DynResidue::new(&x.0, params);
+    let mut cx = c * x;
+    let s = from_uint(&(k - cx).retrieve());
+    k.zeroize();
+    x.zeroize();
+    cx.zeroize();
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reduction_matches_biguint() {
+        let q = BigUint::from_bytes_be(
+            &hex::decode("F518AA8781A8DF278ABA4E7D64B7CB9D49462353").unwrap(),
+        );
+        for len in [0usize, 1, 20, 36, 300, 512, 600] {
+            let bytes: Vec<u8> = (0..len).map(|i| (i * 37 + 11) as u8).collect();
+            let expected = BigUint::from_bytes_be(&bytes) % &q;
+            assert_eq!(
+                SecretScalar::from_bytes_be(&bytes, &q).to_biguint(),
+                expected,
+                "{}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn test_solve_matches_biguint() {
+        let q = BigUint::from(1_000_003u32);
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let k = SecretScalar::random(&q, &mut rng);
+            let x = SecretScalar::random(&q, &mut rng);
+            let c = BigUint::from(rng.next_u64());
+            let (kb, xb) = (k.to_biguint(), x.to_biguint());
+            let expected = (&kb + &q * (&c * &xb)) - &c * &xb;
+            assert_eq!(solve(&q, &k, &c, &x), expected % &q);
+        }
+    }
+
+    #[test]
+    fn test_zeroize_and_debug() {
+        let q = BigUint::from(101u32);
+        let mut x = SecretScalar::from_biguint(&BigUint::from(42u32), &q);
+        assert_eq!(format!("{:?}", x), "SecretScalar(..)");
+        x.zeroize();
+        assert_eq!(x.to_biguint(), BigUint::from(0u32));
+    }
+}
@@ -1,9 +1,22 @@
 use crate::{
-    db::{self, AuthLog, Session, User},
-    ZKP,
+    admin::{self, AdminCredential, AdminImpl},
+    batch::{self, BatchVerifier},
+    challenge::{
+        ChallengeLimits, ChallengeStore, ChallengeStoreError, MemoryChallengeStore,
+        PgChallengeStore,
+    },
+    db::{self, AuthLog, PendingChallenge, User},
+    ids::{self, IdKind},
+    kdf::{KdfError, KdfParams},
+    lockout::{self, LockoutPolicy},
+    params,
+    rate_limit::{MemoryRateLimiter, PgRateLimiter, RateLimitConfig, RateLimitKey, RateLimiter},
+    session::{ClientInfo, Family, IssuedSession, RevokeReason, SessionPolicy},
+    token::{Jwks, TokenClaims, TokenSigner},
+    BatchItem, SharedRng, ValidationError, ZKP,
 };
 use chrono::Utc;
-use dashmap::DashMap;
+use rand::RngCore;
 use sqlx::postgres::PgPoolOptions;
 use std::env;
 use std::{
@@ -12,16 +25,30 @@ use std::{
 };
 // Import ZKP library for all the utility functions
 use num_bigint::BigUint;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 // BigUint helps us to work with very large number, which is essential for zero knowledge applications
-use thiserror::Error;
-use tonic::{transport::Server, Request, Response, Result, Status}; // Import Tonic for building gRPC services.
-use tracing::{event, info, instrument, Level};
 use crate::zkp_auth::{
-    self, auth_server::{Auth, AuthServer},
+    self,
+    admin_server::AdminServer,
+    auth_server::{Auth, AuthServer},
     AuthenticationAnswerRequest, AuthenticationAnswerResponse, AuthenticationChallengeRequest,
-    AuthenticationChallengeResponse, RegisterRequest, RegisterResponse,
+    AuthenticationChallengeResponse, GetJwksRequest, GetJwksResponse,
+    NonInteractiveAuthenticationRequest, RegisterRequest, RegisterResponse,
 };
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tonic::{transport::Server, Request, Response, Result, Status}; // Import Tonic for building gRPC services.
+use tracing::{error, event, info, instrument, Level};
+
+/// How far the timestamp of a non-interactive proof may be from the server clock
+pub const NON_INTERACTIVE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Metadata key on `FAILED_PRECONDITION` errors for locked accounts; holds
+/// the end of the lock, or `admin` if only an admin can lift it
+pub const ACCOUNT_LOCKED_METADATA: &str = "zkp-account-locked-until";
+
+/// How often expired challenges are swept from the challenge store
+pub const CHALLENGE_SWEEP_INTERVAL: Duration = Duration::from_secs(10);
 
 #[derive(Error, Debug)]
 pub enum AuthError {
@@ -29,7 +56,7 @@ pub enum AuthError {
     UserAlreadyExists(String),
     #[error("User {0} not found")]
     UserNotFound(String),
-    #[error("Auth ID {0} not found")]
+    #[error("Auth ID {0} not found or expired")]
     AuthIdNotFound(String),
     #[error("Verification failed for auth ID {0}")]
     VerificationFailed(String),
@@ -37,6 +64,49 @@ pub enum AuthError {
     Internal(String),
     #[error("User {0} is rate limited")]
     RateLimited(String),
+    #[error("Unknown parameter set {0}")]
+    UnknownParamSet(String),
+    #[error("User {0} is registered with parameter set {1}")]
+    ParamSetMismatch(String, String),
+    #[error("Invalid {0}: {1}")]
+    InvalidValue(String, ValidationError),
+    #[error("Invalid KDF parameters: {0}")]
+    InvalidKdf(#[from] KdfError),
+    #[error("Proof timestamp for {0} is outside the accepted window")]
+    StaleProof(String),
+    #[error("Proof {0} was already used")]
+    ProofReplayed(String),
+    #[error("Signed tokens are not enabled on this server")]
+    TokensDisabled,
+    #[error("Refresh token is invalid or expired")]
+    InvalidRefreshToken,
+    #[error("Session is invalid or expired")]
+    InvalidSession,
+    #[error("Refresh token for {0} was reused; its sessions were revoked")]
+    RefreshTokenReused(String),
+    #[error("Credential of {0} changed concurrently")]
+    CredentialChanged(String),
+    #[error("Missing or wrong admin credential")]
+    AdminUnauthorized,
+    #[error("Too many unanswered challenges on this server; try again later")]
+    ChallengeCapacity,
+    #[error("Too many failed attempts from {0}; try again later")]
+    ClientRateLimited(String),
+    #[error("Too many failed attempts on this server; try again later")]
+    GloballyRateLimited,
+    #[error("Account {0} is locked")]
+    AccountLocked(String, Option<chrono::DateTime<Utc>>),
+}
+
+impl From<ChallengeStoreError> for AuthError {
+    fn from(err: ChallengeStoreError) -> Self {
+        match err {
+            ChallengeStoreError::Database(e) => {
+                AuthError::Internal(format!("Challenge store error: {}", e))
+            }
+            ChallengeStoreError::TooManyPending => AuthError::ChallengeCapacity,
+        }
+    }
 }
 
 impl From<AuthError> for Status {
@@ -51,6 +121,31 @@ impl From<AuthError> for Status {
                 "User {} is rate limited. Please try again later.",
                 user
             )),
+            AuthError::UnknownParamSet(_) => Status::invalid_argument(err.to_string()),
+            AuthError::ParamSetMismatch(..) => Status::failed_precondition(err.to_string()),
+            AuthError::InvalidValue(..) => Status::invalid_argument(err.to_string()),
+            AuthError::InvalidKdf(_) => Status::invalid_argument(err.to_string()),
+            AuthError::StaleProof(_) => Status::invalid_argument(err.to_string()),
+            AuthError::ProofReplayed(_) => Status::permission_denied(err.to_string()),
+            AuthError::TokensDisabled => Status::failed_precondition(err.to_string()),
+            AuthError::InvalidRefreshToken => Status::unauthenticated(err.to_string()),
+            AuthError::InvalidSession => Status::unauthenticated(err.to_string()),
+            AuthError::RefreshTokenReused(_) => Status::unauthenticated(err.to_string()),
+            AuthError::CredentialChanged(_) => Status::aborted(err.to_string()),
+            AuthError::AdminUnauthorized => Status::unauthenticated(err.to_string()),
+            AuthError::ChallengeCapacity => Status::resource_exhausted(err.to_string()),
+            AuthError::ClientRateLimited(_) => Status::resource_exhausted(err.to_string()),
+            AuthError::GloballyRateLimited => Status::resource_exhausted(err.to_string()),
+            AuthError::AccountLocked(_, locked_until) => {
+                // Clients tell a lock from other failed preconditions by this key.
+                let mut status = Status::failed_precondition(err.to_string());
+                let until =
+                    locked_until.map_or_else(|| "admin".to_string(), |until| until.to_rfc3339());
+                if let Ok(value) = until.parse() {
+                    status.metadata_mut().insert(ACCOUNT_LOCKED_METADATA, value);
+                }
+                status
+            }
         }
     }
 }
@@ -59,15 +154,14 @@ impl From<AuthError> for Status {
 #[derive(Debug, Clone)]
 pub struct AuthImpl {
     pub db: PgPool, // Database connection pool for persistent storage of user and session data.
-    pub session_info: DashMap<String, AuthSession>, // Stores active authentication sessions.
-    pub rate_limit_info: DashMap<String, RateLimitInfo>, // Tracks rate limiting information for users.
-}
-
-#[derive(Debug, Clone)]
-pub struct RateLimitInfo {
-    pub attempts: u32,
-    pub last_attempt: Instant,
-    pub blocked_until: Option<Instant>,
+    pub challenges: Arc<dyn ChallengeStore>, // Challenges waiting for an answer.
+    pub rate_limiter: Arc<dyn RateLimiter>, // Counts failed attempts per user, client address and server.
+    pub default_param_set: String,          // Parameter set for registrations that don't name one.
+    pub batch_verifier: BatchVerifier,      // Checks concurrent challenge answers together.
+    pub rng: SharedRng,                     // Source of challenges, auth_ids and session_ids.
+    pub token_signer: Option<Arc<TokenSigner>>, // Signs session tokens when configured.
+    pub session_policy: SessionPolicy,      // Access, idle and absolute session lifetimes.
+    pub lockout_policy: LockoutPolicy,      // Failed proofs that lock an account.
 }
 
 #[derive(Debug, Clone)]
@@ -85,80 +179,431 @@ pub struct UserInfo {
     pub y2: BigUint,
 }
 
-#[derive(Debug, Clone)]
-pub struct AuthSession {
-    pub user_name: String,
-    pub r1: BigUint,
-    pub r2: BigUint,
-    pub c: BigUint,
-    pub created_at: Instant,
-}
-
 impl AuthImpl {
-    pub async fn cleanup_expired_sessions(&self) -> Result<(), AuthError> {
+    /// Resolves a parameter set name, falling back to the server default when empty
+    pub fn zkp_for(&self, param_set: &str) -> Result<ZKP, AuthError> {
+        let name = if param_set.is_empty() {
+            &self.default_param_set
+        } else {
+            param_set
+        };
+        params::precomputed_param_set(name)
+            .ok_or_else(|| AuthError::UnknownParamSet(name.to_string()))
+    }
+
+    /// Rejects received group elements that are trivial or outside the order-q group
+    pub fn validate_elements(zkp: &ZKP, elements: &[(&str, &BigUint)]) -> Result<(), AuthError> {
+        for (name, e) in elements {
+            zkp.validate_element(e)
+                .map_err(|err| AuthError::InvalidValue(name.to_string(), err))?;
+        }
+        Ok(())
+    }
+
+    /// Deletes sessions and refresh tokens that ended longer ago than the
+    /// retention period
+    pub async fn purge_old_sessions(&self) -> Result<u64, AuthError> {
         let mut tx = self
             .db
             .begin()
             .await
             .map_err(|e| AuthError::Internal(format!("DB transaction failed: {}", e)))?;
+        let cutoff = Utc::now() - self.session_policy.retention;
+        let purged = db::purge_sessions(&mut tx, cutoff)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        Ok(purged)
+    }
 
-        if let Err(e) = db::delete_expired_sessions(&mut tx).await {
-            event!(
-                Level::ERROR,
-                "Failed to delete expired sessions from database: {:?}",
-                e
-            );
-            tx.commit()
-                .await
-                .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
-            return Err(AuthError::Internal(format!("DB error: {}", e)));
-        } else {
-            event!(
-                Level::INFO,
-                "Expired sessions cleaned up from database successfully"
-            );
+    /// Refuses users whose account is locked
+    pub async fn check_not_locked(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        user_name: &str,
+    ) -> Result<(), AuthError> {
+        let lock = db::get_account_lock(tx, user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        match lock {
+            Some(lock) if lockout::is_locked(&lock, Utc::now()) => {
+                info!(user = %user_name, reason = %lock.reason, "Account is locked"); // Log refused locked account.
+                Err(AuthError::AccountLocked(
+                    user_name.to_string(),
+                    lock.locked_until,
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Locks the account if the failed proofs committed so far reach the
+    /// lockout threshold. It runs in a transaction of its own, after the
+    /// failure is logged, so that a failed log write cannot roll the lock
+    /// back. The request is failing anyway, so an error is only logged.
+    pub async fn apply_lockout(&self, user_name: &str) {
+        if !self.lockout_policy.is_enabled() {
+            return;
+        }
+        let locked = async {
+            let mut tx = self.db.begin().await?;
+            let lock = self
+                .lockout_policy
+                .check_failures(&mut tx, user_name, Utc::now())
+                .await?;
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(lock)
+        };
+        match locked.await {
+            Ok(Some(lock)) => {
+                info!(user = %user_name, reason = %lock.reason, locked_until = ?lock.locked_until, event = "account_locked", "completed")
+            }
+            Ok(None) => {}
+            Err(e) => error!(user = %user_name, error = %e, event = "account_locked", "failed"),
         }
+    }
+
+    /// Clears account locks that ran out, recording each in the audit trail
+    pub async fn expire_account_locks(&self) -> Result<Vec<String>, AuthError> {
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB transaction failed: {}", e)))?;
+        let unlocked = lockout::expire_locks(&mut tx, Utc::now())
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
         tx.commit()
             .await
             .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        Ok(unlocked)
+    }
+
+    /// The keys a failure of `user_name` from `client` is counted under
+    pub fn rate_limit_keys(user_name: &str, client: &ClientInfo) -> [RateLimitKey; 3] {
+        [
+            RateLimitKey::User {
+                user_name: user_name.to_string(),
+                ip: client.ip(),
+            },
+            RateLimitKey::Ip(client.ip()),
+            RateLimitKey::Global,
+        ]
+    }
+
+    pub async fn is_rate_limited(
+        &self,
+        user_name: &str,
+        client: &ClientInfo,
+    ) -> Result<(), AuthError> {
+        for key in AuthImpl::rate_limit_keys(user_name, client) {
+            let blocked_until = self
+                .rate_limiter
+                .blocked_until(&key)
+                .await
+                .map_err(|e| AuthError::Internal(format!("Rate limiter error: {}", e)))?;
+            if let Some(blocked_until) = blocked_until {
+                info!(user = %user_name, key = %key.storage_key(), "Rate limited for {} secs", (blocked_until - Utc::now()).num_seconds()); // Log rate limit status.
+                return Err(match key {
+                    RateLimitKey::User { .. } => AuthError::RateLimited(user_name.to_string()),
+                    RateLimitKey::Ip(ip) => AuthError::ClientRateLimited(ip),
+                    RateLimitKey::Global => AuthError::GloballyRateLimited,
+                });
+            }
+        }
         Ok(())
     }
 
-    pub fn is_rate_limited(&self, user_name: &str) -> Result<(), AuthError> {
-        if let Some(rate_limit_info) = self.rate_limit_info.get(user_name) {
-            if let Some(blocked_until) = rate_limit_info.blocked_until {
-                if Instant::now() < blocked_until {
-                    info!(user = %user_name, "User is currently rate limited for {:?} secs", blocked_until - Instant::now()); // Log rate limit status.
-                    return Err(AuthError::RateLimited(user_name.to_string()));
+    /// Counts a failed proof against the user, the client address and the
+    /// global budget. Like `apply_lockout`, it only logs errors.
+    pub async fn record_failure(&self, user_name: &str, client: &ClientInfo) {
+        for key in AuthImpl::rate_limit_keys(user_name, client) {
+            match self.rate_limiter.record_failure(&key).await {
+                Ok(Some(blocked_until)) => {
+                    info!(user = %user_name, key = %key.storage_key(), %blocked_until, event = "rate_limit_block", "completed")
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!(user = %user_name, error = %e, event = "rate_limit_record", "failed")
                 }
             }
         }
+    }
+
+    /// Rejects non-interactive proofs timestamped outside the accepted window
+    pub fn check_proof_time(user_name: &str, timestamp_ms: u64) -> Result<(), AuthError> {
+        let stale = || AuthError::StaleProof(user_name.to_string());
+        let timestamp_ms = i64::try_from(timestamp_ms).map_err(|_| stale())?;
+        let skew_ms = Utc::now().timestamp_millis().abs_diff(timestamp_ms);
+        if skew_ms > NON_INTERACTIVE_WINDOW.as_millis() as u64 {
+            return Err(stale());
+        }
         Ok(())
     }
 
-    pub fn record_failure(&self, user_name: &str) {
-        let mut rate_limit_info =
-            self.rate_limit_info
-                .entry(user_name.to_string())
-                .or_insert(RateLimitInfo {
-                    attempts: 0,
-                    last_attempt: Instant::now(),
-                    blocked_until: None,
-                });
-        rate_limit_info.attempts += 1;
-        rate_limit_info.last_attempt = Instant::now();
+    /// Records a non-interactive proof in the challenge store, which every
+    /// instance shares, and returns its id with the `auth_id` it is logged
+    /// under. r1 commits to a fresh nonce, so together with the context it
+    /// identifies the proof. It must be the canonical encoding
+    /// (`ZKP::encode_element`): parsing ignores leading zeros, so a padded
+    /// copy of the request bytes would get a new id. Proofs older than twice
+    /// the window can no longer pass the timestamp check, so the record is
+    /// kept that long.
+    pub async fn claim_proof(
+        &self,
+        context: &[u8],
+        r1: &[u8],
+        prefix: &str,
+    ) -> Result<(String, String), AuthError> {
+        let proof_id = hex::encode(
+            Sha256::new()
+                .chain_update(context)
+                .chain_update(r1)
+                .finalize(),
+        );
+        let auth_id = format!("{}_{}", prefix, &proof_id[..24]);
+        let expires_at =
+            Utc::now() + chrono::Duration::from_std(2 * NON_INTERACTIVE_WINDOW).unwrap();
+        if !self.challenges.claim_proof(&proof_id, expires_at).await? {
+            return Err(AuthError::ProofReplayed(auth_id));
+        }
+        Ok((proof_id, auth_id))
+    }
+
+    /// Forgets a rejected proof, so that its id stays free for a valid one
+    pub async fn release_proof(&self, proof_id: &str) {
+        if let Err(e) = self.challenges.release_proof(proof_id).await {
+            error!(error = %e, event = "release_proof", "failed");
+        }
+    }
+
+    pub async fn record_success(&self, user_name: &str, client: &ClientInfo) {
+        // Clear rate limit info on successful authentication. Only the user's
+        // key is cleared: an attacker could otherwise reset their address and
+        // the global budget by logging in to an account of their own.
+        let [user_key, ..] = AuthImpl::rate_limit_keys(user_name, client);
+        if let Err(e) = self.rate_limiter.reset(&user_key).await {
+            error!(user = %user_name, error = %e, event = "rate_limit_reset", "failed");
+        }
+    }
+
+    /// Builds the response for a new session, signing a token if the caller asked
+    /// for one. The token expires when the session would lapse unused, since
+    /// offline validation cannot extend it.
+    pub fn session_response(
+        &self,
+        issue_token: bool,
+        issued: IssuedSession,
+    ) -> Result<AuthenticationAnswerResponse, AuthError> {
+        let token = if issue_token {
+            let signer = self
+                .token_signer
+                .as_ref()
+                .ok_or(AuthError::TokensDisabled)?;
+            signer.sign(&TokenClaims {
+                sub: issued.user_name,
+                sid: ids::hash_token(&issued.session_id),
+                iat: Utc::now().timestamp(),
+                exp: issued.expires_at.timestamp(),
+            })
+        } else {
+            String::new()
+        };
+        Ok(AuthenticationAnswerResponse {
+            session_id: issued.session_id,
+            token,
+            refresh_token: issued.refresh_token,
+            expires_at: issued.expires_at.timestamp(),
+        })
+    }
 
-        if rate_limit_info.attempts >= 5 {
-            rate_limit_info.blocked_until = Some(Instant::now() + Duration::from_secs(60)); // Block for 60 seconds.
-            rate_limit_info.attempts = 0; // Reset attempts after blocking.
+    /// Resolves the session a request is made with, rejecting unknown and
+    /// lapsed ones, and counts the call as a use of it
+    pub async fn require_session(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        session_id: &str,
+    ) -> Result<db::Session, AuthError> {
+        if !ids::check_id(IdKind::Session, session_id) {
+            return Err(AuthError::InvalidSession);
         }
+        let token_hash = ids::hash_token(session_id);
+        let session = db::get_session_by_token_hash(tx, &token_hash)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?
+            .ok_or(AuthError::InvalidSession)?;
+        let now = Utc::now();
+        if !self.session_policy.is_live(&session, now) {
+            return Err(AuthError::InvalidSession);
+        }
+        db::touch_session(tx, &token_hash, now)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        Ok(session)
     }
 
-    pub fn record_success(&self, user_name: &str) {
-        self.rate_limit_info.remove(user_name); // Clear rate limit info on successful authentication.
+    /// Records the outcome of a proof check: on success writes the auth log and
+    /// starts a new session family, on failure logs the attempt and counts it
+    /// towards rate limiting
+    pub async fn complete_authentication(
+        &self,
+        user_name: &str,
+        auth_id: &str,
+        verify: bool,
+        client: &ClientInfo,
+        start: Instant,
+    ) -> Result<IssuedSession, AuthError> {
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+
+        if verify {
+            let auth_log = AuthLog {
+                user_name: user_name.to_string(),
+                auth_id: auth_id.to_string(),
+                success: true,
+                created_at: chrono::Utc::now(),
+                failure_reason: None,
+            };
+
+            if let Err(e) = db::insert_login_attempt(&mut tx, auth_log).await {
+                error!(
+                    user = %user_name,
+                    auth_id = %auth_id,
+                    error = %e,
+                    event = "auth_log_insert_failed",
+                    "Failed to insert auth log"
+                );
+            }
+            let issued = match self
+                .session_policy
+                .create_session(
+                    &mut tx,
+                    &mut self.rng.clone(),
+                    user_name,
+                    auth_id,
+                    None,
+                    client,
+                )
+                .await
+            {
+                Ok(issued) => issued,
+                Err(e) => {
+                    info!(
+                        user = %user_name,
+                        auth_id = %auth_id,
+                        error = %e,
+                        event = "session_insert_failed",
+                        "Failed to insert session"
+                    );
+                    return Err(AuthError::Internal(format!("DB error: {}", e)));
+                }
+            };
+
+            tx.commit()
+                .await
+                .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+            self.record_success(user_name, client).await; // Record successful authentication for rate limiting purposes.
+
+            info!(
+                user = %user_name,
+                success = verify,
+                event = "verify",
+                session = %ids::fingerprint(&issued.session_id),
+                duration_ms = start.elapsed().as_millis(),
+                "completed"
+            );
+            Ok(issued)
+        } else {
+            self.record_failure(user_name, client).await; // Record the failed attempt for rate limiting.
+            info!(
+                user = %user_name,
+                success = verify,
+                event = "verify",
+                duration_ms = start.elapsed().as_millis(),
+                "failed"
+            );
+            let auth_log = AuthLog {
+                user_name: user_name.to_string(),
+                auth_id: auth_id.to_string(),
+                success: false,
+                created_at: chrono::Utc::now(),
+                failure_reason: Some("Verification failed".to_string()),
+            };
+            if let Err(e) = db::insert_login_attempt(&mut tx, auth_log).await {
+                error!(
+                    user = %user_name,
+                    auth_id = %auth_id,
+                    error = %e,
+                    event = "auth_log_insert_failed",
+                    "Failed to insert auth log"
+                );
+            }
+            tx.commit()
+                .await
+                .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+            self.apply_lockout(user_name).await;
+
+            Err(AuthError::VerificationFailed(auth_id.to_string()))
+        }
     }
 }
 
+/// Everything stored about a user, for `ExportAccountData`. Commitments and
+/// salts are hex; sessions are identified by the hash of their token.
+pub fn account_export_json(
+    user: &User,
+    sessions: &[db::Session],
+    logs: &[AuthLog],
+    lockout_events: &[db::LockoutEvent],
+) -> serde_json::Value {
+    serde_json::json!({
+        "user": {
+            "user_name": user.user_name,
+            "y1": hex::encode(user.y1.to_bytes_be()),
+            "y2": hex::encode(user.y2.to_bytes_be()),
+            "param_set": user.param_set,
+            "kdf": {
+                "algorithm": user.kdf.algorithm,
+                "salt": hex::encode(&user.kdf.salt),
+                "memory_kib": user.kdf.memory_kib,
+                "iterations": user.kdf.iterations,
+                "parallelism": user.kdf.parallelism,
+            },
+            "created_at": user.created_at.to_rfc3339(),
+        },
+        "sessions": sessions.iter().map(|session| serde_json::json!({
+            "id": session.token_hash,
+            "auth_id": session.auth_id,
+            "family_id": session.family_id,
+            "created_at": session.created_at.to_rfc3339(),
+            "expires_at": session.expires_at.to_rfc3339(),
+            "last_used_at": session.last_used_at.to_rfc3339(),
+            "client_addr": session.client_addr,
+            "user_agent": session.user_agent,
+            "is_active": session.is_active,
+            "revoked_at": session.revoked_at.map(|t| t.to_rfc3339()),
+            "revoke_reason": session.revoke_reason,
+        })).collect::<Vec<_>>(),
+        "auth_logs": logs.iter().map(|log| serde_json::json!({
+            "auth_id": log.auth_id,
+            "success": log.success,
+            "created_at": log.created_at.to_rfc3339(),
+            "failure_reason": log.failure_reason,
+        })).collect::<Vec<_>>(),
+        "lockout_events": lockout_events.iter().map(|event| serde_json::json!({
+            "event": event.event,
+            "reason": event.reason,
+            "actor": event.actor,
+            "created_at": event.created_at.to_rfc3339(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
 #[tonic::async_trait]
 impl Auth for Arc<AuthImpl> {
     // Handles user registration.
@@ -173,6 +618,10 @@ impl Auth for Arc<AuthImpl> {
         info!(user = %user_name, event = "register", "start"); // Log the user being registered.
         let y1 = BigUint::from_bytes_be(&request.y1);
         let y2 = BigUint::from_bytes_be(&request.y2);
+        let zkp = self.zkp_for(&request.param_set)?;
+        AuthImpl::validate_elements(&zkp, &[("y1", &y1), ("y2", &y2)])?;
+        // Only accounts registered before the KDF existed keep the raw password
+        let kdf = KdfParams::validate_new(request.kdf).map_err(AuthError::from)?;
 
         let mut tx = self
             .db
@@ -184,6 +633,8 @@ impl Auth for Arc<AuthImpl> {
             user_name: user_name.clone(),
             y1,
             y2,
+            param_set: zkp.name().to_string(),
+            kdf,
             created_at: chrono::Utc::now(),
         };
         if let Err(e) = db::insert_user(&mut tx, user).await {
@@ -203,6 +654,7 @@ impl Auth for Arc<AuthImpl> {
 
         info!(
             user = %user_name,
+            param_set = %zkp.name(),
             event = "register",
             duration_ms = start.elapsed().as_millis(),
             "completed"
@@ -217,6 +669,7 @@ impl Auth for Arc<AuthImpl> {
         request: Request<AuthenticationChallengeRequest>,
     ) -> Result<Response<AuthenticationChallengeResponse>, Status> {
         let start = Instant::now(); // Start timer for authentication challenge process.
+        let client = ClientInfo::from_request(&request);
         let request = request.into_inner();
         let user_name = request.name;
         info!(user = %user_name, event = "create_challenge", "start"); // Log the user being authenticated.
@@ -227,31 +680,39 @@ impl Auth for Arc<AuthImpl> {
             .await
             .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
 
-        let exists = db::get_user_by_username(&mut tx, &user_name)
+        let user = db::get_user_by_username(&mut tx, &user_name)
             .await
             .map_err(|e| AuthError::Internal(format!("DB error while fetching user: {}", e)))?
-            .is_some();
+            .ok_or_else(|| AuthError::UserNotFound(user_name.clone()))?;
+        self.check_not_locked(&mut tx, &user_name).await?;
 
-        if !exists {
-            return Err(AuthError::UserNotFound(user_name.clone()).into());
+        if !request.param_set.is_empty() && request.param_set != user.param_set {
+            return Err(AuthError::ParamSetMismatch(user_name, user.param_set).into());
         }
 
-        self.is_rate_limited(&user_name)?; // Check if the user is currently rate limited before proceeding.
+        self.is_rate_limited(&user_name, &client).await?; // Check if the user is currently rate limited before proceeding.
 
         let r1 = BigUint::from_bytes_be(&request.r1);
         let r2 = BigUint::from_bytes_be(&request.r2);
 
-        let (_, _, _, q) = ZKP::get_constants();
-        let auth_id = ZKP::generate_random_string(12);
-        let c = ZKP::generate_random_below(&q);
-        let session = AuthSession {
+        let zkp = self.zkp_for(&user.param_set)?;
+        AuthImpl::validate_elements(&zkp, &[("r1", &r1), ("r2", &r2)])?;
+        let mut rng = self.rng.clone();
+        let auth_id = ids::generate_id(IdKind::Challenge, &mut rng);
+        let c = ZKP::random_below(&zkp.q, &mut rng);
+        let now = Utc::now();
+        let challenge = PendingChallenge {
             user_name: user_name.clone(),
-            r1: r1.clone(),
-            r2: r2.clone(),
+            r1,
+            r2,
             c: c.clone(),
-            created_at: Instant::now(),
+            created_at: now,
+            expires_at: now + self.challenges.limits().ttl,
         };
-        self.session_info.insert(auth_id.clone(), session);
+        self.challenges
+            .insert(&auth_id, challenge)
+            .await
+            .map_err(AuthError::from)?;
         info!(
             user = %user_name,
             auth_id = %auth_id,
@@ -262,6 +723,7 @@ impl Auth for Arc<AuthImpl> {
         Ok(Response::new(AuthenticationChallengeResponse {
             auth_id,
             c: c.to_bytes_be(),
+            kdf: Some(user.kdf.into()),
         }))
     }
 
@@ -272,18 +734,27 @@ impl Auth for Arc<AuthImpl> {
         request: Request<AuthenticationAnswerRequest>,
     ) -> Result<Response<AuthenticationAnswerResponse>, Status> {
         let start = Instant::now(); // Start timer for authentication verification process.
+        let client = ClientInfo::from_request(&request);
         let request = request.into_inner();
         let auth_id = request.auth_id;
+        if request.issue_token && self.token_signer.is_none() {
+            return Err(AuthError::TokensDisabled.into());
+        }
+        if !ids::check_id(IdKind::Challenge, &auth_id) {
+            return Err(AuthError::AuthIdNotFound(auth_id).into());
+        }
 
+        // Expired challenges are never returned, and each is returned once.
         let auth_session_info = self
-            .session_info
-            .remove(&auth_id)
-            .ok_or_else(|| AuthError::AuthIdNotFound(auth_id.clone()))?
-            .1;
+            .challenges
+            .take(&auth_id)
+            .await
+            .map_err(AuthError::from)?
+            .ok_or_else(|| AuthError::AuthIdNotFound(auth_id.clone()))?;
 
         let user_name = auth_session_info.user_name.clone();
         info!(user = %user_name, auth_id = %auth_id, event = "verify", "start"); // Log the user being verified.
-        self.is_rate_limited(&user_name)?;
+        self.is_rate_limited(&user_name, &client).await?;
 
         let mut tx = self
             .db
@@ -295,24 +766,49 @@ impl Auth for Arc<AuthImpl> {
             .await
             .map_err(|e| AuthError::Internal(format!("DB error while fetching user: {}", e)))?
             .ok_or_else(|| AuthError::UserNotFound(user_name.clone()))?;
-
-        if auth_session_info.created_at.elapsed() > Duration::from_secs(60) {
-            return Err(AuthError::Internal("auth challenge expired".into()).into());
-        }
+        self.check_not_locked(&mut tx, &user_name).await?;
 
         let s = BigUint::from_bytes_be(&request.s);
-        let (alpha, beta, p, q) = ZKP::get_constants();
-        let zkp = ZKP { p, q, alpha, beta };
+        let zkp = self.zkp_for(&user.param_set)?;
+        zkp.validate_scalar(&s)
+            .map_err(|err| AuthError::InvalidValue("s".to_string(), err))?;
+
+        // r1, r2 were validated with the challenge and y1, y2 at registration
+        let item = BatchItem {
+            r1: auth_session_info.r1,
+            r2: auth_session_info.r2,
+            y1: user.y1,
+            y2: user.y2,
+            c: auth_session_info.c,
+            s,
+        };
+        let verify = self.batch_verifier.verify(zkp, item).await;
+
+        let issued = self
+            .complete_authentication(&user_name, &auth_id, verify, &client, start)
+            .await?;
+        Ok(Response::new(
+            self.session_response(request.issue_token, issued)?,
+        ))
+    }
 
-        let verify = ZKP::verify(
-            &zkp,
-            &auth_session_info.r1,
-            &auth_session_info.r2,
-            &user.y1,
-            &user.y2,
-            &auth_session_info.c,
-            &s,
-        );
+    // Handles single round trip authentication with a Fiat-Shamir proof.
+    #[instrument(skip(self, request))]
+    async fn authenticate_non_interactive(
+        &self,
+        request: Request<NonInteractiveAuthenticationRequest>,
+    ) -> Result<Response<AuthenticationAnswerResponse>, Status> {
+        let start = Instant::now(); // Start timer for authentication verification process.
+        let client = ClientInfo::from_request(&request);
+        let request = request.into_inner();
+        let user_name = request.name;
+        info!(user = %user_name, event = "verify_non_interactive", "start"); // Log the user being verified.
+        self.is_rate_limited(&user_name, &client).await?;
+        if request.issue_token && self.token_signer.is_none() {
+            return Err(AuthError::TokensDisabled.into());
+        }
+
+        AuthImpl::check_proof_time(&user_name, request.timestamp_ms)?;
 
         let mut tx = self
             .db
@@ -320,87 +816,40 @@ impl Auth for Arc<AuthImpl> {
             .await
             .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
 
-        if verify {
-            let session_id = ZKP::generate_random_string(12);
-            let auth_log = AuthLog {
-                user_name: user_name.clone(),
-                auth_id: auth_id.clone(),
-                success: true,
-                created_at: chrono::Utc::now(),
-                failure_reason: None,
-            };
-            let session = Session {
-                session_id: session_id.clone(),
-                user_name: user_name.clone(),
-                auth_id: auth_id.clone(),
-                created_at: chrono::Utc::now(),
-            };
-
-            if let Err(e) = db::insert_login_attempt(&mut tx, auth_log).await {
-                info!(
-                    user = %user_name,
-                    auth_id = %auth_id,
-                    error = %e,
-                    event = "auth_log_insert_failed",
-                    "Failed to insert auth log"
-                );
-            }
-            if let Err(e) = db::insert_session(&mut tx, session).await {
-                info!(
-                    user = %user_name,
-                    auth_id = %auth_id,
-                    error = %e,
-                    event = "session_insert_failed",
-                    "Failed to insert session"
-                );
-                return Err(AuthError::Internal(format!("DB error: {}", e)).into());
-            }
-
-            tx.commit()
-                .await
-                .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
-            self.record_success(&user_name); // Record successful authentication for rate limiting purposes.
+        let user = db::get_user_by_username(&mut tx, &user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error while fetching user: {}", e)))?
+            .ok_or_else(|| AuthError::UserNotFound(user_name.clone()))?;
+        self.check_not_locked(&mut tx, &user_name).await?;
 
-            info!(
-                user = %user_name,
-                success = verify,
-                event = "verify",
-                session_id = %session_id,
-                duration_ms = start.elapsed().as_millis(),
-                "completed"
-            );
-            Ok(Response::new(AuthenticationAnswerResponse { session_id }))
-        } else {
-            self.record_failure(&user_name); // Record the failed attempt for rate limiting.
-            info!(
-                user = %user_name,
-                success = verify,
-                event = "verify",
-                duration_ms = start.elapsed().as_millis(),
-                "failed"
-            );
-            let auth_log = AuthLog {
-                user_name: user_name.clone(),
-                auth_id: auth_id.clone(),
-                success: false,
-                created_at: chrono::Utc::now(),
-                failure_reason: Some("Verification failed".to_string()),
-            };
-            if let Err(e) = db::insert_login_attempt(&mut tx, auth_log).await {
-                info!(
-                    user = %user_name,
-                    auth_id = %auth_id,
-                    error = %e,
-                    event = "auth_log_insert_failed",
-                    "Failed to insert auth log"
-                );
-            }
-            tx.commit()
-                .await
-                .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        if !request.param_set.is_empty() && request.param_set != user.param_set {
+            return Err(AuthError::ParamSetMismatch(user_name, user.param_set).into());
+        }
 
-            Err(AuthError::VerificationFailed(auth_id).into())
+        let zkp = self.zkp_for(&user.param_set)?;
+        let r1 = BigUint::from_bytes_be(&request.r1);
+        let r2 = BigUint::from_bytes_be(&request.r2);
+        let s = BigUint::from_bytes_be(&request.s);
+        AuthImpl::validate_elements(&zkp, &[("r1", &r1), ("r2", &r2)])?;
+        zkp.validate_scalar(&s)
+            .map_err(|err| AuthError::InvalidValue("s".to_string(), err))?;
+
+        let context = ZKP::fiat_shamir_context(&user_name, request.timestamp_ms);
+        let (proof_id, auth_id) = self
+            .claim_proof(&context, &zkp.encode_element(&r1), "ni")
+            .await?;
+
+        let verify = zkp.verify_non_interactive(&r1, &r2, &user.y1, &user.y2, &s, &context);
+        if !verify {
+            self.release_proof(&proof_id).await;
         }
+
+        let issued = self
+            .complete_authentication(&user_name, &auth_id, verify, &client, start)
+            .await?;
+        Ok(Response::new(
+            self.session_response(request.issue_token, issued)?,
+        ))
     }
 
     // Handles user logout.
@@ -410,8 +859,9 @@ impl Auth for Arc<AuthImpl> {
         request: tonic::Request<zkp_auth::LogoutRequest>,
     ) -> std::result::Result<tonic::Response<zkp_auth::LogoutResponse>, tonic::Status> {
         let request = request.into_inner();
-        let session_id = request.session_id;
-        info!(session_id = %session_id, event = "logout", "start"); // Log the session being logged out.
+        let token_hash = ids::hash_token(&request.session_id);
+        let session = &token_hash[..16]; // Only a fingerprint of the token is logged.
+        info!(session = %session, event = "logout", "start"); // Log the session being logged out.
 
         let mut tx = self
             .db
@@ -419,16 +869,26 @@ impl Auth for Arc<AuthImpl> {
             .await
             .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
 
-        match db::delete_session_by_id(&mut tx, &session_id).await {
-            Ok(_) => {
-                tx.commit()
+        // Logging out also revokes the refresh tokens of the session's family,
+        // so the login cannot be renewed.
+        let revoked = match db::get_session_by_token_hash(&mut tx, &token_hash).await {
+            Ok(Some(row)) => {
+                db::revoke_session_family(&mut tx, &row.family_id, RevokeReason::Logout.as_str())
+                    .await
+            }
+            Ok(None) => Ok(0),
+            Err(e) => Err(e),
+        };
+        match revoked {
+            Ok(_) => {
+                tx.commit()
                     .await
                     .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
-                info!(session_id = %session_id, event = "logout", "completed"); // Log successful logout.
+                info!(session = %session, event = "logout", "completed"); // Log successful logout.
                 Ok(Response::new(zkp_auth::LogoutResponse { success: true }))
             }
             Err(e) => {
-                info!(session_id = %session_id, error = %e, event = "logout", "failed"); // Log failed logout attempt.
+                info!(session = %session, error = %e, event = "logout", "failed"); // Log failed logout attempt.
                 Err(AuthError::Internal(format!("DB error: {}", e)).into())
             }
         }
@@ -442,43 +902,515 @@ impl Auth for Arc<AuthImpl> {
     ) -> std::result::Result<tonic::Response<zkp_auth::ValidateSessionResponse>, tonic::Status>
     {
         let request = request.into_inner();
-        let session_id = request.session_id;
-        info!(session_id = %session_id, event = "validate_session", "start"); // Log the session being validated.
+        let token_hash = ids::hash_token(&request.session_id);
+        let session = &token_hash[..16]; // Only a fingerprint of the token is logged.
+        info!(session = %session, event = "validate_session", "start"); // Log the session being validated.
+        if !ids::check_id(IdKind::Session, &request.session_id) {
+            info!(session = %session, event = "validate_session", "failed - malformed"); // Rejected without a database lookup.
+            return Ok(Response::new(zkp_auth::ValidateSessionResponse {
+                valid: false,
+                user_name: String::new(),
+                expires_at: 0,
+            }));
+        }
 
         let mut tx = self
             .db
             .begin()
             .await
             .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
-        match db::get_session_by_id(&mut tx, &session_id).await {
-            Ok(Some(session)) => {
-                if session.created_at + chrono::Duration::hours(1) < Utc::now() {
-                    info!(session_id = %session_id, event = "validate_session", "failed - expired"); // Log expired session validation attempt.
+        match db::get_session_by_token_hash(&mut tx, &token_hash).await {
+            Ok(Some(mut row)) => {
+                let now = Utc::now();
+                let expires_at = self.session_policy.expires_at(&row);
+                if !row.is_active {
+                    info!(session = %session, reason = ?row.revoke_reason, event = "validate_session", "failed - revoked"); // Log revoked session validation attempt.
                     return Ok(Response::new(zkp_auth::ValidateSessionResponse {
                         valid: false,
-                        user_name: session.user_name,
+                        user_name: row.user_name,
+                        expires_at: expires_at.timestamp(),
                     }));
-                } else {
-                    info!(session_id = %session_id, event = "validate_session", "completed"); // Log successful session validation.
+                }
+                if expires_at <= now {
+                    info!(session = %session, event = "validate_session", "failed - expired"); // Log expired session validation attempt.
                     return Ok(Response::new(zkp_auth::ValidateSessionResponse {
-                        valid: true,
-                        user_name: session.user_name,
+                        valid: false,
+                        user_name: row.user_name,
+                        expires_at: expires_at.timestamp(),
                     }));
                 }
+                // Each use pushes the idle deadline back, up to the session's own expiry.
+                db::touch_session(&mut tx, &token_hash, now)
+                    .await
+                    .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+                tx.commit()
+                    .await
+                    .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+                row.last_used_at = now;
+                let expires_at = self.session_policy.expires_at(&row);
+                info!(session = %session, event = "validate_session", "completed"); // Log successful session validation.
+                return Ok(Response::new(zkp_auth::ValidateSessionResponse {
+                    valid: true,
+                    user_name: row.user_name,
+                    expires_at: expires_at.timestamp(),
+                }));
             }
             Ok(None) => {
-                info!(session_id = %session_id, event = "validate_session", "failed - not found"); // Log session not found validation attempt.
+                info!(session = %session, event = "validate_session", "failed - not found"); // Log session not found validation attempt.
                 return Ok(Response::new(zkp_auth::ValidateSessionResponse {
                     valid: false,
                     user_name: String::new(),
+                    expires_at: 0,
                 }));
             }
             Err(e) => {
-                info!(session_id = %session_id, error = %e, event = "validate_session", "failed - db error"); // Log database error during session validation.
+                info!(session = %session, error = %e, event = "validate_session", "failed - db error"); // Log database error during session validation.
                 return Err(AuthError::Internal(format!("DB error: {}", e)).into());
             }
         }
     }
+
+    // Publishes the public keys that sign session tokens.
+    #[instrument(skip(self, _request))]
+    async fn get_jwks(
+        &self,
+        _request: Request<GetJwksRequest>,
+    ) -> Result<Response<GetJwksResponse>, Status> {
+        let jwks = match &self.token_signer {
+            Some(signer) => signer.jwks(),
+            None => Jwks::default(),
+        };
+        Ok(Response::new(GetJwksResponse {
+            jwks: jwks.to_json(),
+        }))
+    }
+
+    // Exchanges a refresh token for a new session in the same family.
+    #[instrument(skip(self, request))]
+    async fn refresh_session(
+        &self,
+        request: Request<zkp_auth::RefreshSessionRequest>,
+    ) -> Result<Response<AuthenticationAnswerResponse>, Status> {
+        let client = ClientInfo::from_request(&request);
+        let request = request.into_inner();
+        if request.issue_token && self.token_signer.is_none() {
+            return Err(AuthError::TokensDisabled.into());
+        }
+        let token_hash = ids::hash_token(&request.refresh_token);
+        let refresh = &token_hash[..16]; // Only a fingerprint of the token is logged.
+        info!(refresh = %refresh, event = "refresh_session", "start");
+        if !ids::check_id(IdKind::Refresh, &request.refresh_token) {
+            return Err(AuthError::InvalidRefreshToken.into());
+        }
+
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        let stored = db::get_refresh_token_for_update(&mut tx, &token_hash)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?
+            .ok_or(AuthError::InvalidRefreshToken)?;
+
+        let now = Utc::now();
+        let fresh = stored.used_at.is_none()
+            && db::mark_refresh_token_used(&mut tx, &token_hash, now)
+                .await
+                .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        if !fresh {
+            // A spent token came back: either the client or a thief holds a copy,
+            // so every session of the family is revoked.
+            db::revoke_session_family(
+                &mut tx,
+                &stored.family_id,
+                RevokeReason::RefreshReuse.as_str(),
+            )
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+            tx.commit()
+                .await
+                .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+            event!(
+                Level::WARN,
+                user = %stored.user_name,
+                family = %stored.family_id,
+                event = "refresh_session",
+                "refresh token reused, family revoked"
+            );
+            return Err(AuthError::RefreshTokenReused(stored.user_name).into());
+        }
+        if stored.revoked_at.is_some() {
+            info!(refresh = %refresh, event = "refresh_session", "failed - revoked");
+            return Err(AuthError::InvalidRefreshToken.into());
+        }
+        if stored.expires_at <= now {
+            info!(refresh = %refresh, event = "refresh_session", "failed - expired");
+            return Err(AuthError::InvalidRefreshToken.into());
+        }
+
+        // Refreshed sessions record their family in place of an auth_id.
+        let family = Family {
+            id: stored.family_id.clone(),
+            deadline: stored.expires_at,
+        };
+        let issued = self
+            .session_policy
+            .create_session(
+                &mut tx,
+                &mut self.rng.clone(),
+                &stored.user_name,
+                &stored.family_id,
+                Some(family),
+                &client,
+            )
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        info!(
+            user = %stored.user_name,
+            refresh = %refresh,
+            session = %ids::fingerprint(&issued.session_id),
+            event = "refresh_session",
+            "completed"
+        );
+        Ok(Response::new(
+            self.session_response(request.issue_token, issued)?,
+        ))
+    }
+
+    // Lists every stored session of the caller's user.
+    #[instrument(skip(self, request))]
+    async fn list_sessions(
+        &self,
+        request: Request<zkp_auth::ListSessionsRequest>,
+    ) -> Result<Response<zkp_auth::ListSessionsResponse>, Status> {
+        let request = request.into_inner();
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        let current = self.require_session(&mut tx, &request.session_id).await?;
+        info!(user = %current.user_name, event = "list_sessions", "start");
+
+        let rows = db::get_sessions_by_user(&mut tx, &current.user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+
+        let now = Utc::now();
+        let sessions = rows
+            .into_iter()
+            .map(|row| {
+                let expires_at = self.session_policy.expires_at(&row);
+                let active = self.session_policy.is_live(&row, now);
+                zkp_auth::SessionSummary {
+                    current: row.token_hash == current.token_hash,
+                    id: row.token_hash,
+                    created_at: row.created_at.timestamp(),
+                    expires_at: expires_at.timestamp(),
+                    last_used_at: row.last_used_at.timestamp(),
+                    client_addr: row.client_addr.unwrap_or_default(),
+                    user_agent: row.user_agent.unwrap_or_default(),
+                    active,
+                    revoked_at: row.revoked_at.map_or(0, |t| t.timestamp()),
+                    revoke_reason: row.revoke_reason.unwrap_or_default(),
+                }
+            })
+            .collect();
+        Ok(Response::new(zkp_auth::ListSessionsResponse { sessions }))
+    }
+
+    // Signs the caller's user out everywhere, optionally except this session.
+    #[instrument(skip(self, request))]
+    async fn revoke_all_sessions(
+        &self,
+        request: Request<zkp_auth::RevokeAllSessionsRequest>,
+    ) -> Result<Response<zkp_auth::RevokeAllSessionsResponse>, Status> {
+        let request = request.into_inner();
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        let current = self.require_session(&mut tx, &request.session_id).await?;
+        info!(user = %current.user_name, keep_current = request.keep_current, event = "revoke_all_sessions", "start");
+
+        let (keep, keep_family) = if request.keep_current {
+            (
+                Some(current.token_hash.as_str()),
+                Some(current.family_id.as_str()),
+            )
+        } else {
+            (None, None)
+        };
+        let revoked = db::revoke_sessions_by_user(
+            &mut tx,
+            &current.user_name,
+            keep,
+            keep_family,
+            RevokeReason::RevokeAll.as_str(),
+        )
+        .await
+        .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        info!(user = %current.user_name, revoked, event = "revoke_all_sessions", "completed");
+        Ok(Response::new(zkp_auth::RevokeAllSessionsResponse {
+            revoked: revoked as u32,
+        }))
+    }
+
+    // Replaces the user's commitments after a proof of the current secret.
+    #[instrument(skip(self, request))]
+    async fn rotate_credential(
+        &self,
+        request: Request<zkp_auth::RotateCredentialRequest>,
+    ) -> Result<Response<zkp_auth::RotateCredentialResponse>, Status> {
+        let start = Instant::now(); // Start timer for credential rotation process.
+        let client = ClientInfo::from_request(&request);
+        let request = request.into_inner();
+        let user_name = request.name;
+        info!(user = %user_name, revoke_sessions = request.revoke_sessions, event = "rotate_credential", "start"); // Log the user rotating their credential.
+        self.is_rate_limited(&user_name, &client).await?;
+
+        AuthImpl::check_proof_time(&user_name, request.timestamp_ms)?;
+
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        let user = db::get_user_by_username(&mut tx, &user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error while fetching user: {}", e)))?
+            .ok_or_else(|| AuthError::UserNotFound(user_name.clone()))?;
+        self.check_not_locked(&mut tx, &user_name).await?;
+
+        // The new commitments stay in the user's parameter set.
+        let zkp = self.zkp_for(&user.param_set)?;
+        let r1 = BigUint::from_bytes_be(&request.r1);
+        let r2 = BigUint::from_bytes_be(&request.r2);
+        let s = BigUint::from_bytes_be(&request.s);
+        let new_y1 = BigUint::from_bytes_be(&request.new_y1);
+        let new_y2 = BigUint::from_bytes_be(&request.new_y2);
+        AuthImpl::validate_elements(
+            &zkp,
+            &[
+                ("r1", &r1),
+                ("r2", &r2),
+                ("new_y1", &new_y1),
+                ("new_y2", &new_y2),
+            ],
+        )?;
+        zkp.validate_scalar(&s)
+            .map_err(|err| AuthError::InvalidValue("s".to_string(), err))?;
+        let new_kdf = KdfParams::validate_new(request.new_kdf).map_err(AuthError::from)?;
+
+        let context =
+            zkp.rotation_context(&user_name, request.timestamp_ms, &new_y1, &new_y2, &new_kdf);
+        let (proof_id, auth_id) = self
+            .claim_proof(&context, &zkp.encode_element(&r1), "rot")
+            .await?;
+
+        let verify = zkp.verify_non_interactive(&r1, &r2, &user.y1, &user.y2, &s, &context);
+        let auth_log = AuthLog {
+            user_name: user_name.clone(),
+            auth_id: auth_id.clone(),
+            success: verify,
+            created_at: Utc::now(),
+            failure_reason: (!verify).then(|| "Credential rotation proof failed".to_string()),
+        };
+        if let Err(e) = db::insert_login_attempt(&mut tx, auth_log).await {
+            error!(user = %user_name, auth_id = %auth_id, error = %e, event = "auth_log_insert_failed", "Failed to insert auth log");
+        }
+        if !verify {
+            self.release_proof(&proof_id).await;
+            self.record_failure(&user_name, &client).await; // A wrong old secret counts like a failed login.
+            tx.commit()
+                .await
+                .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+            self.apply_lockout(&user_name).await;
+            info!(user = %user_name, event = "rotate_credential", duration_ms = start.elapsed().as_millis(), "failed"); // Log failed rotation attempt.
+            return Err(AuthError::VerificationFailed(auth_id).into());
+        }
+
+        let replaced = db::update_user_credential(&mut tx, &user, &new_y1, &new_y2, &new_kdf)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        if !replaced {
+            return Err(AuthError::CredentialChanged(user_name).into());
+        }
+        let revoked = if request.revoke_sessions {
+            db::revoke_sessions_by_user(
+                &mut tx,
+                &user_name,
+                None,
+                None,
+                RevokeReason::CredentialRotated.as_str(),
+            )
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?
+        } else {
+            0
+        };
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        self.record_success(&user_name, &client).await;
+
+        info!(
+            user = %user_name,
+            revoked,
+            event = "rotate_credential",
+            duration_ms = start.elapsed().as_millis(),
+            "completed"
+        );
+        Ok(Response::new(zkp_auth::RotateCredentialResponse {
+            revoked: revoked as u32,
+        }))
+    }
+
+    // Deletes the user and their sessions after a fresh proof of the secret.
+    #[instrument(skip(self, request))]
+    async fn delete_account(
+        &self,
+        request: Request<zkp_auth::DeleteAccountRequest>,
+    ) -> Result<Response<zkp_auth::DeleteAccountResponse>, Status> {
+        let start = Instant::now(); // Start timer for account deletion process.
+        let client = ClientInfo::from_request(&request);
+        let request = request.into_inner();
+        let user_name = request.name;
+        info!(user = %user_name, anonymize_logs = request.anonymize_logs, event = "delete_account", "start"); // Log the account being deleted.
+        self.is_rate_limited(&user_name, &client).await?;
+        AuthImpl::check_proof_time(&user_name, request.timestamp_ms)?;
+
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        let user = db::get_user_by_username(&mut tx, &user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error while fetching user: {}", e)))?
+            .ok_or_else(|| AuthError::UserNotFound(user_name.clone()))?;
+        self.check_not_locked(&mut tx, &user_name).await?;
+
+        let zkp = self.zkp_for(&user.param_set)?;
+        let r1 = BigUint::from_bytes_be(&request.r1);
+        let r2 = BigUint::from_bytes_be(&request.r2);
+        let s = BigUint::from_bytes_be(&request.s);
+        AuthImpl::validate_elements(&zkp, &[("r1", &r1), ("r2", &r2)])?;
+        zkp.validate_scalar(&s)
+            .map_err(|err| AuthError::InvalidValue("s".to_string(), err))?;
+
+        let context =
+            ZKP::deletion_context(&user_name, request.timestamp_ms, request.anonymize_logs);
+        let (proof_id, auth_id) = self
+            .claim_proof(&context, &zkp.encode_element(&r1), "del")
+            .await?;
+        if !zkp.verify_non_interactive(&r1, &r2, &user.y1, &user.y2, &s, &context) {
+            self.release_proof(&proof_id).await;
+            self.record_failure(&user_name, &client).await; // A wrong secret counts like a failed login.
+            let auth_log = AuthLog {
+                user_name: user_name.clone(),
+                auth_id: auth_id.clone(),
+                success: false,
+                created_at: Utc::now(),
+                failure_reason: Some("Account deletion proof failed".to_string()),
+            };
+            if let Err(e) = db::insert_login_attempt(&mut tx, auth_log).await {
+                error!(user = %user_name, auth_id = %auth_id, error = %e, event = "auth_log_insert_failed", "Failed to insert auth log");
+            }
+            tx.commit()
+                .await
+                .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+            self.apply_lockout(&user_name).await;
+            info!(user = %user_name, event = "delete_account", duration_ms = start.elapsed().as_millis(), "failed"); // Log failed deletion attempt.
+            return Err(AuthError::VerificationFailed(auth_id).into());
+        }
+
+        // The alias is random, so kept rows cannot be linked back to the name.
+        let alias = format!("deleted_{:016x}", self.rng.clone().next_u64());
+        let logs_anonymized = if request.anonymize_logs {
+            db::anonymize_auth_logs(&mut tx, &user_name, &alias)
+                .await
+                .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?
+        } else {
+            0
+        };
+        // The lockout audit trail is always kept, and a new account with the
+        // name does not inherit it.
+        db::anonymize_lockout_events(&mut tx, &user_name, &alias)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        // Sessions and refresh tokens cascade from the user row.
+        db::delete_user_by_username(&mut tx, &user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+        // The account is gone, so its rate limiting state goes for every address.
+        if let Err(e) = self.rate_limiter.reset_user(&user_name).await {
+            error!(user = %user_name, error = %e, event = "rate_limit_reset", "failed");
+        }
+
+        info!(
+            user = %user_name,
+            logs_anonymized,
+            event = "delete_account",
+            duration_ms = start.elapsed().as_millis(),
+            "completed"
+        );
+        Ok(Response::new(zkp_auth::DeleteAccountResponse {
+            logs_anonymized: logs_anonymized as u32,
+        }))
+    }
+
+    // Returns everything stored about the caller's user as JSON.
+    #[instrument(skip(self, request))]
+    async fn export_account_data(
+        &self,
+        request: Request<zkp_auth::ExportAccountDataRequest>,
+    ) -> Result<Response<zkp_auth::ExportAccountDataResponse>, Status> {
+        let request = request.into_inner();
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB Transaction failed: {}", e)))?;
+        let current = self.require_session(&mut tx, &request.session_id).await?;
+        info!(user = %current.user_name, event = "export_account_data", "start");
+
+        let user = db::get_user_by_username(&mut tx, &current.user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error while fetching user: {}", e)))?
+            .ok_or_else(|| AuthError::UserNotFound(current.user_name.clone()))?;
+        let sessions = db::get_sessions_by_user(&mut tx, &current.user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        let logs = db::get_login_attempts_by_user(&mut tx, &current.user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        let lockout_events = db::get_lockout_events_by_user(&mut tx, &current.user_name)
+            .await
+            .map_err(|e| AuthError::Internal(format!("DB error: {}", e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| AuthError::Internal(format!("Commit failed: {}", e)))?;
+
+        info!(user = %current.user_name, sessions = sessions.len(), auth_logs = logs.len(), event = "export_account_data", "completed");
+        Ok(Response::new(zkp_auth::ExportAccountDataResponse {
+            json: account_export_json(&user, &sessions, &logs, &lockout_events).to_string(),
+        }))
+    }
 }
 
 pub async fn run_server() {
@@ -490,6 +1422,50 @@ pub async fn run_server() {
     info!(addr = %addr, "Starting server"); // Log server startup.
 
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let default_param_set =
+        env::var("ZKP_PARAM_SET").unwrap_or_else(|_| params::DEFAULT_PARAM_SET.to_string());
+    // Builds the fixed-base tables up front instead of on the first login
+    let default_zkp = params::precomputed_param_set(&default_param_set).unwrap_or_else(|| {
+        panic!(
+            "Unknown parameter set in ZKP_PARAM_SET: {}",
+            default_param_set
+        )
+    });
+    if !params::verify_generators(&default_zkp) {
+        event!(
+            Level::WARN,
+            param_set = %default_param_set,
+            "beta is not derived from a public seed; its discrete log may be known"
+        );
+    }
+    info!(param_set = %default_param_set, "Default parameter set");
+    let max_batch = env::var("ZKP_BATCH_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(batch::DEFAULT_MAX_BATCH);
+    let max_wait = env::var("ZKP_BATCH_WAIT_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(batch::DEFAULT_MAX_WAIT);
+    let rng = match env::var("ZKP_RNG_SEED").ok().and_then(|v| v.parse().ok()) {
+        Some(seed) => {
+            event!(
+                Level::WARN,
+                seed,
+                "ZKP_RNG_SEED is set: challenges and session ids are predictable, never use this in production"
+            );
+            SharedRng::seeded(seed)
+        }
+        None => SharedRng::from_entropy(),
+    };
+    // Signed tokens are issued only when a key is configured
+    let token_signer = env::var("ZKP_TOKEN_KEY").ok().map(|seed| {
+        let signer =
+            TokenSigner::from_hex(&seed).expect("ZKP_TOKEN_KEY must be a 32-byte hex seed");
+        info!(kid = %signer.kid(), "Signed session tokens enabled");
+        Arc::new(signer)
+    });
 
     let db_pool = PgPoolOptions::new()
         .max_connections(10) // optional but good practice
@@ -497,23 +1473,93 @@ pub async fn run_server() {
         .await
         .expect("Failed to connect to database");
 
+    // Postgres-backed challenges let several instances share one load balancer
+    let challenge_limits = ChallengeLimits::from_env();
+    let challenges: Arc<dyn ChallengeStore> = match env::var("ZKP_CHALLENGE_STORE").as_deref() {
+        Ok("postgres") => Arc::new(PgChallengeStore::new(db_pool.clone(), challenge_limits)),
+        Ok("memory") | Err(_) => Arc::new(MemoryChallengeStore::new(challenge_limits)),
+        Ok(other) => panic!("Unknown ZKP_CHALLENGE_STORE: {}", other),
+    };
+    info!(store = ?challenges, "Challenge store");
+    let rate_limit_config = RateLimitConfig::from_env();
+    let rate_limiter: Arc<dyn RateLimiter> = match env::var("ZKP_RATE_LIMIT_STORE").as_deref() {
+        Ok("postgres") => Arc::new(PgRateLimiter::new(db_pool.clone(), rate_limit_config)),
+        Ok("memory") | Err(_) => Arc::new(MemoryRateLimiter::new(rate_limit_config)),
+        Ok(other) => panic!("Unknown ZKP_RATE_LIMIT_STORE: {}", other),
+    };
+    info!(config = ?rate_limit_config, "Rate limiter");
+
     let auth_impl = Arc::new(AuthImpl {
         db: db_pool,
-        session_info: Default::default(),
-        rate_limit_info: Default::default(),
+        challenges,
+        rate_limiter,
+        default_param_set,
+        batch_verifier: BatchVerifier::spawn(max_batch, max_wait),
+        rng,
+        token_signer,
+        session_policy: SessionPolicy::from_env(),
+        lockout_policy: LockoutPolicy::from_env(),
+    });
+    // Abandoned challenges and expired proof ids are dropped as soon as they
+    // expire, and the number of challenges still pending is reported on every
+    // sweep.
+    let challenges = Arc::clone(&auth_impl.challenges);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(CHALLENGE_SWEEP_INTERVAL).await;
+            match challenges.remove_expired().await {
+                Ok(removed) => match challenges.pending().await {
+                    Ok(pending) => info!(removed, pending, event = "challenge_sweep", "completed"),
+                    Err(e) => error!(removed, error = %e, event = "challenge_sweep", "failed"),
+                },
+                Err(e) => error!(error = %e, event = "challenge_sweep", "failed"),
+            }
+        }
     });
+    // Expired and revoked sessions stay visible for auditing until the
+    // retention period has passed.
     let auth_clone = Arc::clone(&auth_impl);
-
-    // Start the gRPC server and add the AuthImpl service.
     tokio::spawn(async move {
         loop {
-            tokio::time::sleep(Duration::from_secs(60)).await; // Sleep for 10 minutes
-            info!("Running session cleanup task...");
-            if let Err(e) = auth_clone.cleanup_expired_sessions().await {
-                info!(error = %e, event = "session_cleanup", "failed"); // Log failed session cleanup attempt.
-            };
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+            match auth_clone.purge_old_sessions().await {
+                Ok(purged) => info!(purged, event = "session_purge", "completed"), // Log purged session count.
+                Err(e) => info!(error = %e, event = "session_purge", "failed"), // Log failed session purge.
+            }
+            match auth_clone.expire_account_locks().await {
+                Ok(unlocked) => {
+                    for user in unlocked {
+                        info!(user = %user, event = "account_unlocked", actor = "policy", "completed");
+                    }
+                }
+                Err(e) => error!(error = %e, event = "account_unlock_expired", "failed"),
+            }
+            match auth_clone.rate_limiter.remove_idle().await {
+                Ok(removed) => info!(removed, event = "rate_limit_purge", "completed"), // Log dropped limiter entries.
+                Err(e) => info!(error = %e, event = "rate_limit_purge", "failed"), // Log failed limiter purge.
+            }
         }
     });
+    // The admin service runs only with a credential, on its own port.
+    match env::var("ZKP_ADMIN_TOKEN") {
+        Ok(token) if !token.is_empty() => {
+            let admin_addr = env::var("ZKP_ADMIN_ADDR")
+                .unwrap_or_else(|_| admin::DEFAULT_ADMIN_ADDR.to_string());
+            info!(addr = %admin_addr, "Starting admin server"); // Log admin server startup.
+            let admin_impl = AdminImpl {
+                auth: Arc::clone(&auth_impl),
+                credential: AdminCredential::new(&token),
+            };
+            tokio::spawn(async move {
+                Server::builder()
+                    .add_service(AdminServer::new(admin_impl))
+                    .serve(admin_addr.parse().expect("Couldn't convert admin address"))
+                    .await
+                    .unwrap();
+            });
+        }
+        _ => info!("ZKP_ADMIN_TOKEN is not set; admin server disabled"),
+    }
     Server::builder()
         .add_service(AuthServer::new((auth_impl).clone()))
         .serve(addr.parse().expect("Couldn't convert address"))
@@ -526,7 +1572,13 @@ mod tests {
     use super::*;
     use zkp_auth::auth_client::AuthClient;
 
-    use crate::test_utils::{setup_zkp, spawn_test_server};
+    use crate::challenge::PgChallengeStore;
+    use crate::rate_limit::{MemoryRateLimiter, RateLimitPolicy, DEFAULT_MAX_ATTEMPTS};
+    use crate::test_utils::{
+        connect_from, setup_zkp, spawn_test_server, spawn_test_server_with_challenges,
+        spawn_test_server_with_lockout, spawn_test_server_with_rate_limiter,
+        spawn_test_server_with_rng,
+    };
 
     async fn register_user(
         client: &mut AuthClient<tonic::transport::Channel>,
@@ -542,6 +1594,8 @@ mod tests {
                 name: username.into(),
                 y1: y1.to_bytes_be(),
                 y2: y2.to_bytes_be(),
+                param_set: zkp.name().to_string(),
+                kdf: Some(KdfParams::generate().into()),
             })
             .await
             .unwrap();
@@ -553,6 +1607,17 @@ mod tests {
         username: &str,
         password: &BigUint,
     ) -> Option<String> {
+        login(client, zkp, username, password)
+            .await
+            .map(|res| res.session_id)
+    }
+
+    async fn login(
+        client: &mut AuthClient<tonic::transport::Channel>,
+        zkp: &ZKP,
+        username: &str,
+        password: &BigUint,
+    ) -> Option<AuthenticationAnswerResponse> {
         let k = ZKP::generate_random_below(&zkp.q);
 
         let r1 = zkp.exponentiate(&zkp.alpha, &k);
@@ -563,6 +1628,7 @@ mod tests {
                 name: username.into(),
                 r1: r1.to_bytes_be(),
                 r2: r2.to_bytes_be(),
+                param_set: zkp.name().to_string(),
             })
             .await
             .ok()?
@@ -575,21 +1641,20 @@ mod tests {
             .verify_authentication(AuthenticationAnswerRequest {
                 auth_id: challenge.auth_id,
                 s: s.to_bytes_be(),
+                issue_token: false,
             })
             .await
             .ok()?
             .into_inner();
 
-        Some(res.session_id)
+        Some(res)
     }
 
     #[tokio::test]
     async fn test_register() {
         let endpoint = spawn_test_server().await;
         let mut client = AuthClient::connect(endpoint).await.unwrap();
-        let (alpha, beta, p, q) = ZKP::get_constants();
-
-        let zkp = ZKP { p, q, alpha, beta };
+        let zkp = params::default_param_set();
 
         let password = BigUint::from_bytes_be(b"secret_password");
 
@@ -602,6 +1667,8 @@ mod tests {
             name: username.clone(),
             y1: y1.to_bytes_be(),
             y2: y2.to_bytes_be(),
+            param_set: zkp.name().to_string(),
+            kdf: Some(KdfParams::generate().into()),
         };
 
         let res = client.register(req.clone()).await;
@@ -612,9 +1679,7 @@ mod tests {
     async fn test_auth_flow() {
         let endpoint = spawn_test_server().await;
         let mut client = AuthClient::connect(endpoint).await.unwrap();
-        let (alpha, beta, p, q) = ZKP::get_constants();
-
-        let zkp = ZKP { p, q, alpha, beta };
+        let zkp = params::default_param_set();
 
         let password = BigUint::from_bytes_be(b"secret_password");
         let y1 = zkp.exponentiate(&zkp.alpha, &password);
@@ -627,6 +1692,8 @@ mod tests {
                 name: username.clone(),
                 y1: y1.to_bytes_be(),
                 y2: y2.to_bytes_be(),
+                param_set: zkp.name().to_string(),
+                kdf: Some(KdfParams::generate().into()),
             })
             .await
             .unwrap();
@@ -638,9 +1705,10 @@ mod tests {
 
         let challenge = client
             .create_authentication_challenge(AuthenticationChallengeRequest {
-                name: username.into(),
+                name: username,
                 r1: r1.to_bytes_be(),
                 r2: r2.to_bytes_be(),
+                param_set: zkp.name().to_string(),
             })
             .await
             .unwrap()
@@ -653,6 +1721,7 @@ mod tests {
             .verify_authentication(AuthenticationAnswerRequest {
                 auth_id: challenge.auth_id,
                 s: s.to_bytes_be(),
+                issue_token: false,
             })
             .await;
 
@@ -660,17 +1729,70 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_duplicate_register() {
-        let endpoint = spawn_test_server().await;
-        let mut client = AuthClient::connect(endpoint).await.unwrap();
-
+    async fn test_seeded_servers_replay_challenges() {
+        let seed = rand::random::<u64>();
+        let mut client_a =
+            AuthClient::connect(spawn_test_server_with_rng(SharedRng::seeded(seed)).await)
+                .await
+                .unwrap();
+        let mut client_b =
+            AuthClient::connect(spawn_test_server_with_rng(SharedRng::seeded(seed)).await)
+                .await
+                .unwrap();
         let (zkp, password) = setup_zkp();
-        let username = format!("dup_{}", uuid::Uuid::new_v4());
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut client_a, &zkp, &username, &password).await;
 
-        register_user(&mut client, &zkp, &username, &password).await;
+        let mut rng = SharedRng::seeded(seed ^ 1);
+        let k = ZKP::random_below(&zkp.q, &mut rng);
+        let (r1, r2) = (
+            zkp.exponentiate(&zkp.alpha, &k),
+            zkp.exponentiate(&zkp.beta, &k),
+        );
+        let request = AuthenticationChallengeRequest {
+            name: username,
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            param_set: zkp.name().to_string(),
+        };
+        let challenge_a = client_a
+            .create_authentication_challenge(request.clone())
+            .await
+            .unwrap()
+            .into_inner();
+        let challenge_b = client_b
+            .create_authentication_challenge(request)
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(challenge_a.auth_id, challenge_b.auth_id);
+        assert_eq!(challenge_a.c, challenge_b.c);
 
-        // second attempt should fail
-        let y1 = zkp.exponentiate(&zkp.alpha, &password);
+        let s = zkp.solve(&k, &BigUint::from_bytes_be(&challenge_a.c), &password);
+        let res = client_a
+            .verify_authentication(AuthenticationAnswerRequest {
+                auth_id: challenge_a.auth_id,
+                s: s.to_bytes_be(),
+                issue_token: false,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(ids::check_id(IdKind::Session, &res.session_id));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_register() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("dup_{}", uuid::Uuid::new_v4());
+
+        register_user(&mut client, &zkp, &username, &password).await;
+
+        // second attempt should fail
+        let y1 = zkp.exponentiate(&zkp.alpha, &password);
         let y2 = zkp.exponentiate(&zkp.beta, &password);
 
         let res = client
@@ -678,6 +1800,8 @@ mod tests {
                 name: username,
                 y1: y1.to_bytes_be(),
                 y2: y2.to_bytes_be(),
+                param_set: zkp.name().to_string(),
+                kdf: Some(KdfParams::generate().into()),
             })
             .await;
 
@@ -694,6 +1818,7 @@ mod tests {
                 name: "ghost".into(),
                 r1: vec![1],
                 r2: vec![2],
+                param_set: String::new(),
             })
             .await;
 
@@ -709,6 +1834,7 @@ mod tests {
             .verify_authentication(AuthenticationAnswerRequest {
                 auth_id: "fake_id".into(),
                 s: vec![1],
+                issue_token: false,
             })
             .await;
 
@@ -731,9 +1857,10 @@ mod tests {
 
         let challenge = client
             .create_authentication_challenge(AuthenticationChallengeRequest {
-                name: username.into(),
+                name: username,
                 r1: r1.to_bytes_be(),
                 r2: r2.to_bytes_be(),
+                param_set: zkp.name().to_string(),
             })
             .await
             .unwrap()
@@ -745,6 +1872,7 @@ mod tests {
         let req = AuthenticationAnswerRequest {
             auth_id: challenge.auth_id.clone(),
             s: s.to_bytes_be(),
+            issue_token: false,
         };
 
         client.verify_authentication(req.clone()).await.unwrap();
@@ -775,6 +1903,307 @@ mod tests {
         assert!(res.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_signed_token_validates_offline() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut client, &zkp, &username, &password).await;
+
+        let k = ZKP::generate_random_below(&zkp.q);
+        let challenge = client
+            .create_authentication_challenge(AuthenticationChallengeRequest {
+                name: username.clone(),
+                r1: zkp.exponentiate(&zkp.alpha, &k).to_bytes_be(),
+                r2: zkp.exponentiate(&zkp.beta, &k).to_bytes_be(),
+                param_set: zkp.name().to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let s = zkp.solve(&k, &BigUint::from_bytes_be(&challenge.c), &password);
+        let res = client
+            .verify_authentication(AuthenticationAnswerRequest {
+                auth_id: challenge.auth_id,
+                s: s.to_bytes_be(),
+                issue_token: true,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        let jwks = client
+            .get_jwks(GetJwksRequest {})
+            .await
+            .unwrap()
+            .into_inner()
+            .jwks;
+        let claims = Jwks::from_json(&jwks)
+            .unwrap()
+            .verify(&res.token, Utc::now().timestamp())
+            .unwrap();
+        assert_eq!(claims.sub, username);
+        assert_eq!(claims.sid, ids::hash_token(&res.session_id));
+        assert_eq!(claims.exp, res.expires_at);
+    }
+
+    #[tokio::test]
+    async fn test_session_token_stored_hashed() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut client, &zkp, &username, &password).await;
+        let session_id = authenticate(&mut client, &zkp, &username, &password)
+            .await
+            .expect("auth failed");
+        assert!(ids::check_id(IdKind::Session, &session_id));
+
+        dotenvy::from_filename(".env.test").ok();
+        let pool = PgPool::connect(&env::var("DATABASE_URL").unwrap())
+            .await
+            .unwrap();
+        let mut tx = pool.begin().await.unwrap();
+        assert!(db::get_session_by_token_hash(&mut tx, &session_id)
+            .await
+            .unwrap()
+            .is_none());
+        let row = db::get_session_by_token_hash(&mut tx, &ids::hash_token(&session_id))
+            .await
+            .unwrap()
+            .expect("session row missing");
+        assert_eq!(row.user_name, username);
+
+        // A token with a broken checksum is rejected
+        let mut forged = session_id.clone();
+        forged.replace_range(8..9, if &session_id[8..9] == "0" { "1" } else { "0" });
+        let res = client
+            .validate_session(zkp_auth::ValidateSessionRequest { session_id: forged })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!res.valid);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_session_rotates_and_detects_reuse() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut client, &zkp, &username, &password).await;
+        let first = login(&mut client, &zkp, &username, &password)
+            .await
+            .expect("auth failed");
+        assert!(ids::check_id(IdKind::Refresh, &first.refresh_token));
+
+        let validated = client
+            .validate_session(zkp_auth::ValidateSessionRequest {
+                session_id: first.session_id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(validated.valid);
+        assert!(validated.expires_at > Utc::now().timestamp());
+        assert!(validated.expires_at <= first.expires_at + 1);
+
+        let refreshed = client
+            .refresh_session(zkp_auth::RefreshSessionRequest {
+                refresh_token: first.refresh_token.clone(),
+                issue_token: false,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_ne!(refreshed.session_id, first.session_id);
+        assert_ne!(refreshed.refresh_token, first.refresh_token);
+        let validated = client
+            .validate_session(zkp_auth::ValidateSessionRequest {
+                session_id: refreshed.session_id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(validated.valid);
+        assert_eq!(validated.user_name, username);
+
+        // Replaying the spent token revokes the whole family
+        let err = client
+            .refresh_session(zkp_auth::RefreshSessionRequest {
+                refresh_token: first.refresh_token,
+                issue_token: false,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        for session_id in [first.session_id, refreshed.session_id] {
+            let res = client
+                .validate_session(zkp_auth::ValidateSessionRequest { session_id })
+                .await
+                .unwrap()
+                .into_inner();
+            assert!(!res.valid);
+        }
+        let err = client
+            .refresh_session(zkp_auth::RefreshSessionRequest {
+                refresh_token: refreshed.refresh_token,
+                issue_token: false,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_list_and_revoke_all_sessions() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut client, &zkp, &username, &password).await;
+        let mut logins = Vec::new();
+        for _ in 0..3 {
+            logins.push(
+                login(&mut client, &zkp, &username, &password)
+                    .await
+                    .expect("auth failed"),
+            );
+        }
+
+        let listed = client
+            .list_sessions(zkp_auth::ListSessionsRequest {
+                session_id: logins[0].session_id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .sessions;
+        assert_eq!(listed.len(), 3);
+        assert_eq!(listed.iter().filter(|s| s.current).count(), 1);
+        let current = listed.iter().find(|s| s.current).unwrap();
+        assert_eq!(current.id, ids::hash_token(&logins[0].session_id));
+        assert!(listed
+            .iter()
+            .all(|s| s.active && s.client_addr.starts_with("127.0.0.1")));
+        assert!(listed.iter().all(|s| !s.user_agent.is_empty()));
+
+        let revoked = client
+            .revoke_all_sessions(zkp_auth::RevokeAllSessionsRequest {
+                session_id: logins[0].session_id.clone(),
+                keep_current: true,
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .revoked;
+        assert_eq!(revoked, 2);
+        for (login, valid) in logins.iter().zip([true, false, false]) {
+            let res = client
+                .validate_session(zkp_auth::ValidateSessionRequest {
+                    session_id: login.session_id.clone(),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(res.valid, valid);
+        }
+        // Revoked families cannot be refreshed back to life
+        let err = client
+            .refresh_session(zkp_auth::RefreshSessionRequest {
+                refresh_token: logins[1].refresh_token.clone(),
+                issue_token: false,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+        let err = client
+            .list_sessions(zkp_auth::ListSessionsRequest {
+                session_id: logins[1].session_id.clone(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+        // Revoked sessions are kept, marked with when and why they ended
+        let listed = client
+            .list_sessions(zkp_auth::ListSessionsRequest {
+                session_id: logins[0].session_id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .sessions;
+        assert_eq!(listed.len(), 3);
+        for s in &listed {
+            assert_eq!(s.active, s.current);
+            assert_eq!(s.revoked_at == 0, s.current);
+            assert_eq!(s.revoke_reason, if s.current { "" } else { "revoke_all" });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_logout_soft_revokes_session() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut client, &zkp, &username, &password).await;
+        let first = login(&mut client, &zkp, &username, &password)
+            .await
+            .expect("auth failed");
+        let second = login(&mut client, &zkp, &username, &password)
+            .await
+            .expect("auth failed");
+
+        client
+            .logout(zkp_auth::LogoutRequest {
+                session_id: first.session_id.clone(),
+            })
+            .await
+            .unwrap();
+        let res = client
+            .validate_session(zkp_auth::ValidateSessionRequest {
+                session_id: first.session_id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!res.valid);
+        // Its refresh token is revoked with it
+        let err = client
+            .refresh_session(zkp_auth::RefreshSessionRequest {
+                refresh_token: first.refresh_token.clone(),
+                issue_token: false,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+        let listed = client
+            .list_sessions(zkp_auth::ListSessionsRequest {
+                session_id: second.session_id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .sessions;
+        let ended = listed
+            .iter()
+            .find(|s| s.id == ids::hash_token(&first.session_id))
+            .expect("logged out session was deleted");
+        assert!(!ended.active);
+        assert!(ended.revoked_at > 0);
+        assert_eq!(ended.revoke_reason, "logout");
+    }
+
     #[tokio::test]
     async fn test_logout() {
         let endpoint = spawn_test_server().await;
@@ -829,6 +2258,7 @@ mod tests {
                     name: username.clone(),
                     r1: r1.to_bytes_be(),
                     r2: r2.to_bytes_be(),
+                    param_set: zkp.name().to_string(),
                 })
                 .await
                 .unwrap()
@@ -843,6 +2273,7 @@ mod tests {
                 .verify_authentication(AuthenticationAnswerRequest {
                     auth_id: challenge.auth_id,
                     s: s.to_bytes_be(),
+                    issue_token: false,
                 })
                 .await;
         }
@@ -851,11 +2282,892 @@ mod tests {
         let res = client
             .create_authentication_challenge(AuthenticationChallengeRequest {
                 name: username.clone(),
-                r1: vec![1], 
+                r1: vec![1],
                 r2: vec![1],
+                param_set: zkp.name().to_string(),
             })
             .await;
 
         assert!(res.is_err());
     }
+
+    #[tokio::test]
+    async fn test_legacy_param_set_user() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let zkp = params::param_set(params::LEGACY_PARAM_SET).unwrap();
+        let password = ZKP::generate_random_below(&zkp.q);
+        let username = format!("legacy_{}", uuid::Uuid::new_v4());
+
+        register_user(&mut client, &zkp, &username, &password).await;
+
+        let session_id = authenticate(&mut client, &zkp, &username, &password).await;
+        assert!(session_id.is_some());
+
+        // A challenge for a different parameter set is refused up front
+        let (other, _) = setup_zkp();
+        let res = client
+            .create_authentication_challenge(AuthenticationChallengeRequest {
+                name: username,
+                r1: vec![1],
+                r2: vec![1],
+                param_set: other.name().to_string(),
+            })
+            .await;
+        assert_eq!(res.unwrap_err().code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_elements_rejected() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        let y1 = zkp.exponentiate(&zkp.alpha, &password);
+
+        // y2 = 1 would make the second equation trivially true
+        let res = client
+            .register(RegisterRequest {
+                name: username.clone(),
+                y1: y1.to_bytes_be(),
+                y2: vec![1],
+                param_set: zkp.name().to_string(),
+                kdf: Some(KdfParams::generate().into()),
+            })
+            .await;
+        assert_eq!(res.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        register_user(&mut client, &zkp, &username, &password).await;
+
+        // Zero and values above p are not group elements
+        let too_large = vec![0xffu8; zkp.group.element_len()];
+        for (r1, r2) in [(vec![0], y1.to_bytes_be()), (y1.to_bytes_be(), too_large)] {
+            let res = client
+                .create_authentication_challenge(AuthenticationChallengeRequest {
+                    name: username.clone(),
+                    r1,
+                    r2,
+                    param_set: zkp.name().to_string(),
+                })
+                .await;
+            assert_eq!(res.unwrap_err().code(), tonic::Code::InvalidArgument);
+        }
+
+        let k = ZKP::generate_random_below(&zkp.q);
+        let challenge = client
+            .create_authentication_challenge(AuthenticationChallengeRequest {
+                name: username,
+                r1: zkp.exponentiate(&zkp.alpha, &k).to_bytes_be(),
+                r2: zkp.exponentiate(&zkp.beta, &k).to_bytes_be(),
+                param_set: zkp.name().to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        // s must be reduced modulo q
+        let s = zkp.solve(&k, &BigUint::from_bytes_be(&challenge.c), &password) + &zkp.q;
+        let res = client
+            .verify_authentication(AuthenticationAnswerRequest {
+                auth_id: challenge.auth_id,
+                s: s.to_bytes_be(),
+                issue_token: false,
+            })
+            .await;
+        assert_eq!(res.unwrap_err().code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_kdf_params_returned_with_challenge() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, _) = setup_zkp();
+        let username = format!("kdf_{}", uuid::Uuid::new_v4());
+        let kdf = KdfParams::generate();
+        let x = kdf.derive_secret("correct horse", &zkp.q).unwrap();
+
+        client
+            .register(RegisterRequest {
+                name: username.clone(),
+                y1: zkp.exponentiate(&zkp.alpha, &x).to_bytes_be(),
+                y2: zkp.exponentiate(&zkp.beta, &x).to_bytes_be(),
+                param_set: zkp.name().to_string(),
+                kdf: Some(kdf.clone().into()),
+            })
+            .await
+            .unwrap();
+
+        let k = ZKP::generate_random_below(&zkp.q);
+        let challenge = client
+            .create_authentication_challenge(AuthenticationChallengeRequest {
+                name: username,
+                r1: zkp.exponentiate(&zkp.alpha, &k).to_bytes_be(),
+                r2: zkp.exponentiate(&zkp.beta, &k).to_bytes_be(),
+                param_set: zkp.name().to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        let returned = KdfParams::from(challenge.kdf.expect("kdf params missing"));
+        assert_eq!(returned, kdf);
+
+        let x = returned.derive_secret("correct horse", &zkp.q).unwrap();
+        let s = zkp.solve(&k, &BigUint::from_bytes_be(&challenge.c), &x);
+        let res = client
+            .verify_authentication(AuthenticationAnswerRequest {
+                auth_id: challenge.auth_id,
+                s: s.to_bytes_be(),
+                issue_token: false,
+            })
+            .await;
+        assert!(res.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_register_rejects_weak_kdf() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let weak = KdfParams {
+            memory_kib: 16,
+            ..KdfParams::generate()
+        };
+        let res = client
+            .register(RegisterRequest {
+                name: format!("weak_{}", uuid::Uuid::new_v4()),
+                y1: zkp.exponentiate(&zkp.alpha, &password).to_bytes_be(),
+                y2: zkp.exponentiate(&zkp.beta, &password).to_bytes_be(),
+                param_set: zkp.name().to_string(),
+                kdf: Some(weak.into()),
+            })
+            .await;
+        assert_eq!(res.unwrap_err().code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_new_credentials_require_kdf() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        for kdf in [None, Some(KdfParams::legacy().into())] {
+            let res = client
+                .register(RegisterRequest {
+                    name: username.clone(),
+                    y1: zkp.exponentiate(&zkp.alpha, &password).to_bytes_be(),
+                    y2: zkp.exponentiate(&zkp.beta, &password).to_bytes_be(),
+                    param_set: zkp.name().to_string(),
+                    kdf: kdf.clone(),
+                })
+                .await;
+            assert_eq!(res.unwrap_err().code(), tonic::Code::InvalidArgument);
+        }
+
+        register_user(&mut client, &zkp, &username, &password).await;
+        let new_password = ZKP::generate_random_below(&zkp.q);
+        for new_kdf in [None, Some(KdfParams::legacy().into())] {
+            let request = zkp_auth::RotateCredentialRequest {
+                new_kdf,
+                ..rotation_request(&zkp, &username, &password, &new_password, false)
+            };
+            let err = client.rotate_credential(request).await.unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        }
+        assert!(login(&mut client, &zkp, &username, &password)
+            .await
+            .is_some());
+    }
+
+    fn now_ms() -> u64 {
+        Utc::now().timestamp_millis() as u64
+    }
+
+    async fn authenticate_non_interactive(
+        client: &mut AuthClient<tonic::transport::Channel>,
+        zkp: &ZKP,
+        username: &str,
+        password: &BigUint,
+        timestamp_ms: u64,
+    ) -> Result<String, Status> {
+        let k = ZKP::generate_random_below(&zkp.q);
+        let context = ZKP::fiat_shamir_context(username, timestamp_ms);
+        let (r1, r2, s) = zkp.prove_non_interactive(password, &k, &context);
+
+        client
+            .authenticate_non_interactive(NonInteractiveAuthenticationRequest {
+                name: username.into(),
+                r1: r1.to_bytes_be(),
+                r2: r2.to_bytes_be(),
+                s: s.to_bytes_be(),
+                timestamp_ms,
+                param_set: zkp.name().to_string(),
+                issue_token: false,
+            })
+            .await
+            .map(|res| res.into_inner().session_id)
+    }
+
+    #[tokio::test]
+    async fn test_non_interactive_auth() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut client, &zkp, &username, &password).await;
+
+        let session_id =
+            authenticate_non_interactive(&mut client, &zkp, &username, &password, now_ms())
+                .await
+                .expect("non-interactive auth failed");
+
+        let res = client
+            .validate_session(zkp_auth::ValidateSessionRequest { session_id })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(res.valid);
+
+        let wrong_password = ZKP::generate_random_below(&zkp.q);
+        let res =
+            authenticate_non_interactive(&mut client, &zkp, &username, &wrong_password, now_ms())
+                .await;
+        assert_eq!(res.unwrap_err().code(), tonic::Code::PermissionDenied);
+    }
+
+    #[test]
+    fn test_check_proof_time_bounds() {
+        assert!(AuthImpl::check_proof_time("alice", now_ms()).is_ok());
+        for timestamp_ms in [0, i64::MAX as u64, i64::MAX as u64 + 1, u64::MAX] {
+            assert!(matches!(
+                AuthImpl::check_proof_time("alice", timestamp_ms),
+                Err(AuthError::StaleProof(user)) if user == "alice"
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_interactive_stale_and_replayed() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut client, &zkp, &username, &password).await;
+
+        let stale = now_ms() - 2 * NON_INTERACTIVE_WINDOW.as_millis() as u64;
+        let res =
+            authenticate_non_interactive(&mut client, &zkp, &username, &password, stale).await;
+        assert_eq!(res.unwrap_err().code(), tonic::Code::InvalidArgument);
+        // Timestamps past i64::MAX are stale rather than an overflow
+        for stale in [u64::MAX, i64::MAX as u64 + 1] {
+            let res =
+                authenticate_non_interactive(&mut client, &zkp, &username, &password, stale).await;
+            assert_eq!(res.unwrap_err().code(), tonic::Code::InvalidArgument);
+        }
+
+        let timestamp_ms = now_ms();
+        let k = ZKP::generate_random_below(&zkp.q);
+        let context = ZKP::fiat_shamir_context(&username, timestamp_ms);
+        let (r1, r2, s) = zkp.prove_non_interactive(&password, &k, &context);
+        let req = NonInteractiveAuthenticationRequest {
+            name: username,
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            s: s.to_bytes_be(),
+            timestamp_ms,
+            param_set: zkp.name().to_string(),
+            issue_token: false,
+        };
+
+        client
+            .authenticate_non_interactive(req.clone())
+            .await
+            .unwrap();
+        let res = client.authenticate_non_interactive(req.clone()).await;
+        assert_eq!(res.unwrap_err().code(), tonic::Code::PermissionDenied);
+
+        // Leading zeros on r1 parse to the same element and are the same proof
+        let mut padded = req;
+        padded.r1.insert(0, 0);
+        let err = client
+            .authenticate_non_interactive(padded)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        assert!(err.message().contains("already used"));
+    }
+
+    fn rotation_request(
+        zkp: &ZKP,
+        username: &str,
+        old_password: &BigUint,
+        new_password: &BigUint,
+        revoke_sessions: bool,
+    ) -> zkp_auth::RotateCredentialRequest {
+        let new_y1 = zkp.exponentiate(&zkp.alpha, new_password);
+        let new_y2 = zkp.exponentiate(&zkp.beta, new_password);
+        let new_kdf = KdfParams::generate();
+        let timestamp_ms = now_ms();
+        let context = zkp.rotation_context(username, timestamp_ms, &new_y1, &new_y2, &new_kdf);
+        let k = ZKP::generate_random_below(&zkp.q);
+        let (r1, r2, s) = zkp.prove_non_interactive(old_password, &k, &context);
+        zkp_auth::RotateCredentialRequest {
+            name: username.into(),
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            s: s.to_bytes_be(),
+            timestamp_ms,
+            new_y1: new_y1.to_bytes_be(),
+            new_y2: new_y2.to_bytes_be(),
+            new_kdf: Some(new_kdf.into()),
+            revoke_sessions,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotate_credential() {
+        let endpoint = spawn_test_server().await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut client, &zkp, &username, &password).await;
+        let old_session = login(&mut client, &zkp, &username, &password)
+            .await
+            .expect("auth failed");
+        let new_password = ZKP::generate_random_below(&zkp.q);
+
+        // A proof with the wrong current secret changes nothing
+        let wrong = ZKP::generate_random_below(&zkp.q);
+        let err = client
+            .rotate_credential(rotation_request(
+                &zkp,
+                &username,
+                &wrong,
+                &new_password,
+                true,
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        assert!(login(&mut client, &zkp, &username, &password)
+            .await
+            .is_some());
+
+        let request = rotation_request(&zkp, &username, &password, &new_password, true);
+        let res = client
+            .rotate_credential(request.clone())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(res.revoked, 2);
+        let err = client.rotate_credential(request.clone()).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        // A zero-padded r1 is still the same proof
+        let mut padded = request;
+        padded.r1.insert(0, 0);
+        let err = client.rotate_credential(padded).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        assert!(err.message().contains("already used"));
+
+        let validated = client
+            .validate_session(zkp_auth::ValidateSessionRequest {
+                session_id: old_session.session_id,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!validated.valid);
+        assert!(login(&mut client, &zkp, &username, &password)
+            .await
+            .is_none());
+        assert!(login(&mut client, &zkp, &username, &new_password)
+            .await
+            .is_some());
+
+        // Without revoke_sessions existing sessions survive
+        let current = login(&mut client, &zkp, &username, &new_password)
+            .await
+            .expect("auth failed");
+        let res = client
+            .rotate_credential(rotation_request(
+                &zkp,
+                &username,
+                &new_password,
+                &password,
+                false,
+            ))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(res.revoked, 0);
+        let validated = client
+            .validate_session(zkp_auth::ValidateSessionRequest {
+                session_id: current.session_id,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(validated.valid);
+    }
+
+    fn deletion_request(
+        zkp: &ZKP,
+        username: &str,
+        password: &BigUint,
+        anonymize_logs: bool,
+    ) -> zkp_auth::DeleteAccountRequest {
+        let timestamp_ms = now_ms();
+        let context = ZKP::deletion_context(username, timestamp_ms, anonymize_logs);
+        let k = ZKP::generate_random_below(&zkp.q);
+        let (r1, r2, s) = zkp.prove_non_interactive(password, &k, &context);
+        zkp_auth::DeleteAccountRequest {
+            name: username.into(),
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            s: s.to_bytes_be(),
+            timestamp_ms,
+            anonymize_logs,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_and_delete_account() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = PgPool::connect(&env::var("DATABASE_URL").unwrap())
+            .await
+            .unwrap();
+        let limiter = Arc::new(PgRateLimiter::new(pool.clone(), RateLimitConfig::default()));
+        let endpoint = spawn_test_server_with_rate_limiter(limiter).await;
+        let mut client = AuthClient::connect(endpoint).await.unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut client, &zkp, &username, &password).await;
+        let session = login(&mut client, &zkp, &username, &password)
+            .await
+            .expect("auth failed");
+
+        let export = client
+            .export_account_data(zkp_auth::ExportAccountDataRequest {
+                session_id: session.session_id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .json;
+        let export: serde_json::Value = serde_json::from_str(&export).unwrap();
+        assert_eq!(export["user"]["user_name"], username.as_str());
+        assert_eq!(export["user"]["param_set"], zkp.name());
+        assert_eq!(
+            export["sessions"][0]["id"],
+            ids::hash_token(&session.session_id).as_str()
+        );
+        assert_eq!(export["auth_logs"].as_array().unwrap().len(), 1);
+        assert_eq!(export["auth_logs"][0]["success"], true);
+        assert_eq!(export["lockout_events"].as_array().unwrap().len(), 0);
+
+        // The proof is bound to the anonymisation choice
+        let mut tampered = deletion_request(&zkp, &username, &password, false);
+        tampered.anonymize_logs = true;
+        let err = client.delete_account(tampered).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        let user_key = RateLimitKey::User {
+            user_name: username.clone(),
+            ip: "127.0.0.1".to_string(),
+        }
+        .storage_key();
+        let rate_limit = |pool: PgPool, key: String| async move {
+            let mut tx = pool.begin().await.unwrap();
+            db::get_rate_limit(&mut tx, &key).await.unwrap()
+        };
+        assert!(rate_limit(pool.clone(), user_key.clone()).await.is_some());
+        let reason = format!("audit_{}", uuid::Uuid::new_v4());
+        let mut tx = pool.begin().await.unwrap();
+        db::insert_lockout_event(
+            &mut tx,
+            &db::LockoutEvent {
+                user_name: username.clone(),
+                event: "locked".to_string(),
+                reason: Some(reason.clone()),
+                actor: "admin".to_string(),
+                created_at: Utc::now(),
+            },
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        let deletion = deletion_request(&zkp, &username, &password, true);
+        let res = client
+            .delete_account(deletion.clone())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(res.logs_anonymized, 2);
+        // No rate limiting state is left behind for the name
+        assert!(rate_limit(pool.clone(), user_key).await.is_none());
+        // The lockout audit trail is kept under an alias
+        let kept: String =
+            sqlx::query_scalar("SELECT user_name FROM lockout_events WHERE reason = $1")
+                .bind(&reason)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert!(kept.starts_with("deleted_"));
+
+        let validated = client
+            .validate_session(zkp_auth::ValidateSessionRequest {
+                session_id: session.session_id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!validated.valid);
+        let err = client
+            .export_account_data(zkp_auth::ExportAccountDataRequest {
+                session_id: session.session_id,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        assert!(login(&mut client, &zkp, &username, &password)
+            .await
+            .is_none());
+        let err = client
+            .delete_account(deletion_request(&zkp, &username, &password, false))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+
+        // The name is free again, and the spent proof cannot delete the new
+        // account, even with a zero-padded r1
+        register_user(&mut client, &zkp, &username, &password).await;
+        let mut tx = pool.begin().await.unwrap();
+        assert!(db::get_lockout_events_by_user(&mut tx, &username)
+            .await
+            .unwrap()
+            .is_empty());
+        tx.rollback().await.unwrap();
+        let mut padded = deletion;
+        padded.r1.insert(0, 0);
+        let err = client.delete_account(padded).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        assert!(err.message().contains("already used"));
+    }
+
+    #[tokio::test]
+    async fn test_challenge_answered_by_another_instance() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = PgPool::connect(&env::var("DATABASE_URL").unwrap())
+            .await
+            .unwrap();
+        let mut first = AuthClient::connect(
+            spawn_test_server_with_challenges(Arc::new(PgChallengeStore::new(
+                pool.clone(),
+                ChallengeLimits::default(),
+            )))
+            .await,
+        )
+        .await
+        .unwrap();
+        let mut second = AuthClient::connect(
+            spawn_test_server_with_challenges(Arc::new(PgChallengeStore::new(
+                pool,
+                ChallengeLimits::default(),
+            )))
+            .await,
+        )
+        .await
+        .unwrap();
+        let mut separate = AuthClient::connect(spawn_test_server().await)
+            .await
+            .unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut first, &zkp, &username, &password).await;
+
+        let k = ZKP::generate_random_below(&zkp.q);
+        let issued = first
+            .create_authentication_challenge(AuthenticationChallengeRequest {
+                name: username.clone(),
+                r1: zkp.exponentiate(&zkp.alpha, &k).to_bytes_be(),
+                r2: zkp.exponentiate(&zkp.beta, &k).to_bytes_be(),
+                param_set: zkp.name().to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let answer = AuthenticationAnswerRequest {
+            auth_id: issued.auth_id,
+            s: zkp
+                .solve(&k, &BigUint::from_bytes_be(&issued.c), &password)
+                .to_bytes_be(),
+            issue_token: false,
+        };
+
+        // An instance with its own in-memory store has never seen the challenge
+        let err = separate
+            .verify_authentication(answer.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        assert!(second.verify_authentication(answer.clone()).await.is_ok());
+        let err = first.verify_authentication(answer).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_proof_replayed_to_another_instance() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = PgPool::connect(&env::var("DATABASE_URL").unwrap())
+            .await
+            .unwrap();
+        let mut first = AuthClient::connect(
+            spawn_test_server_with_challenges(Arc::new(PgChallengeStore::new(
+                pool.clone(),
+                ChallengeLimits::default(),
+            )))
+            .await,
+        )
+        .await
+        .unwrap();
+        let mut second = AuthClient::connect(
+            spawn_test_server_with_challenges(Arc::new(PgChallengeStore::new(
+                pool,
+                ChallengeLimits::default(),
+            )))
+            .await,
+        )
+        .await
+        .unwrap();
+
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut first, &zkp, &username, &password).await;
+
+        let timestamp_ms = now_ms();
+        let k = ZKP::generate_random_below(&zkp.q);
+        let context = ZKP::fiat_shamir_context(&username, timestamp_ms);
+        let (r1, r2, s) = zkp.prove_non_interactive(&password, &k, &context);
+        let req = NonInteractiveAuthenticationRequest {
+            name: username,
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            s: s.to_bytes_be(),
+            timestamp_ms,
+            param_set: zkp.name().to_string(),
+            issue_token: false,
+        };
+
+        // A wrong s under the same r1 does not use up the proof id
+        let forged = NonInteractiveAuthenticationRequest {
+            s: vec![1],
+            ..req.clone()
+        };
+        let err = second
+            .authenticate_non_interactive(forged)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+        first
+            .authenticate_non_interactive(req.clone())
+            .await
+            .unwrap();
+        let err = second.authenticate_non_interactive(req).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_challenges_evict_oldest_per_user() {
+        let mut client = AuthClient::connect(spawn_test_server().await)
+            .await
+            .unwrap();
+        let (zkp, password) = setup_zkp();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut client, &zkp, &username, &password).await;
+
+        let k = ZKP::generate_random_below(&zkp.q);
+        let request = AuthenticationChallengeRequest {
+            name: username.clone(),
+            r1: zkp.exponentiate(&zkp.alpha, &k).to_bytes_be(),
+            r2: zkp.exponentiate(&zkp.beta, &k).to_bytes_be(),
+            param_set: zkp.name().to_string(),
+        };
+        let mut auth_ids = Vec::new();
+        for _ in 0..ChallengeLimits::default().max_per_user {
+            let challenge = client
+                .create_authentication_challenge(request.clone())
+                .await
+                .unwrap()
+                .into_inner();
+            auth_ids.push(challenge.auth_id);
+        }
+
+        // Filling a user's cap does not block their login; it only drops the
+        // oldest unanswered challenge
+        assert!(login(&mut client, &zkp, &username, &password)
+            .await
+            .is_some());
+        assert!(client
+            .verify_authentication(AuthenticationAnswerRequest {
+                auth_id: auth_ids[0].clone(),
+                s: vec![1],
+                issue_token: false,
+            })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failures_from_several_addresses() {
+        let (zkp, password) = setup_zkp();
+        let wrong = ZKP::generate_random_below(&zkp.q);
+        let addresses = ["127.0.0.2", "127.0.0.3", "127.0.0.4"];
+
+        // Lockout is off by default, so failures spread over addresses, each
+        // under its rate limit, leave the owner able to log in
+        let endpoint = spawn_test_server().await;
+        let mut owner = AuthClient::connect(endpoint.clone()).await.unwrap();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut owner, &zkp, &username, &password).await;
+        for ip in addresses {
+            let mut attacker = AuthClient::new(connect_from(&endpoint, ip).await);
+            for _ in 1..DEFAULT_MAX_ATTEMPTS {
+                assert!(login(&mut attacker, &zkp, &username, &wrong)
+                    .await
+                    .is_none());
+            }
+        }
+        assert!(login(&mut owner, &zkp, &username, &password)
+            .await
+            .is_some());
+
+        // Once enabled, the same failures add up to a lock on the owner
+        let policy = LockoutPolicy {
+            threshold: 6,
+            ..Default::default()
+        };
+        let endpoint = spawn_test_server_with_lockout(policy).await;
+        let mut owner = AuthClient::connect(endpoint.clone()).await.unwrap();
+        let username = format!("user_{}", uuid::Uuid::new_v4());
+        register_user(&mut owner, &zkp, &username, &password).await;
+        for ip in &addresses[..2] {
+            let mut attacker = AuthClient::new(connect_from(&endpoint, ip).await);
+            for _ in 0..policy.threshold / 2 {
+                assert!(login(&mut attacker, &zkp, &username, &wrong)
+                    .await
+                    .is_none());
+            }
+        }
+        let err = owner
+            .create_authentication_challenge(AuthenticationChallengeRequest {
+                name: username,
+                r1: zkp.alpha.to_bytes_be(),
+                r2: zkp.beta.to_bytes_be(),
+                param_set: zkp.name().to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_by_client_address_and_globally() {
+        let (zkp, password) = setup_zkp();
+        let wrong = ZKP::generate_random_below(&zkp.q);
+        let challenge_for = |name: &str| AuthenticationChallengeRequest {
+            name: name.to_string(),
+            r1: zkp.alpha.to_bytes_be(),
+            r2: zkp.beta.to_bytes_be(),
+            param_set: zkp.name().to_string(),
+        };
+
+        // Spraying one wrong secret across users blocks the client address
+        let config = RateLimitConfig {
+            ip: RateLimitPolicy {
+                max_attempts: 3,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let limiter = Arc::new(MemoryRateLimiter::new(config));
+        let mut client =
+            AuthClient::connect(spawn_test_server_with_rate_limiter(limiter.clone()).await)
+                .await
+                .unwrap();
+        let names: Vec<String> = (0..4)
+            .map(|_| format!("user_{}", uuid::Uuid::new_v4()))
+            .collect();
+        for name in &names {
+            register_user(&mut client, &zkp, name, &password).await;
+        }
+        for name in &names[..3] {
+            assert!(login(&mut client, &zkp, name, &wrong).await.is_none());
+        }
+        let err = client
+            .create_authentication_challenge(challenge_for(&names[3]))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+        assert!(err.message().contains("127.0.0.1"));
+
+        // Failures from an attacker's address do not lock out the owner
+        let attacker = ClientInfo {
+            addr: Some("203.0.113.7:4444".to_string()),
+            user_agent: None,
+        };
+        let owner = format!("user_{}", uuid::Uuid::new_v4());
+        let mut client =
+            AuthClient::connect(spawn_test_server_with_rate_limiter(limiter.clone()).await)
+                .await
+                .unwrap();
+        limiter
+            .reset(&RateLimitKey::Ip("127.0.0.1".to_string()))
+            .await
+            .unwrap();
+        register_user(&mut client, &zkp, &owner, &password).await;
+        let [attacker_key, ..] = AuthImpl::rate_limit_keys(&owner, &attacker);
+        for _ in 0..config.user.max_attempts {
+            limiter.record_failure(&attacker_key).await.unwrap();
+        }
+        assert!(limiter
+            .blocked_until(&attacker_key)
+            .await
+            .unwrap()
+            .is_some());
+        assert!(login(&mut client, &zkp, &owner, &password).await.is_some());
+
+        // The global budget blocks everyone once it is spent
+        let config = RateLimitConfig {
+            global: RateLimitPolicy {
+                max_attempts: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut client = AuthClient::connect(
+            spawn_test_server_with_rate_limiter(Arc::new(MemoryRateLimiter::new(config))).await,
+        )
+        .await
+        .unwrap();
+        for name in &names[..2] {
+            assert!(login(&mut client, &zkp, name, &wrong).await.is_none());
+        }
+        let err = client
+            .create_authentication_challenge(challenge_for(&names[3]))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(err.message(), AuthError::GloballyRateLimited.to_string());
+    }
 }
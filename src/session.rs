@@ -0,0 +1,276 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::{CryptoRng, RngCore};
+use sqlx::{Postgres, Transaction};
+use std::env;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+use crate::db::{self, RefreshToken, Session};
+use crate::ids::{self, IdKind};
+
+/// Default lifetime of one access session
+pub const DEFAULT_ACCESS_TTL_SECS: i64 = 3600;
+/// Default time after which an unused session lapses
+pub const DEFAULT_IDLE_TIMEOUT_SECS: i64 = 1800;
+/// Default deadline of a login, however often it is refreshed
+pub const DEFAULT_ABSOLUTE_TTL_SECS: i64 = 24 * 3600;
+/// Default number of days ended sessions are kept for auditing
+pub const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// Why a session was revoked; stored in `sessions.revoke_reason`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevokeReason {
+    /// The client logged out
+    Logout,
+    /// The user revoked all their sessions
+    RevokeAll,
+    /// A used refresh token was presented again
+    RefreshReuse,
+    /// The user rotated their credential and asked to sign out everywhere
+    CredentialRotated,
+    /// An operator revoked the user's sessions through the admin service
+    Admin,
+}
+
+impl RevokeReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RevokeReason::Logout => "logout",
+            RevokeReason::RevokeAll => "revoke_all",
+            RevokeReason::RefreshReuse => "refresh_reuse",
+            RevokeReason::CredentialRotated => "credential_rotated",
+            RevokeReason::Admin => "admin",
+        }
+    }
+}
+
+/// How long sessions and their refresh families live
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionPolicy {
+    pub access_ttl: Duration,
+    pub idle_timeout: Duration,
+    pub absolute_ttl: Duration,
+    /// How long expired and revoked sessions are kept before purging
+    pub retention: Duration,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        SessionPolicy {
+            access_ttl: Duration::seconds(DEFAULT_ACCESS_TTL_SECS),
+            idle_timeout: Duration::seconds(DEFAULT_IDLE_TIMEOUT_SECS),
+            absolute_ttl: Duration::seconds(DEFAULT_ABSOLUTE_TTL_SECS),
+            retention: Duration::days(DEFAULT_RETENTION_DAYS),
+        }
+    }
+}
+
+fn env_secs(name: &str, default: i64) -> Duration {
+    Duration::seconds(
+        env::var(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default),
+    )
+}
+
+impl SessionPolicy {
+    /// Reads `ZKP_SESSION_TTL_SECS`, `ZKP_SESSION_IDLE_SECS`,
+    /// `ZKP_SESSION_ABSOLUTE_SECS` and `ZKP_SESSION_RETENTION_DAYS`, falling
+    /// back to the defaults
+    pub fn from_env() -> Self {
+        SessionPolicy {
+            access_ttl: env_secs("ZKP_SESSION_TTL_SECS", DEFAULT_ACCESS_TTL_SECS),
+            idle_timeout: env_secs("ZKP_SESSION_IDLE_SECS", DEFAULT_IDLE_TIMEOUT_SECS),
+            absolute_ttl: env_secs("ZKP_SESSION_ABSOLUTE_SECS", DEFAULT_ABSOLUTE_TTL_SECS),
+            retention: env_secs("ZKP_SESSION_RETENTION_DAYS", DEFAULT_RETENTION_DAYS) * 86400,
+        }
+    }
+
+    /// When a session lapses: at its own expiry or after the idle timeout,
+    /// whichever comes first
+    pub fn expires_at(&self, session: &Session) -> DateTime<Utc> {
+        session
+            .expires_at
+            .min(session.last_used_at + self.idle_timeout)
+    }
+
+    /// Whether a session may still be used at `now`
+    pub fn is_live(&self, session: &Session, now: DateTime<Utc>) -> bool {
+        session.is_active && self.expires_at(session) > now
+    }
+}
+
+/// Length of the prefix an IPv6 client address is limited by
+const IPV6_PREFIX_LEN: u32 = 64;
+
+/// Where a request came from, recorded with the sessions it creates
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub addr: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_request<T>(request: &tonic::Request<T>) -> Self {
+        ClientInfo {
+            addr: request.remote_addr().map(|addr| addr.to_string()),
+            user_agent: request
+                .metadata()
+                .get("user-agent")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+        }
+    }
+
+    /// The client's IP address without the port, which changes with every
+    /// connection; `"unknown"` when the transport does not report one.
+    /// IPv6 addresses collapse to their /64, since one host usually holds
+    /// the whole prefix and could otherwise rotate through it.
+    pub fn ip(&self) -> String {
+        self.addr
+            .as_deref()
+            .and_then(|addr| addr.parse::<SocketAddr>().ok())
+            .map(|addr| match addr.ip().to_canonical() {
+                IpAddr::V4(ip) => ip.to_string(),
+                IpAddr::V6(ip) => {
+                    let prefix = u128::from(ip) & !(u128::MAX >> IPV6_PREFIX_LEN);
+                    format!("{}/{}", Ipv6Addr::from(prefix), IPV6_PREFIX_LEN)
+                }
+            })
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+/// A session handed to the client, with the refresh token that renews it
+#[derive(Debug, Clone)]
+pub struct IssuedSession {
+    pub user_name: String,
+    pub session_id: String,
+    pub refresh_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The refresh family a new session joins, and its absolute deadline
+#[derive(Debug, Clone)]
+pub struct Family {
+    pub id: String,
+    pub deadline: DateTime<Utc>,
+}
+
+impl SessionPolicy {
+    /// Stores a new access session and refresh token. Without `family` this
+    /// starts a new family whose deadline is `absolute_ttl` from now.
+    pub async fn create_session<R: CryptoRng + RngCore>(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        rng: &mut R,
+        user_name: &str,
+        auth_id: &str,
+        family: Option<Family>,
+        client: &ClientInfo,
+    ) -> Result<IssuedSession, sqlx::Error> {
+        let now = Utc::now();
+        let family = family.unwrap_or_else(|| Family {
+            id: ids::generate_id(IdKind::Family, rng),
+            deadline: now + self.absolute_ttl,
+        });
+        let session_id = ids::generate_id(IdKind::Session, rng);
+        let refresh_token = ids::generate_id(IdKind::Refresh, rng);
+        let expires_at = (now + self.access_ttl).min(family.deadline);
+
+        db::insert_session(
+            tx,
+            Session {
+                token_hash: ids::hash_token(&session_id),
+                user_name: user_name.to_string(),
+                auth_id: auth_id.to_string(),
+                created_at: now,
+                expires_at,
+                last_used_at: now,
+                family_id: family.id.clone(),
+                client_addr: client.addr.clone(),
+                user_agent: client.user_agent.clone(),
+                is_active: true,
+                revoked_at: None,
+                revoke_reason: None,
+            },
+        )
+        .await?;
+        db::insert_refresh_token(
+            tx,
+            RefreshToken {
+                token_hash: ids::hash_token(&refresh_token),
+                family_id: family.id,
+                user_name: user_name.to_string(),
+                created_at: now,
+                expires_at: family.deadline,
+                used_at: None,
+                revoked_at: None,
+            },
+        )
+        .await?;
+
+        Ok(IssuedSession {
+            user_name: user_name.to_string(),
+            session_id,
+            refresh_token,
+            expires_at: expires_at.min(now + self.idle_timeout),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_ip() {
+        let ip = |addr: Option<&str>| {
+            ClientInfo {
+                addr: addr.map(str::to_string),
+                user_agent: None,
+            }
+            .ip()
+        };
+        assert_eq!(ip(Some("10.0.0.1:4000")), "10.0.0.1");
+        assert_eq!(ip(Some("[::ffff:10.0.0.1]:4000")), "10.0.0.1");
+        assert_eq!(ip(None), "unknown");
+
+        // Every address in one /64 shares a key
+        assert_eq!(ip(Some("[2001:db8:1:2::1]:4000")), "2001:db8:1:2::/64");
+        assert_eq!(
+            ip(Some("[2001:db8:1:2:ffff:abcd:1234:5678]:4001")),
+            "2001:db8:1:2::/64"
+        );
+        assert_eq!(ip(Some("[2001:db8:1:3::1]:4000")), "2001:db8:1:3::/64");
+    }
+
+    #[test]
+    fn test_expiry_is_earliest_deadline() {
+        let policy = SessionPolicy::default();
+        let now = Utc::now();
+        let mut session = Session {
+            token_hash: String::new(),
+            user_name: String::new(),
+            auth_id: String::new(),
+            created_at: now,
+            expires_at: now + policy.access_ttl,
+            last_used_at: now,
+            family_id: String::new(),
+            client_addr: None,
+            user_agent: None,
+            is_active: true,
+            revoked_at: None,
+            revoke_reason: None,
+        };
+        assert_eq!(policy.expires_at(&session), now + policy.idle_timeout);
+        assert!(policy.is_live(&session, now));
+
+        // Use slides the idle deadline up to the session's own expiry
+        session.last_used_at = now + Duration::minutes(50);
+        assert_eq!(policy.expires_at(&session), now + policy.access_ttl);
+
+        session.is_active = false;
+        assert!(!policy.is_live(&session, now));
+    }
+}
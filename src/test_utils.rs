@@ -1,29 +1,74 @@
-use dashmap::DashMap;
+use crate::admin::{AdminCredential, AdminImpl};
+use crate::batch::{self, BatchVerifier};
+use crate::challenge::{ChallengeLimits, ChallengeStore, MemoryChallengeStore};
+use crate::rate_limit::{MemoryRateLimiter, RateLimitConfig, RateLimiter};
+use crate::server::AuthImpl;
+use crate::zkp_auth::{admin_server::AdminServer, auth_server::AuthServer};
+use crate::{
+    lockout::LockoutPolicy, params, session::SessionPolicy, token::TokenSigner, SharedRng, ZKP,
+};
+use num_bigint::BigUint;
 use sqlx::PgPool;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::net::{TcpSocket, TcpStream};
 use tokio_stream::wrappers::TcpListenerStream;
-use tonic::transport::Server;
-use crate::zkp_auth::auth_server::AuthServer;
-use crate::server::{AuthImpl};
-use crate::ZKP;
-use num_bigint::BigUint;
+use tonic::codegen::Service;
+use tonic::transport::{Channel, Endpoint, Server, Uri};
 
 pub async fn spawn_test_server() -> String {
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
-        .await
-        .unwrap();
+    spawn_test_server_with_rng(SharedRng::from_entropy()).await
+}
 
-    let addr = listener.local_addr().unwrap();
+fn test_auth_impl(db_pool: PgPool, rng: SharedRng) -> AuthImpl {
+    AuthImpl {
+        db: db_pool,
+        challenges: Arc::new(MemoryChallengeStore::new(ChallengeLimits::default())),
+        rate_limiter: Arc::new(MemoryRateLimiter::new(RateLimitConfig::default())),
+        default_param_set: params::DEFAULT_PARAM_SET.to_string(),
+        batch_verifier: BatchVerifier::spawn(batch::DEFAULT_MAX_BATCH, batch::DEFAULT_MAX_WAIT),
+        token_signer: Some(Arc::new(TokenSigner::generate(
+            &mut SharedRng::from_entropy(),
+        ))),
+        rng,
+        session_policy: SessionPolicy::default(),
+        lockout_policy: LockoutPolicy::default(),
+    }
+}
 
+async fn test_db_pool() -> PgPool {
     dotenvy::from_filename(".env.test").ok();
     let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let db_pool = PgPool::connect(&db_url).await.unwrap();
+    PgPool::connect(&db_url).await.unwrap()
+}
 
-    let auth_impl = AuthImpl {
-        db: db_pool,
-        session_info: DashMap::new(),
-        rate_limit_info: DashMap::new(),
-    };
+/// Starts a server whose challenges and ids all come from `rng`
+pub async fn spawn_test_server_with_rng(rng: SharedRng) -> String {
+    serve(test_auth_impl(test_db_pool().await, rng)).await
+}
+
+/// Starts a server that keeps pending challenges in `challenges`
+pub async fn spawn_test_server_with_challenges(challenges: Arc<dyn ChallengeStore>) -> String {
+    let mut auth_impl = test_auth_impl(test_db_pool().await, SharedRng::from_entropy());
+    auth_impl.challenges = challenges;
+    serve(auth_impl).await
+}
+
+/// Starts a server that counts failed attempts in `rate_limiter`
+pub async fn spawn_test_server_with_rate_limiter(rate_limiter: Arc<dyn RateLimiter>) -> String {
+    let mut auth_impl = test_auth_impl(test_db_pool().await, SharedRng::from_entropy());
+    auth_impl.rate_limiter = rate_limiter;
+    serve(auth_impl).await
+}
+
+async fn serve(auth_impl: AuthImpl) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+    let addr = listener.local_addr().unwrap();
 
     let server = Arc::new(auth_impl);
 
@@ -38,12 +83,98 @@ pub async fn spawn_test_server() -> String {
     format!("http://{}", addr)
 }
 
-pub fn setup_zkp() -> (ZKP, BigUint) {
-    let (alpha, beta, p, q) = ZKP::get_constants();
+/// Starts a server that locks accounts according to `lockout_policy`
+pub async fn spawn_test_server_with_lockout(lockout_policy: LockoutPolicy) -> String {
+    let mut auth_impl = test_auth_impl(test_db_pool().await, SharedRng::from_entropy());
+    auth_impl.lockout_policy = lockout_policy;
+    serve(auth_impl).await
+}
 
-    let zkp = ZKP { p, q, alpha, beta };
+/// Opens connections from a chosen local address, so that a test can act as
+/// several clients; any address in 127.0.0.0/8 reaches a loopback server
+#[derive(Clone)]
+struct LocalAddrConnector(IpAddr);
+
+impl Service<Uri> for LocalAddrConnector {
+    type Response = TcpStream;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<TcpStream>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let local = SocketAddr::new(self.0, 0);
+        Box::pin(async move {
+            let authority = uri
+                .authority()
+                .map(|authority| authority.as_str())
+                .unwrap_or_default();
+            let remote: SocketAddr = authority.parse().map_err(io::Error::other)?;
+            let socket = TcpSocket::new_v4()?;
+            socket.bind(local)?;
+            socket.connect(remote).await
+        })
+    }
+}
+
+/// Connects to `endpoint` from the local address `ip`
+pub async fn connect_from(endpoint: &str, ip: &str) -> Channel {
+    Endpoint::from_shared(endpoint.to_string())
+        .unwrap()
+        .connect_with_connector(LocalAddrConnector(ip.parse().unwrap()))
+        .await
+        .unwrap()
+}
+
+/// Starts a server and an admin server sharing its state; returns both endpoints
+pub async fn spawn_test_admin_server(admin_token: &str) -> (String, String) {
+    spawn_test_admin_server_with_lockout(admin_token, LockoutPolicy::default()).await
+}
+
+/// `spawn_test_admin_server` with accounts locked according to `lockout_policy`
+pub async fn spawn_test_admin_server_with_lockout(
+    admin_token: &str,
+    lockout_policy: LockoutPolicy,
+) -> (String, String) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let admin_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoints = (
+        format!("http://{}", listener.local_addr().unwrap()),
+        format!("http://{}", admin_listener.local_addr().unwrap()),
+    );
+
+    let mut auth_impl = test_auth_impl(test_db_pool().await, SharedRng::from_entropy());
+    auth_impl.lockout_policy = lockout_policy;
+    let server = Arc::new(auth_impl);
+    let admin = AdminImpl {
+        auth: Arc::clone(&server),
+        credential: AdminCredential::new(admin_token),
+    };
+
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(AuthServer::new(server))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(AdminServer::new(admin))
+            .serve_with_incoming(TcpListenerStream::new(admin_listener))
+            .await
+            .unwrap();
+    });
+
+    endpoints
+}
+
+pub fn setup_zkp() -> (ZKP, BigUint) {
+    let zkp = params::default_param_set();
 
     let password = ZKP::generate_random_below(&zkp.q);
 
     (zkp, password)
-}
\ No newline at end of file
+}
@@ -0,0 +1,287 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use thiserror::Error;
+
+/// JWS algorithm name for Ed25519
+pub const TOKEN_ALG: &str = "EdDSA";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TokenError {
+    #[error("malformed token: {0}")]
+    Malformed(String),
+    #[error("unknown key id {0}")]
+    UnknownKey(String),
+    #[error("bad signature")]
+    BadSignature,
+    #[error("token expired")]
+    Expired,
+}
+
+/// Claims of a session token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenClaims {
+    /// User name
+    pub sub: String,
+    /// `ids::hash_token` of the backing session, for revocation checks
+    pub sid: String,
+    /// Issue time, seconds since the epoch
+    pub iat: i64,
+    /// Expiry, seconds since the epoch
+    pub exp: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    typ: String,
+    kid: String,
+}
+
+/// One public key in JWK form (RFC 8037 OKP)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub x: String,
+    pub kid: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub use_: String,
+}
+
+/// A JWKS document: the keys that may have signed a token
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+impl Jwks {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("JWKS always serializes")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, TokenError> {
+        serde_json::from_str(json).map_err(|e| TokenError::Malformed(e.to_string()))
+    }
+
+    /// Verifies a token against the key named in its header and checks expiry
+    pub fn verify(&self, token: &str, now: i64) -> Result<TokenClaims, TokenError> {
+        let (header, _) = split(token)?;
+        let jwk = self
+            .keys
+            .iter()
+            .find(|k| k.kid == header.kid)
+            .ok_or_else(|| TokenError::UnknownKey(header.kid.clone()))?;
+        if jwk.kty != "OKP" || jwk.crv != "Ed25519" {
+            return Err(TokenError::Malformed(format!(
+                "unsupported key type {}/{}",
+                jwk.kty, jwk.crv
+            )));
+        }
+        let bytes: [u8; 32] = decode(&jwk.x)?
+            .try_into()
+            .map_err(|_| TokenError::Malformed("key is not 32 bytes".into()))?;
+        let key =
+            VerifyingKey::from_bytes(&bytes).map_err(|e| TokenError::Malformed(e.to_string()))?;
+        verify_token(token, &key, now)
+    }
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, TokenError> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|e| TokenError::Malformed(e.to_string()))
+}
+
+// Parses the header and returns it with the signed prefix `header.claims`
+fn split(token: &str) -> Result<(Header, &str), TokenError> {
+    let (signed, _) = token
+        .rsplit_once('.')
+        .ok_or_else(|| TokenError::Malformed("missing signature".into()))?;
+    let (header, _) = signed
+        .split_once('.')
+        .ok_or_else(|| TokenError::Malformed("missing claims".into()))?;
+    let header: Header = serde_json::from_slice(&decode(header)?)
+        .map_err(|e| TokenError::Malformed(e.to_string()))?;
+    if header.alg != TOKEN_ALG {
+        return Err(TokenError::Malformed(format!(
+            "unsupported algorithm {}",
+            header.alg
+        )));
+    }
+    Ok((header, signed))
+}
+
+/// Verifies a token with a known public key and checks expiry against `now`
+pub fn verify_token(token: &str, key: &VerifyingKey, now: i64) -> Result<TokenClaims, TokenError> {
+    let (_, signed) = split(token)?;
+    let signature: [u8; 64] = decode(&token[signed.len() + 1..])?
+        .try_into()
+        .map_err(|_| TokenError::Malformed("signature is not 64 bytes".into()))?;
+    key.verify(signed.as_bytes(), &Signature::from_bytes(&signature))
+        .map_err(|_| TokenError::BadSignature)?;
+    let (_, claims) = signed.split_once('.').expect("checked by split");
+    let claims: TokenClaims = serde_json::from_slice(&decode(claims)?)
+        .map_err(|e| TokenError::Malformed(e.to_string()))?;
+    if claims.exp <= now {
+        return Err(TokenError::Expired);
+    }
+    Ok(claims)
+}
+
+/// Signs session tokens as compact JWS (JWT with `alg: EdDSA`)
+pub struct TokenSigner {
+    key: SigningKey,
+    kid: String,
+}
+
+impl TokenSigner {
+    pub fn new(key: SigningKey) -> Self {
+        // The key id is a fingerprint of the public key, so it changes on rotation
+        let kid = hex::encode(&Sha256::digest(key.verifying_key().as_bytes())[..8]);
+        TokenSigner { key, kid }
+    }
+
+    pub fn generate<R: CryptoRng + RngCore>(rng: &mut R) -> Self {
+        Self::new(SigningKey::generate(rng))
+    }
+
+    /// Loads a key from a 32-byte hex seed
+    pub fn from_hex(seed: &str) -> Result<Self, TokenError> {
+        let seed: [u8; 32] = hex::decode(seed.trim())
+            .map_err(|e| TokenError::Malformed(e.to_string()))?
+            .try_into()
+            .map_err(|_| TokenError::Malformed("key seed is not 32 bytes".into()))?;
+        Ok(Self::new(SigningKey::from_bytes(&seed)))
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    pub fn sign(&self, claims: &TokenClaims) -> String {
+        let header = Header {
+            alg: TOKEN_ALG.to_string(),
+            typ: "JWT".to_string(),
+            kid: self.kid.clone(),
+        };
+        let signed = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header).expect("header always serializes")),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).expect("claims always serialize"))
+        );
+        let signature = self.key.sign(signed.as_bytes());
+        format!(
+            "{}.{}",
+            signed,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        )
+    }
+
+    /// The public half as a JWKS document
+    pub fn jwks(&self) -> Jwks {
+        Jwks {
+            keys: vec![Jwk {
+                kty: "OKP".to_string(),
+                crv: "Ed25519".to_string(),
+                x: URL_SAFE_NO_PAD.encode(self.verifying_key().as_bytes()),
+                kid: self.kid.clone(),
+                alg: TOKEN_ALG.to_string(),
+                use_: "sig".to_string(),
+            }],
+        }
+    }
+}
+
+impl fmt::Debug for TokenSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenSigner")
+            .field("kid", &self.kid)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SharedRng;
+
+    fn claims() -> TokenClaims {
+        TokenClaims {
+            sub: "alice".into(),
+            sid: "ab".repeat(32),
+            iat: 1_000,
+            exp: 4_600,
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = TokenSigner::generate(&mut SharedRng::from_entropy());
+        let token = signer.sign(&claims());
+        assert_eq!(token.split('.').count(), 3);
+        assert_eq!(
+            verify_token(&token, &signer.verifying_key(), 2_000),
+            Ok(claims())
+        );
+
+        let jwks = Jwks::from_json(&signer.jwks().to_json()).unwrap();
+        assert_eq!(jwks.verify(&token, 2_000), Ok(claims()));
+        assert_eq!(jwks.verify(&token, 4_600), Err(TokenError::Expired));
+
+        let other = TokenSigner::generate(&mut SharedRng::from_entropy());
+        assert_eq!(
+            other.jwks().verify(&token, 2_000),
+            Err(TokenError::UnknownKey(signer.kid().to_string()))
+        );
+        assert_eq!(
+            verify_token(&token, &other.verifying_key(), 2_000),
+            Err(TokenError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn test_tampered_claims_rejected() {
+        let signer = TokenSigner::generate(&mut SharedRng::from_entropy());
+        let token = signer.sign(&claims());
+        let parts: Vec<&str> = token.split('.').collect();
+        let mut forged = claims();
+        forged.sub = "mallory".into();
+        let forged = format!(
+            "{}.{}.{}",
+            parts[0],
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap()),
+            parts[2]
+        );
+        assert_eq!(
+            verify_token(&forged, &signer.verifying_key(), 2_000),
+            Err(TokenError::BadSignature)
+        );
+        assert!(matches!(
+            verify_token("a.b", &signer.verifying_key(), 0),
+            Err(TokenError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_hex_is_stable() {
+        let seed = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+        let a = TokenSigner::from_hex(seed).unwrap();
+        let b = TokenSigner::from_hex(seed).unwrap();
+        assert_eq!(a.kid(), b.kid());
+        // RFC 8032 test 1 public key
+        assert_eq!(
+            hex::encode(a.verifying_key().as_bytes()),
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+        assert!(TokenSigner::from_hex("00").is_err());
+    }
+}